@@ -0,0 +1,31 @@
+-- KOReader sync accounts. The username is the email of the bookshelf user the
+-- device syncs into, and the userkey is the MD5 hash KOReader sends.
+CREATE TABLE kosync_accounts (
+    user_id BIGINT PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    userkey TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Maps KOReader document hashes to books
+CREATE TABLE book_documents (
+    document TEXT PRIMARY KEY,
+    book_id BIGINT NOT NULL,
+    FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_book_documents_book_id ON book_documents(book_id);
+
+-- Raw progress as last reported by a device, so it can be handed back verbatim
+CREATE TABLE kosync_progress (
+    user_id BIGINT NOT NULL,
+    document TEXT NOT NULL,
+    progress TEXT NOT NULL,
+    percentage DOUBLE PRECISION NOT NULL,
+    device TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, document),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,25 @@
+CREATE TYPE highlight_kind AS ENUM ('highlight', 'note', 'bookmark');
+
+-- Highlights, notes and bookmarks a user made in a book on their shelf
+CREATE TABLE highlights (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    book_id BIGINT NOT NULL,
+    kind highlight_kind DEFAULT 'highlight' NOT NULL,
+    quote TEXT,
+    note TEXT,
+    location TEXT,
+    page INTEGER CHECK (page >= 0),
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id, book_id) REFERENCES user_books(user_id, book_id) ON DELETE CASCADE
+);
+
+-- The same clipping imported twice must end up as a single row
+CREATE UNIQUE INDEX idx_highlights_unique ON highlights (
+    user_id,
+    book_id,
+    kind,
+    COALESCE(location, ''),
+    COALESCE(page, -1),
+    md5(COALESCE(quote, note, ''))
+);
@@ -0,0 +1,18 @@
+-- Local copy of the parts of the Open Library data dumps used to enrich books
+-- without network access. Editions are stored once per ISBN they list.
+CREATE TABLE openlibrary_editions (
+    isbn TEXT PRIMARY KEY,
+    edition_key TEXT NOT NULL,
+    work_key TEXT,
+    description TEXT,
+    cover_id BIGINT,
+    pages INTEGER
+);
+
+CREATE INDEX idx_openlibrary_editions_work_key ON openlibrary_editions(work_key);
+
+CREATE TABLE openlibrary_works (
+    key TEXT PRIMARY KEY,
+    description TEXT,
+    cover_id BIGINT
+);
@@ -0,0 +1,13 @@
+-- A work groups the editions (books) of the same text, e.g. the hardcover and
+-- the paperback of a novel, so stats and ratings can be shared between them
+CREATE TABLE works (
+    id BIGSERIAL PRIMARY KEY,
+    title TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- Books without a work are editions of their own
+ALTER TABLE books ADD COLUMN work_id BIGINT REFERENCES works(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_books_work_id ON books(work_id);
@@ -0,0 +1,22 @@
+-- Cover images stored by the cover store, addressed by the SHA-256 hash of
+-- the original image
+CREATE TABLE covers (
+    hash TEXT PRIMARY KEY,
+    media_type TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- The stored cover of each book, and where it was downloaded from if it was
+CREATE TABLE book_covers (
+    book_id BIGINT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    source_url TEXT,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE,
+    FOREIGN KEY (hash) REFERENCES covers(hash)
+);
+
+CREATE INDEX idx_book_covers_hash ON book_covers(hash);
@@ -0,0 +1,26 @@
+CREATE TYPE book_format AS ENUM ('hardcover', 'paperback', 'ebook', 'audiobook');
+
+-- Books added before formats existed are taken to be paperbacks
+ALTER TABLE books ADD COLUMN format book_format DEFAULT 'paperback' NOT NULL;
+
+-- The length of a book depends on its format, and any of these can be
+-- unknown. A page count of 0 used to mean unknown.
+ALTER TABLE books ALTER COLUMN pages DROP NOT NULL;
+UPDATE books SET pages = NULL WHERE pages <= 0;
+ALTER TABLE books ADD CONSTRAINT books_pages_positive CHECK (pages > 0);
+ALTER TABLE books ADD COLUMN duration_minutes INTEGER CHECK (duration_minutes > 0);
+ALTER TABLE books ADD COLUMN word_count INTEGER CHECK (word_count > 0);
+
+-- Progress can be kept as a page or, for audiobooks, a position in seconds.
+-- Either way it is also kept as a percentage, so books of all formats can be
+-- compared in stats.
+ALTER TABLE user_books ADD COLUMN current_seconds INTEGER CHECK (current_seconds >= 0);
+ALTER TABLE user_books ADD COLUMN progress_percent DOUBLE PRECISION
+    CHECK (progress_percent >= 0 AND progress_percent <= 100);
+
+UPDATE user_books ub
+SET progress_percent = LEAST(100, ub.current_page * 100.0 / b.pages)
+FROM books b
+WHERE b.id = ub.book_id AND ub.current_page IS NOT NULL AND b.pages IS NOT NULL;
+
+UPDATE user_books SET progress_percent = 100 WHERE status = 'completed';
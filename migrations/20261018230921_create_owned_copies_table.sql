@@ -0,0 +1,19 @@
+CREATE TYPE copy_condition AS ENUM ('new', 'fine', 'very-good', 'good', 'fair', 'poor');
+
+-- Physical copies of books a user owns, and where they are kept
+CREATE TABLE owned_copies (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    condition copy_condition,
+    acquired_on DATE,
+    -- In the smallest unit of the user's currency, e.g. cents
+    price_paid_cents INTEGER CHECK (price_paid_cents >= 0),
+    location TEXT,
+    signed BOOLEAN DEFAULT FALSE NOT NULL,
+    first_edition BOOLEAN DEFAULT FALSE NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_owned_copies_user_location ON owned_copies (user_id, lower(location));
+CREATE INDEX idx_owned_copies_book ON owned_copies (book_id);
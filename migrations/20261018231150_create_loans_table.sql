@@ -0,0 +1,22 @@
+-- Lets a copy's loan periods be compared with an exclusion constraint
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
+-- Owned copies lent to another user or to someone without an account
+CREATE TABLE loans (
+    id BIGSERIAL PRIMARY KEY,
+    copy_id BIGINT NOT NULL REFERENCES owned_copies(id) ON DELETE CASCADE,
+    borrower_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    borrower_name TEXT,
+    lent_on DATE DEFAULT CURRENT_DATE NOT NULL,
+    due_on DATE CHECK (due_on >= lent_on),
+    returned_on DATE CHECK (returned_on >= lent_on),
+    -- A borrower who deletes their account is still remembered by name
+    CHECK (borrower_id IS NOT NULL OR borrower_name IS NOT NULL),
+    -- A copy can only be with one borrower at a time
+    CONSTRAINT loans_no_overlap EXCLUDE USING gist (
+        copy_id WITH =,
+        daterange(lent_on, returned_on, '[)') WITH &&
+    )
+);
+
+CREATE INDEX idx_loans_borrower ON loans (borrower_id);
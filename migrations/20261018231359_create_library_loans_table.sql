@@ -0,0 +1,25 @@
+-- Books on a user's shelf that are borrowed from a public library
+CREATE TABLE library_loans (
+    user_id BIGINT NOT NULL,
+    book_id BIGINT NOT NULL,
+    library TEXT NOT NULL,
+    checked_out_on DATE DEFAULT CURRENT_DATE NOT NULL,
+    due_on DATE NOT NULL CHECK (due_on >= checked_out_on),
+    renewals INTEGER DEFAULT 0 NOT NULL CHECK (renewals >= 0),
+    PRIMARY KEY (user_id, book_id),
+    FOREIGN KEY (user_id, book_id) REFERENCES user_books(user_id, book_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_library_loans_due ON library_loans (user_id, due_on);
+
+-- Each change of a user's progress in a book, for working out their pace
+CREATE TABLE reading_progress (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    book_id BIGINT NOT NULL,
+    progress_percent DOUBLE PRECISION NOT NULL CHECK (progress_percent >= 0 AND progress_percent <= 100),
+    recorded_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id, book_id) REFERENCES user_books(user_id, book_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_reading_progress_user_book ON reading_progress (user_id, book_id, recorded_at);
@@ -0,0 +1,35 @@
+-- Follows of a private account have to be accepted by its owner
+ALTER TABLE users ADD COLUMN private BOOLEAN DEFAULT FALSE NOT NULL;
+
+CREATE TYPE follow_status AS ENUM ('pending', 'accepted');
+
+CREATE TABLE follows (
+    follower_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    followee_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status follow_status DEFAULT 'accepted' NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (follower_id, followee_id),
+    CONSTRAINT follows_not_self CHECK (follower_id <> followee_id)
+);
+
+CREATE INDEX idx_follows_followee ON follows (followee_id, status);
+
+-- A blocked user can't follow the blocker, and the blocker can't follow them
+CREATE TABLE blocks (
+    blocker_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    blocked_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (blocker_id, blocked_id),
+    CONSTRAINT blocks_not_self CHECK (blocker_id <> blocked_id)
+);
+
+CREATE INDEX idx_blocks_blocked ON blocks (blocked_id);
+
+-- A muted user can still be followed, their activity is just hidden
+CREATE TABLE mutes (
+    muter_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    muted_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (muter_id, muted_id),
+    CONSTRAINT mutes_not_self CHECK (muter_id <> muted_id)
+);
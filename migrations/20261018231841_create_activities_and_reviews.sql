@@ -0,0 +1,28 @@
+-- A user's written review of a book on their shelf
+CREATE TABLE reviews (
+    user_id BIGINT NOT NULL,
+    book_id BIGINT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, book_id),
+    FOREIGN KEY (user_id, book_id) REFERENCES user_books(user_id, book_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_reviews_book ON reviews (book_id);
+
+CREATE TYPE activity_kind AS ENUM ('added', 'started', 'progress', 'finished', 'rated', 'reviewed');
+
+-- Events on users' shelves, read by their followers' feeds
+CREATE TABLE activities (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    book_id BIGINT NOT NULL,
+    kind activity_kind NOT NULL,
+    progress_percent DOUBLE PRECISION,
+    rating SMALLINT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id, book_id) REFERENCES user_books(user_id, book_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_activities_user_created ON activities (user_id, created_at DESC, id DESC);
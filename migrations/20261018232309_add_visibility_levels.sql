@@ -0,0 +1,33 @@
+-- Who can see a profile, a shelf entry or a review. Ordered from most to
+-- least restrictive, so LEAST gives the level of the stricter of two.
+CREATE TYPE visibility AS ENUM ('private', 'followers', 'public');
+
+-- Private accounts become visible to followers only, whose follows they
+-- still have to accept
+ALTER TABLE users ADD COLUMN visibility visibility DEFAULT 'public' NOT NULL;
+UPDATE users SET visibility = 'followers' WHERE private;
+ALTER TABLE users DROP COLUMN private;
+
+ALTER TABLE user_books ADD COLUMN visibility visibility DEFAULT 'public' NOT NULL;
+ALTER TABLE reviews ADD COLUMN visibility visibility DEFAULT 'public' NOT NULL;
+
+-- Whether the viewer can see something of the owner's with the given level.
+-- Owners see all of their own, and users who blocked one another see
+-- nothing of each other's.
+CREATE FUNCTION visible_to(owner_id BIGINT, viewer_id BIGINT, level visibility)
+RETURNS BOOLEAN AS $$
+    SELECT owner_id = viewer_id OR (
+        NOT EXISTS (
+            SELECT 1 FROM blocks
+            WHERE (blocker_id = owner_id AND blocked_id = viewer_id)
+               OR (blocker_id = viewer_id AND blocked_id = owner_id)
+        )
+        AND (
+            level = 'public'
+            OR (level = 'followers' AND EXISTS (
+                SELECT 1 FROM follows
+                WHERE follower_id = viewer_id AND followee_id = owner_id AND status = 'accepted'
+            ))
+        )
+    )
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,20 @@
+-- How alike two books are rated by the users who rated both. Recomputed in
+-- batches, each pair is stored both ways round
+CREATE TABLE book_similarities (
+    book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    similar_book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    similarity DOUBLE PRECISION NOT NULL,
+    co_ratings INTEGER NOT NULL,
+    PRIMARY KEY (book_id, similar_book_id)
+);
+
+-- Books suggested to users from the similarities, with the rated book that
+-- contributed most to each
+CREATE TABLE recommendations (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    because_book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    computed_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, book_id)
+);
@@ -0,0 +1,16 @@
+-- Words of the books' titles, authors and descriptions, with the number of
+-- books using each. Rebuilt with the vectors
+CREATE TABLE terms (
+    id INTEGER PRIMARY KEY,
+    term TEXT NOT NULL UNIQUE,
+    document_frequency INTEGER NOT NULL
+);
+
+-- TF-IDF vector of each book's text, as the ids of its terms in ascending
+-- order and their weights
+CREATE TABLE book_vectors (
+    book_id BIGINT PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+    terms INTEGER[] NOT NULL,
+    weights REAL[] NOT NULL,
+    CONSTRAINT book_vectors_same_length CHECK (cardinality(terms) = cardinality(weights))
+);
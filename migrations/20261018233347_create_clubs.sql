@@ -0,0 +1,62 @@
+-- Reading groups, reading books together on a shared schedule
+CREATE TABLE clubs (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE TYPE club_role AS ENUM ('member', 'moderator', 'owner');
+
+CREATE TABLE club_members (
+    club_id BIGINT NOT NULL REFERENCES clubs(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role club_role DEFAULT 'member' NOT NULL,
+    joined_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (club_id, user_id)
+);
+
+CREATE INDEX idx_club_members_user ON club_members (user_id);
+
+-- The books a club reads, and when it starts each
+CREATE TABLE club_picks (
+    id BIGSERIAL PRIMARY KEY,
+    club_id BIGINT NOT NULL REFERENCES clubs(id) ON DELETE CASCADE,
+    book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    starts_on DATE NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_club_picks_club ON club_picks (club_id);
+
+-- Points of a pick's schedule, like "Chapters 1-5", to be read up to
+-- `end_page` by `due_on`. Each has a discussion thread
+CREATE TABLE milestones (
+    id BIGSERIAL PRIMARY KEY,
+    pick_id BIGINT NOT NULL REFERENCES club_picks(id) ON DELETE CASCADE,
+    label TEXT NOT NULL,
+    end_page INTEGER NOT NULL CHECK (end_page > 0),
+    due_on DATE NOT NULL,
+    UNIQUE (pick_id, end_page)
+);
+
+CREATE TABLE club_posts (
+    id BIGSERIAL PRIMARY KEY,
+    milestone_id BIGINT NOT NULL REFERENCES milestones(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_club_posts_milestone ON club_posts (milestone_id, created_at);
+
+-- The page a user is on in a book: their current page, or where their
+-- percentage falls in the book, or the last page once they completed it
+CREATE FUNCTION reading_page(reader_id BIGINT, reading_book_id BIGINT)
+RETURNS INTEGER AS $$
+    SELECT CASE WHEN ub.status = 'completed' THEN COALESCE(b.pages, ub.current_page)
+                ELSE COALESCE(ub.current_page, (ub.progress_percent * b.pages / 100)::INTEGER) END
+    FROM user_books ub
+    JOIN books b ON b.id = ub.book_id
+    WHERE ub.user_id = reader_id AND ub.book_id = reading_book_id
+$$ LANGUAGE sql STABLE;
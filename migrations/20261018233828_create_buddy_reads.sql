@@ -0,0 +1,28 @@
+-- A few friends reading a book together
+CREATE TABLE buddy_reads (
+    id BIGSERIAL PRIMARY KEY,
+    book_id BIGINT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE TABLE buddy_read_members (
+    buddy_read_id BIGINT NOT NULL REFERENCES buddy_reads(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    joined_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (buddy_read_id, user_id)
+);
+
+CREATE INDEX idx_buddy_read_members_user ON buddy_read_members (user_id);
+
+-- Comments pinned to a page, shown to the others once they read that far
+CREATE TABLE buddy_comments (
+    id BIGSERIAL PRIMARY KEY,
+    buddy_read_id BIGINT NOT NULL REFERENCES buddy_reads(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    page INTEGER NOT NULL CHECK (page > 0),
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_buddy_comments_read ON buddy_comments (buddy_read_id, page);
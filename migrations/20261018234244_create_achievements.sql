@@ -0,0 +1,9 @@
+CREATE TYPE badge AS ENUM ('first-book', 'ten-thousand-pages', 'five-day-streak', 'ten-decades');
+
+-- Badges awarded to users for their reading, each at most once
+CREATE TABLE achievements (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    badge badge NOT NULL,
+    awarded_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, badge)
+);
@@ -0,0 +1,30 @@
+-- The IANA time zone days are counted in for the user's reading streak, and
+-- the local hour after which they're reminded to read, if they want to be
+ALTER TABLE users
+    ADD COLUMN timezone TEXT DEFAULT 'UTC' NOT NULL
+        -- Converting to an unknown time zone raises an error
+        CHECK (TIMESTAMPTZ '2000-01-01 00:00+00' AT TIME ZONE timezone IS NOT NULL),
+    ADD COLUMN reminder_hour SMALLINT CHECK (reminder_hour BETWEEN 0 AND 23);
+
+-- Days that keep a user's streak going without reading
+CREATE TABLE streak_freezes (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, day)
+);
+
+CREATE TYPE notification_kind AS ENUM ('reading-reminder');
+
+-- Messages for users, sent by a notifier and marked delivered once it has
+CREATE TABLE notifications (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind notification_kind NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_notifications_user ON notifications (user_id, created_at);
+CREATE INDEX idx_notifications_undelivered ON notifications (id) WHERE delivered_at IS NULL;
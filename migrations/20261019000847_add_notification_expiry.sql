@@ -0,0 +1,3 @@
+-- Notifications no longer worth sending after a time, like a reminder to
+-- read today once the day is over
+ALTER TABLE notifications ADD COLUMN expires_at TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- Locations are looked up and grouped with surrounding whitespace trimmed,
+-- which the index on lower(location) can't serve
+DROP INDEX idx_owned_copies_user_location;
+CREATE INDEX idx_owned_copies_user_location ON owned_copies (user_id, lower(trim(location)));
@@ -0,0 +1,533 @@
+//! Awards users badges for their reading.
+//!
+//! Each badge has a rule over a user's reading `Stats`: books finished, pages
+//! read, the longest run of days with logged progress and the decades their
+//! finished books were published in. `evaluate` awards a user the badges
+//! whose rules they meet and haven't been awarded yet; it runs in the same
+//! transaction as every shelf entry saved, only for the rules the `Change` to
+//! the entry can affect, and does nothing for users with every badge already.
+//! `backfill`, which the `achievements` binary runs, evaluates every user
+//! with a shelf, for the reading done before a badge existed, and dates the
+//! badges from the user's history.
+use crate::models::user_book::{ReadingStatus, UserBook};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "badge", rename_all = "kebab-case")]
+pub enum Badge {
+    FirstBook,
+    TenThousandPages,
+    FiveDayStreak,
+    TenDecades,
+}
+
+impl Badge {
+    pub fn description(self) -> &'static str {
+        match self {
+            Badge::FirstBook => "Finished a first book",
+            Badge::TenThousandPages => "Read 10,000 pages",
+            Badge::FiveDayStreak => "Read on 5 days in a row",
+            Badge::TenDecades => "Finished books from 10 decades",
+        }
+    }
+}
+
+/// What a user has read, as the rules see it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stats {
+    pub books_finished: i64,
+    /// Pages of finished books and pages reached in the others.
+    pub pages_read: i64,
+    /// Most consecutive days, in the user's time zone, with logged progress.
+    /// Unlike in `streaks::Streak`, freeze days don't count.
+    pub longest_streak: i64,
+    /// Decades the finished books were published in.
+    pub decades: i64,
+}
+
+/// What a shelf change touched, as the rules see it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    /// A book was finished, or is no longer.
+    pub finished: bool,
+    /// The page or percentage reached changed.
+    pub progress: bool,
+}
+
+impl Change {
+    /// A change every rule is evaluated for.
+    pub const ANY: Change = Change {
+        finished: true,
+        progress: true,
+    };
+
+    /// The change from `before`, `None` for a new entry, to `after`.
+    pub fn between(before: Option<&UserBook>, after: &UserBook) -> Self {
+        let finished = |entry: &UserBook| entry.status == ReadingStatus::Completed;
+        let position = |entry: &UserBook| (entry.current_page, entry.progress_percent);
+        Change {
+            finished: before.is_some_and(finished) != finished(after),
+            progress: before.map(position).unwrap_or_default() != position(after),
+        }
+    }
+}
+
+/// A badge, when it is earned and which changes can earn it.
+pub struct Rule {
+    pub badge: Badge,
+    pub earned: fn(&Stats) -> bool,
+    pub affected_by: fn(Change) -> bool,
+}
+
+const PAGES: i64 = 10_000;
+const STREAK_DAYS: i64 = 5;
+const DECADES: i64 = 10;
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        badge: Badge::FirstBook,
+        earned: |stats| stats.books_finished >= 1,
+        affected_by: |change| change.finished,
+    },
+    Rule {
+        badge: Badge::TenThousandPages,
+        earned: |stats| stats.pages_read >= PAGES,
+        affected_by: |change| change.finished || change.progress,
+    },
+    Rule {
+        badge: Badge::FiveDayStreak,
+        earned: |stats| stats.longest_streak >= STREAK_DAYS,
+        affected_by: |change| change.progress,
+    },
+    Rule {
+        badge: Badge::TenDecades,
+        earned: |stats| stats.decades >= DECADES,
+        affected_by: |change| change.finished,
+    },
+];
+
+/// A badge awarded to a user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Achievement {
+    pub user_id: i64,
+    pub badge: Badge,
+    pub awarded_at: DateTime<Utc>,
+}
+
+impl Stats {
+    /// The stats of `user_id` the rules affected by `change` need, leaving
+    /// the others at 0.
+    pub async fn for_user(
+        conn: &mut PgConnection,
+        user_id: i64,
+        change: Change,
+    ) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Stats,
+            r#"
+            SELECT
+                CASE WHEN $2 THEN
+                (SELECT COUNT(*) FROM user_books WHERE user_id = $1 AND status = 'completed')
+                ELSE 0 END AS "books_finished!",
+                CASE WHEN $2 OR $3 THEN
+                (SELECT COALESCE(SUM(reading_page(user_id, book_id)), 0) FROM user_books WHERE user_id = $1)
+                ELSE 0 END AS "pages_read!",
+                CASE WHEN $3 THEN
+                (SELECT COALESCE(MAX(days), 0) FROM (
+                    SELECT COUNT(*) AS days
+                    FROM (
+                        SELECT day - (ROW_NUMBER() OVER (ORDER BY day))::INTEGER AS run
+                        FROM (
+                            SELECT DISTINCT (rp.recorded_at AT TIME ZONE u.timezone)::DATE AS day
+                            FROM reading_progress rp
+                            JOIN users u ON u.id = rp.user_id
+                            WHERE rp.user_id = $1
+                        ) days
+                    ) numbered
+                    GROUP BY run
+                ) runs)
+                ELSE 0 END AS "longest_streak!",
+                CASE WHEN $2 THEN
+                (SELECT COUNT(DISTINCT b.published_year / 10)
+                 FROM user_books ub
+                 JOIN books b ON b.id = ub.book_id
+                 WHERE ub.user_id = $1 AND ub.status = 'completed')
+                ELSE 0 END AS "decades!"
+            "#,
+            user_id,
+            change.finished,
+            change.progress
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(record)
+    }
+}
+
+/// The badges whose rules `stats` meets.
+pub fn earned(stats: &Stats) -> Vec<Badge> {
+    RULES
+        .iter()
+        .filter(|rule| (rule.earned)(stats))
+        .map(|rule| rule.badge)
+        .collect()
+}
+
+/// Awards `user_id` the badges they've earned and don't have yet, of the
+/// rules `change` can affect, returning those.
+pub async fn evaluate(
+    conn: &mut PgConnection,
+    user_id: i64,
+    change: Change,
+) -> Result<Vec<Badge>, sqlx::Error> {
+    if !RULES.iter().any(|rule| (rule.affected_by)(change)) {
+        return Ok(Vec::new());
+    }
+    let awarded = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM achievements WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if awarded == RULES.len() as i64 {
+        return Ok(Vec::new());
+    }
+
+    let stats = Stats::for_user(&mut *conn, user_id, change).await?;
+    let badges: Vec<_> = RULES
+        .iter()
+        .filter(|rule| (rule.affected_by)(change) && (rule.earned)(&stats))
+        .map(|rule| rule.badge)
+        .collect();
+    let dates = vec![None; badges.len()];
+    award(conn, user_id, &badges, &dates).await
+}
+
+/// Evaluates every user with books on their shelf, returning how many badges
+/// were awarded. Badges are dated from when the user's shelf and logged
+/// progress show they were earned, or now where they don't tell.
+pub async fn backfill(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let users = sqlx::query_scalar!("SELECT DISTINCT user_id FROM user_books ORDER BY user_id")
+        .fetch_all(pool)
+        .await?;
+
+    let mut awarded = 0;
+    for user_id in users {
+        let mut tx = pool.begin().await?;
+        let badges = earned(&Stats::for_user(&mut tx, user_id, Change::ANY).await?);
+        let history = earned_at(&mut tx, user_id).await?;
+        let dates: Vec<_> = badges
+            .iter()
+            .map(|badge| {
+                history
+                    .iter()
+                    .find(|(b, _)| b == badge)
+                    .and_then(|(_, at)| *at)
+            })
+            .collect();
+        awarded += award(&mut tx, user_id, &badges, &dates).await?.len();
+        tx.commit().await?;
+    }
+
+    Ok(awarded)
+}
+
+/// When `user_id` first met each rule, as far as their history tells: shelf
+/// entries count from when they were finished or last read in, and streak
+/// days from the first progress logged on them.
+async fn earned_at(
+    conn: &mut PgConnection,
+    user_id: i64,
+) -> Result<[(Badge, Option<DateTime<Utc>>); 4], sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        WITH entries AS (
+            SELECT
+                ub.status,
+                b.published_year,
+                reading_page(ub.user_id, ub.book_id) AS pages,
+                COALESCE(
+                    ub.done_reading,
+                    (SELECT MAX(rp.recorded_at) FROM reading_progress rp
+                     WHERE rp.user_id = ub.user_id AND rp.book_id = ub.book_id),
+                    ub.began_reading,
+                    ub.added_at
+                ) AS reached_at
+            FROM user_books ub
+            JOIN books b ON b.id = ub.book_id
+            WHERE ub.user_id = $1
+        ), days AS (
+            SELECT (rp.recorded_at AT TIME ZONE u.timezone)::DATE AS day, MIN(rp.recorded_at) AS first_at
+            FROM reading_progress rp
+            JOIN users u ON u.id = rp.user_id
+            WHERE rp.user_id = $1
+            GROUP BY day
+        ), runs AS (
+            SELECT first_at, ROW_NUMBER() OVER (PARTITION BY run ORDER BY day) AS nth
+            FROM (
+                SELECT day, first_at, day - (ROW_NUMBER() OVER (ORDER BY day))::INTEGER AS run
+                FROM days
+            ) numbered
+        )
+        SELECT
+            (SELECT MIN(reached_at) FROM entries WHERE status = 'completed') AS first_book,
+            (SELECT MIN(reached_at) FROM (
+                SELECT reached_at, SUM(pages) OVER (ORDER BY reached_at) AS total FROM entries
+            ) totals WHERE total >= $2) AS pages,
+            (SELECT MIN(first_at) FROM runs WHERE nth = $3) AS streak,
+            (SELECT reached_at FROM (
+                SELECT MIN(reached_at) AS reached_at
+                FROM entries
+                WHERE status = 'completed' AND published_year IS NOT NULL
+                GROUP BY published_year / 10
+            ) decades ORDER BY reached_at OFFSET $4::BIGINT - 1 LIMIT 1) AS decades
+        "#,
+        user_id,
+        PAGES,
+        STREAK_DAYS,
+        DECADES
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok([
+        (Badge::FirstBook, record.first_book),
+        (Badge::TenThousandPages, record.pages),
+        (Badge::FiveDayStreak, record.streak),
+        (Badge::TenDecades, record.decades),
+    ])
+}
+
+/// Awards `user_id` the `badges` they don't have yet, each at its date in
+/// `dates` or now, returning those.
+async fn award(
+    conn: &mut PgConnection,
+    user_id: i64,
+    badges: &[Badge],
+    dates: &[Option<DateTime<Utc>>],
+) -> Result<Vec<Badge>, sqlx::Error> {
+    if badges.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let awarded = sqlx::query_scalar!(
+        r#"
+        INSERT INTO achievements (user_id, badge, awarded_at)
+        SELECT $1, badge, COALESCE(awarded_at, NOW())
+        FROM UNNEST($2::badge[], $3::timestamptz[]) AS earned (badge, awarded_at)
+        ON CONFLICT DO NOTHING
+        RETURNING badge AS "badge: Badge"
+        "#,
+        user_id,
+        badges as &[Badge],
+        dates as &[Option<DateTime<Utc>>]
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(awarded)
+}
+
+impl Achievement {
+    /// The badges of `user_id` that `viewer_id` can see, oldest first.
+    pub async fn for_user(
+        pool: &PgPool,
+        user_id: i64,
+        viewer_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Achievement,
+            r#"
+            SELECT a.user_id, a.badge AS "badge: Badge", a.awarded_at
+            FROM achievements a
+            JOIN users u ON u.id = a.user_id
+            WHERE a.user_id = $1 AND visible_to(a.user_id, $2, u.visibility)
+            ORDER BY a.awarded_at, a.badge
+            "#,
+            user_id,
+            viewer_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user};
+    use crate::models::user_book::{ReadingStatus, UserBookBuilder};
+    use crate::test_utils::setup_db;
+    use chrono::TimeZone;
+
+    #[test]
+    fn rules_award_on_their_thresholds() {
+        let almost = Stats {
+            books_finished: 0,
+            pages_read: 9_999,
+            longest_streak: 4,
+            decades: 9,
+        };
+        let there = Stats {
+            books_finished: 1,
+            pages_read: 10_000,
+            longest_streak: 5,
+            decades: 10,
+        };
+
+        assert_eq!(Vec::<Badge>::new(), earned(&almost));
+        assert_eq!(
+            vec![
+                Badge::FirstBook,
+                Badge::TenThousandPages,
+                Badge::FiveDayStreak,
+                Badge::TenDecades,
+            ],
+            earned(&there)
+        );
+    }
+
+    #[tokio::test]
+    async fn badges_are_awarded_once_on_shelf_changes() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.pages = Some(10_000);
+        book.create(&pool).await.unwrap();
+        let mut entry = UserBookBuilder::default()
+            .user_id(user.id)
+            .book_id(book.id)
+            .status(ReadingStatus::Reading)
+            .current_page(Some(400))
+            .build()
+            .unwrap();
+
+        // Act
+        entry.create(&pool).await.unwrap();
+        let reading = Achievement::for_user(&pool, user.id, user.id)
+            .await
+            .unwrap();
+        entry.status = ReadingStatus::Completed;
+        entry.update(&pool).await.unwrap();
+        let finished = Achievement::for_user(&pool, user.id, user.id)
+            .await
+            .unwrap();
+        for days_ago in 0..5 {
+            sqlx::query!(
+                r#"
+                INSERT INTO reading_progress (user_id, book_id, progress_percent, recorded_at)
+                VALUES ($1, $2, $3, NOW() - make_interval(days => $4))
+                "#,
+                user.id,
+                book.id,
+                f64::from(days_ago),
+                days_ago
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let only_finished = Change {
+            finished: true,
+            progress: false,
+        };
+        let progressed = Change {
+            finished: false,
+            progress: true,
+        };
+        let mut conn = pool.acquire().await.unwrap();
+        let unaffected = evaluate(&mut conn, user.id, only_finished).await.unwrap();
+        let streak = evaluate(&mut conn, user.id, progressed).await.unwrap();
+        let again = evaluate(&mut conn, user.id, progressed).await.unwrap();
+        drop(conn);
+        let all = Achievement::for_user(&pool, user.id, user.id)
+            .await
+            .unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(reading.is_empty());
+        assert!(unaffected.is_empty());
+        assert_eq!(
+            vec![Badge::FirstBook, Badge::TenThousandPages],
+            finished.iter().map(|a| a.badge).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![Badge::FiveDayStreak], streak);
+        assert!(again.is_empty());
+        assert_eq!(&finished[..], &all[..2]);
+        assert_eq!(Badge::FiveDayStreak, all[2].badge);
+    }
+
+    #[tokio::test]
+    async fn backfilled_badges_are_dated_from_history() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = fake_user();
+        user.timezone = "UTC".to_string();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.pages = Some(10_000);
+        book.create(&pool).await.unwrap();
+        let finished = Utc.with_ymd_and_hms(2020, 5, 1, 12, 0, 0).unwrap();
+        UserBookBuilder::default()
+            .user_id(user.id)
+            .book_id(book.id)
+            .status(ReadingStatus::Completed)
+            .added_at(finished)
+            .done_reading(Some(finished))
+            .build()
+            .unwrap()
+            .import(&pool)
+            .await
+            .unwrap();
+        sqlx::query!("DELETE FROM reading_progress WHERE user_id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        // A day, a gap, then five days in a row from the 10th
+        for day in [1, 10, 11, 12, 13, 14, 15] {
+            sqlx::query!(
+                "INSERT INTO reading_progress (user_id, book_id, progress_percent, recorded_at) VALUES ($1, $2, $3, $4)",
+                user.id,
+                book.id,
+                f64::from(day),
+                Utc.with_ymd_and_hms(2021, 1, day, 20, 0, 0).unwrap()
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        // Act
+        let mut conn = pool.acquire().await.unwrap();
+        let dates = earned_at(&mut conn, user.id).await.unwrap();
+        drop(conn);
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            [
+                (Badge::FirstBook, Some(finished)),
+                (Badge::TenThousandPages, Some(finished)),
+                (
+                    Badge::FiveDayStreak,
+                    Some(Utc.with_ymd_and_hms(2021, 1, 14, 20, 0, 0).unwrap())
+                ),
+                (Badge::TenDecades, None),
+            ],
+            dates
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use anyhow::Result;
+use bookshelf::achievements::backfill;
+use bookshelf::db::init_pool;
+
+/// Awards users the badges their reading so far has earned them, such as
+/// after a new badge is added.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let pool = init_pool().await?;
+    println!("📚 awarded {} badges", backfill(&pool).await?);
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use anyhow::Result;
+use axum::middleware;
+use bookshelf::covers::{self, CoverStore, FsBackend};
+use bookshelf::db::init_pool;
+use bookshelf::server::report;
+use std::env;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Serves stored covers on `COVERS_ADDR` (default `0.0.0.0:7300`) from
+/// `COVERS_DIR` (default `covers`).
+#[tokio::main]
+async fn main() -> Result<()> {
+    let pool = init_pool().await?;
+    let addr = env::var("COVERS_ADDR").unwrap_or_else(|_| "0.0.0.0:7300".to_string());
+    let dir = env::var("COVERS_DIR").unwrap_or_else(|_| "covers".to_string());
+    let store = Arc::new(CoverStore::new(FsBackend::new(dir)));
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!("📚 covers listening on {}", listener.local_addr()?);
+    let app = covers::router(pool, store).layer(middleware::map_response(report));
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+use axum::middleware;
+use bookshelf::db::init_pool;
+use bookshelf::kosync;
+use bookshelf::server::report;
+use std::env;
+use tokio::net::TcpListener;
+
+/// Runs the KOReader sync server on `KOSYNC_ADDR` (default `0.0.0.0:7200`).
+#[tokio::main]
+async fn main() -> Result<()> {
+    let pool = init_pool().await?;
+    let addr = env::var("KOSYNC_ADDR").unwrap_or_else(|_| "0.0.0.0:7200".to_string());
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!("📚 kosync listening on {}", listener.local_addr()?);
+    let app = kosync::router(pool).layer(middleware::map_response(report));
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
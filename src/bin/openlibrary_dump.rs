@@ -0,0 +1,49 @@
+use anyhow::{Result, bail};
+use bookshelf::db::init_pool;
+use bookshelf::import::open_library_dump::{
+    Dump, Progress, enrich_books, load_editions, load_works,
+};
+use std::env;
+use std::io::Write;
+use std::path::Path;
+
+/// Loads Open Library dumps and enriches books from them:
+///
+/// ```text
+/// openlibrary_dump editions ol_dump_editions_latest.txt.gz
+/// openlibrary_dump works ol_dump_works_latest.txt.gz
+/// openlibrary_dump enrich
+/// ```
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let pool = init_pool().await?;
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["editions", path] => {
+            let progress = load_editions(&pool, Dump::open(Path::new(path))?, report).await?;
+            eprintln!();
+            println!("📚 loaded {} ISBNs", progress.loaded);
+        }
+        ["works", path] => {
+            let progress = load_works(&pool, Dump::open(Path::new(path))?, report).await?;
+            eprintln!();
+            println!("📚 loaded {} works", progress.loaded);
+        }
+        ["enrich"] => println!("📚 enriched {} books", enrich_books(&pool).await?),
+        _ => bail!("usage: openlibrary_dump editions <dump> | works <dump> | enrich"),
+    }
+
+    Ok(())
+}
+
+fn report(progress: &Progress) {
+    eprint!(
+        "\r{:5.1}%  {} lines, {} loaded, {} skipped",
+        progress.percent().unwrap_or(0.0),
+        progress.lines,
+        progress.loaded,
+        progress.skipped
+    );
+    std::io::stderr().flush().ok();
+}
@@ -0,0 +1,56 @@
+use anyhow::{Result, bail};
+use bookshelf::db::init_pool;
+use bookshelf::recommendations::{DEFAULT_COUNT, recompute};
+use std::env;
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+const USAGE: &str = "usage: recommendations once | every <hours>";
+
+/// Recomputes book recommendations, once or every few hours:
+///
+/// ```text
+/// recommendations once
+/// recommendations every 6
+/// ```
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let pool = init_pool().await?;
+
+    let hours = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["once"] => None,
+        ["every", hours] => {
+            let Ok(hours) = hours.parse::<NonZeroU64>() else {
+                bail!(USAGE);
+            };
+            Some(hours)
+        }
+        _ => bail!(USAGE),
+    };
+    let Some(hours) = hours else {
+        return run(&pool).await;
+    };
+    let Some(seconds) = hours.get().checked_mul(60 * 60) else {
+        bail!(USAGE);
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+    loop {
+        interval.tick().await;
+        // A failed run is retried at the next tick rather than stopping
+        if let Err(e) = run(&pool).await {
+            eprintln!("recomputing recommendations failed: {e}");
+        }
+    }
+}
+
+async fn run(pool: &sqlx::PgPool) -> Result<()> {
+    let recomputed = recompute(pool, DEFAULT_COUNT).await?;
+    println!(
+        "📚 stored {} similarities and {} recommendations",
+        recomputed.similarities, recomputed.recommendations
+    );
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use anyhow::{Result, bail};
+use bookshelf::db::init_pool;
+use bookshelf::reminders::{StdoutNotifier, deliver, schedule};
+use chrono::Utc;
+use std::env;
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+const USAGE: &str = "usage: reminders once | every <minutes>";
+
+/// Reminds users to read, once or every few minutes:
+///
+/// ```text
+/// reminders once
+/// reminders every 15
+/// ```
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let pool = init_pool().await?;
+
+    let minutes = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["once"] => None,
+        ["every", minutes] => {
+            let Ok(minutes) = minutes.parse::<NonZeroU64>() else {
+                bail!(USAGE);
+            };
+            Some(minutes)
+        }
+        _ => bail!(USAGE),
+    };
+    let Some(minutes) = minutes else {
+        return run(&pool).await;
+    };
+    let Some(seconds) = minutes.get().checked_mul(60) else {
+        bail!(USAGE);
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+    loop {
+        interval.tick().await;
+        // A failed run is retried at the next tick rather than stopping
+        if let Err(e) = run(&pool).await {
+            eprintln!("sending reminders failed: {e}");
+        }
+    }
+}
+
+async fn run(pool: &sqlx::PgPool) -> Result<()> {
+    let now = Utc::now();
+    let scheduled = schedule(pool, now).await?;
+    let delivered = deliver(pool, &StdoutNotifier, now).await?;
+    println!(
+        "📚 scheduled {} reminders, delivered {} notifications, {} failed",
+        scheduled.len(),
+        delivered.delivered,
+        delivered.failed
+    );
+
+    Ok(())
+}
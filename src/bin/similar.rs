@@ -0,0 +1,12 @@
+use anyhow::Result;
+use bookshelf::db::init_pool;
+use bookshelf::similar::reindex;
+
+/// Rebuilds the index of book texts that `Book::similar` searches.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let pool = init_pool().await?;
+    println!("📚 indexed {} books", reindex(&pool).await?);
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! Cover storage in a directory on the local filesystem.
+use crate::covers::CoverBackend;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+impl FsBackend {
+    /// Stores covers under `root`, which is created when the first cover is
+    /// stored.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FsBackend { root: root.into() }
+    }
+
+    /// The path of `key`, refusing keys that would point outside the root.
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid cover key {key:?}"),
+            ));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+impl CoverBackend for FsBackend {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Written next to the final path and renamed, so a half written file
+        // is never served
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn put_get_and_delete() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let backend = FsBackend::new(dir.path());
+
+        // Act
+        backend.put("originals/abc", b"cover").await.unwrap();
+        let stored = backend.get("originals/abc").await.unwrap();
+        backend.delete("originals/abc").await.unwrap();
+        let deleted = backend.get("originals/abc").await.unwrap();
+        let escaping = backend.put("../outside", b"cover").await;
+
+        // Assert
+        assert_eq!(Some(b"cover".to_vec()), stored);
+        assert_eq!(None, deleted);
+        assert_eq!(io::ErrorKind::InvalidInput, escaping.unwrap_err().kind());
+        assert!(!dir.path().parent().unwrap().join("outside").exists());
+    }
+}
@@ -0,0 +1,620 @@
+//! Keeps copies of book covers, so covers don't disappear when the site a
+//! `cover_url` points to does.
+//!
+//! Covers are uploaded or downloaded, checked to be a JPEG, PNG, WebP or GIF
+//! image within the size limits, and stored by the SHA-256 hash of their
+//! contents together with thumbnails in fixed sizes. Where they are stored is
+//! up to a `CoverBackend`, `FsBackend` keeps them on the local filesystem.
+use crate::models::cover::Cover;
+use crate::server::ServerError;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Router};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader, Limits};
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::fmt;
+use std::io::{self, Cursor};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod fs;
+mod public;
+
+pub use fs::FsBackend;
+
+/// Largest accepted image file, 10 MiB by default.
+pub const DEFAULT_MAX_BYTES: usize = 10 * 1024 * 1024;
+/// Largest accepted width or height, in pixels.
+pub const DEFAULT_MAX_DIMENSION: u32 = 6000;
+
+const THUMBNAIL_QUALITY: u8 = 85;
+
+/// Where covers are stored, e.g. a directory or an S3 compatible bucket.
+/// Keys are relative paths like `originals/<hash>`.
+pub trait CoverBackend: Send + Sync + 'static {
+    fn put(&self, key: &str, data: &[u8]) -> impl Future<Output = io::Result<()>> + Send;
+
+    /// The data stored under `key`, or `None` if there is none.
+    fn get(&self, key: &str) -> impl Future<Output = io::Result<Option<Vec<u8>>>> + Send;
+
+    /// Removes `key`. Removing a key that doesn't exist is not an error.
+    fn delete(&self, key: &str) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// The thumbnails made of every cover. Covers are cropped to fill the size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThumbnailSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 3] = [
+        ThumbnailSize::Small,
+        ThumbnailSize::Medium,
+        ThumbnailSize::Large,
+    ];
+
+    /// Width and height in pixels, in the 2:3 ratio of most covers.
+    pub fn dimensions(self) -> (u32, u32) {
+        match self {
+            ThumbnailSize::Small => (100, 150),
+            ThumbnailSize::Medium => (200, 300),
+            ThumbnailSize::Large => (400, 600),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThumbnailSize::Small => "small",
+            ThumbnailSize::Medium => "medium",
+            ThumbnailSize::Large => "large",
+        }
+    }
+
+    pub fn parse(size: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == size)
+    }
+}
+
+#[derive(Debug)]
+pub enum CoverError {
+    TooLarge,
+    /// Not an image, or an image in a format that isn't accepted.
+    UnsupportedFormat,
+    InvalidImage(image::ImageError),
+    /// The cover URL isn't an http or https URL, or points to a private or
+    /// local address.
+    ForbiddenUrl,
+    Download(reqwest::Error),
+    /// The cover URL answered with an error status.
+    Status(u16),
+    Storage(io::Error),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for CoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoverError::TooLarge => write!(f, "cover image is too large"),
+            CoverError::UnsupportedFormat => {
+                write!(f, "cover must be a JPEG, PNG, WebP or GIF image")
+            }
+            CoverError::InvalidImage(e) => write!(f, "invalid cover image: {e}"),
+            CoverError::ForbiddenUrl => {
+                write!(f, "cover URL must point to a public http or https address")
+            }
+            CoverError::Download(e) => write!(f, "could not download cover: {e}"),
+            CoverError::Status(status) => {
+                write!(f, "could not download cover: status {status}")
+            }
+            CoverError::Storage(e) => write!(f, "could not store cover: {e}"),
+            CoverError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for CoverError {}
+
+impl From<image::ImageError> for CoverError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::Limits(_) => CoverError::TooLarge,
+            e => CoverError::InvalidImage(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for CoverError {
+    fn from(e: reqwest::Error) -> Self {
+        CoverError::Download(e)
+    }
+}
+
+impl From<io::Error> for CoverError {
+    fn from(e: io::Error) -> Self {
+        CoverError::Storage(e)
+    }
+}
+
+impl From<sqlx::Error> for CoverError {
+    fn from(e: sqlx::Error) -> Self {
+        CoverError::Database(e)
+    }
+}
+
+pub struct CoverStore<B> {
+    backend: B,
+    client: Client,
+    allow_private: bool,
+    max_bytes: usize,
+    max_dimension: u32,
+}
+
+impl<B: CoverBackend> CoverStore<B> {
+    pub fn new(backend: B) -> Self {
+        CoverStore {
+            backend,
+            client: client(false),
+            allow_private: false,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_dimension: DEFAULT_MAX_DIMENSION,
+        }
+    }
+
+    /// Lets covers be downloaded from private and local addresses too, such
+    /// as from a server on the same network. Off by default, as the URLs
+    /// come from users and metadata sources.
+    pub fn allow_private_addresses(mut self) -> Self {
+        self.client = client(true);
+        self.allow_private = true;
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    /// Stores an uploaded image as the cover of a book.
+    pub async fn upload(
+        &self,
+        pool: &PgPool,
+        book_id: i64,
+        data: Vec<u8>,
+    ) -> Result<Cover, CoverError> {
+        self.store(pool, book_id, data, None).await
+    }
+
+    /// Downloads the image at `url` and stores it as the cover of a book.
+    /// Only http and https URLs of public addresses are downloaded, unless
+    /// private addresses are allowed.
+    pub async fn download(
+        &self,
+        pool: &PgPool,
+        book_id: i64,
+        url: &str,
+    ) -> Result<Cover, CoverError> {
+        let parsed = reqwest::Url::parse(url).map_err(|_| CoverError::ForbiddenUrl)?;
+        if !self.allow_private && !public::allowed(&parsed) {
+            return Err(CoverError::ForbiddenUrl);
+        }
+        let mut response = self.client.get(parsed).send().await?;
+        if !response.status().is_success() {
+            return Err(CoverError::Status(response.status().as_u16()));
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_bytes as u64)
+        {
+            return Err(CoverError::TooLarge);
+        }
+
+        // The length isn't always known up front, so it is checked as the
+        // image comes in as well
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if data.len() + chunk.len() > self.max_bytes {
+                return Err(CoverError::TooLarge);
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        self.store(pool, book_id, data, Some(url)).await
+    }
+
+    async fn store(
+        &self,
+        pool: &PgPool,
+        book_id: i64,
+        data: Vec<u8>,
+        source_url: Option<&str>,
+    ) -> Result<Cover, CoverError> {
+        if data.len() > self.max_bytes {
+            return Err(CoverError::TooLarge);
+        }
+
+        let max_dimension = self.max_dimension;
+        // Decoding and resizing take a while for large images
+        let (data, cover, thumbnails) =
+            tokio::task::spawn_blocking(move || process(data, max_dimension))
+                .await
+                .expect("image processing doesn't panic")?;
+
+        self.backend.put(&original_key(&cover.hash), &data).await?;
+        for (size, thumbnail) in thumbnails {
+            self.backend
+                .put(&thumbnail_key(&cover.hash, size), &thumbnail)
+                .await?;
+        }
+
+        cover.create_if_new(pool).await?;
+        cover.attach(pool, book_id, source_url).await?;
+
+        Ok(cover)
+    }
+
+    /// The original image of a cover.
+    pub async fn original(&self, cover: &Cover) -> io::Result<Option<Vec<u8>>> {
+        self.backend.get(&original_key(&cover.hash)).await
+    }
+
+    /// A thumbnail of a cover, as a JPEG image.
+    pub async fn thumbnail(
+        &self,
+        cover: &Cover,
+        size: ThumbnailSize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        self.backend.get(&thumbnail_key(&cover.hash, size)).await
+    }
+}
+
+fn original_key(hash: &str) -> String {
+    format!("originals/{hash}")
+}
+
+fn thumbnail_key(hash: &str, size: ThumbnailSize) -> String {
+    format!("thumbnails/{}/{hash}.jpg", size.as_str())
+}
+
+type Processed = (Vec<u8>, Cover, Vec<(ThumbnailSize, Vec<u8>)>);
+
+/// The client covers are downloaded with, resolving host names to public
+/// addresses only unless `allow_private`.
+fn client(allow_private: bool) -> Client {
+    let builder = Client::builder()
+        .user_agent(concat!("bookshelf/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(30));
+    let builder = if allow_private {
+        builder
+    } else {
+        builder
+            .dns_resolver(Arc::new(public::PublicResolver))
+            .redirect(public::redirect_policy())
+    };
+    builder.build().expect("HTTP client configuration is valid")
+}
+
+/// Validates an image and makes its thumbnails.
+fn process(data: Vec<u8>, max_dimension: u32) -> Result<Processed, CoverError> {
+    let format = image::guess_format(&data).map_err(|_| CoverError::UnsupportedFormat)?;
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif
+    ) {
+        return Err(CoverError::UnsupportedFormat);
+    }
+
+    // Limits are checked before decoding, so a small file claiming to be a
+    // huge image isn't decoded
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+    let mut reader = ImageReader::with_format(Cursor::new(&data), format);
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    let mut thumbnails = Vec::with_capacity(ThumbnailSize::ALL.len());
+    for size in ThumbnailSize::ALL {
+        let (width, height) = size.dimensions();
+        let thumbnail = image
+            .resize_to_fill(width, height, FilterType::Lanczos3)
+            .to_rgb8();
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, THUMBNAIL_QUALITY).encode_image(&thumbnail)?;
+        thumbnails.push((size, jpeg));
+    }
+
+    let cover = Cover {
+        hash: hex::encode(Sha256::digest(&data)),
+        media_type: format.to_mime_type().to_string(),
+        width: image.width() as i32,
+        height: image.height() as i32,
+        size_bytes: data.len() as i32,
+    };
+
+    Ok((data, cover, thumbnails))
+}
+
+struct CoverState<B> {
+    pool: PgPool,
+    store: Arc<CoverStore<B>>,
+}
+
+impl<B> Clone for CoverState<B> {
+    fn clone(&self) -> Self {
+        CoverState {
+            pool: self.pool.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
+
+/// Serves covers at `/covers/{book_id}` and their thumbnails at
+/// `/covers/{book_id}/{small|medium|large}`.
+///
+/// The ETag of an original is its hash. Thumbnails are made from the original
+/// alone, so their ETag is the original's hash and the size.
+pub fn router<B: CoverBackend>(pool: PgPool, store: Arc<CoverStore<B>>) -> Router {
+    Router::new()
+        .route("/covers/{book_id}", get(original::<B>))
+        .route("/covers/{book_id}/{size}", get(thumbnail::<B>))
+        .with_state(CoverState { pool, store })
+}
+
+async fn original<B: CoverBackend>(
+    State(state): State<CoverState<B>>,
+    Path(book_id): Path<i64>,
+    headers: HeaderMap,
+) -> Response {
+    serve(&state, book_id, None, &headers).await
+}
+
+async fn thumbnail<B: CoverBackend>(
+    State(state): State<CoverState<B>>,
+    Path((book_id, size)): Path<(i64, String)>,
+    headers: HeaderMap,
+) -> Response {
+    match ThumbnailSize::parse(&size) {
+        Some(size) => serve(&state, book_id, Some(size), &headers).await,
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn serve<B: CoverBackend>(
+    state: &CoverState<B>,
+    book_id: i64,
+    size: Option<ThumbnailSize>,
+    headers: &HeaderMap,
+) -> Response {
+    let cover = match Cover::for_book(&state.pool, book_id).await {
+        Ok(Some(cover)) => cover,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            let error = ServerError::new("covers: database error", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Extension(error)).into_response();
+        }
+    };
+
+    let (etag, media_type) = match size {
+        None => (format!("\"{}\"", cover.hash), cover.media_type.as_str()),
+        Some(size) => (
+            format!("\"{}-{}\"", cover.hash, size.as_str()),
+            "image/jpeg",
+        ),
+    };
+    let etag = HeaderValue::from_str(&etag).expect("hashes are valid header values");
+
+    let not_modified = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    let data = match size {
+        None => state.store.original(&cover).await,
+        Some(size) => state.store.thumbnail(&cover, size).await,
+    };
+    match data {
+        Ok(Some(data)) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_str(media_type)
+                        .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+                ),
+                (header::ETAG, etag),
+                (
+                    header::CACHE_CONTROL,
+                    HeaderValue::from_static("public, no-cache"),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            let error = ServerError::new("covers: storage error", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Extension(error)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::fake_book;
+    use crate::test_utils::setup_db;
+    use image::{Rgb, RgbImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        });
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn upload_validates_and_makes_thumbnails() {
+        // Arrange
+        let pool = setup_db().await;
+        let dir = tempfile::tempdir().unwrap();
+        let store = CoverStore::new(FsBackend::new(dir.path())).max_dimension(1000);
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        let data = png(600, 800);
+
+        // Act
+        let not_an_image = store
+            .upload(&pool, book.id, b"<html></html>".to_vec())
+            .await;
+        let too_wide = store.upload(&pool, book.id, png(1200, 10)).await;
+        let too_large = CoverStore::new(FsBackend::new(dir.path()))
+            .max_bytes(100)
+            .upload(&pool, book.id, data.clone())
+            .await;
+        let cover = store.upload(&pool, book.id, data.clone()).await.unwrap();
+        let stored = Cover::for_book(&pool, book.id).await.unwrap();
+        let original = store.original(&cover).await.unwrap();
+        let small = store
+            .thumbnail(&cover, ThumbnailSize::Small)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Cleanup
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(not_an_image, Err(CoverError::UnsupportedFormat)));
+        assert!(matches!(too_wide, Err(CoverError::TooLarge)));
+        assert!(matches!(too_large, Err(CoverError::TooLarge)));
+        assert_eq!(hex::encode(Sha256::digest(&data)), cover.hash);
+        assert_eq!("image/png", cover.media_type);
+        assert_eq!((600, 800), (cover.width, cover.height));
+        assert_eq!(Some(cover), stored);
+        assert_eq!(Some(data), original);
+        let small = image::load_from_memory(&small).unwrap();
+        assert_eq!((100, 150), (small.width(), small.height()));
+    }
+
+    #[tokio::test]
+    async fn downloads_and_serves_with_etags() {
+        // Arrange
+        let pool = setup_db().await;
+        let dir = tempfile::tempdir().unwrap();
+        let guarded = CoverStore::new(FsBackend::new(dir.path()));
+        let store = Arc::new(CoverStore::new(FsBackend::new(dir.path())).allow_private_addresses());
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        let mut uncovered = fake_book();
+        uncovered.create(&pool).await.unwrap();
+
+        let data = png(60, 90);
+        let served = data.clone();
+        let images = Router::new()
+            .route("/cover.png", get(move || async move { served }))
+            .route("/missing.png", get(|| async { StatusCode::NOT_FOUND }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let images_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, images).await.unwrap() });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let covers_url = format!("http://{}", listener.local_addr().unwrap());
+        let app = router(pool.clone(), store.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = reqwest::Client::new();
+
+        // Act
+        let local = guarded
+            .download(&pool, book.id, &format!("{images_url}/cover.png"))
+            .await;
+        let local_name = guarded
+            .download(
+                &pool,
+                book.id,
+                &images_url.replace("127.0.0.1", "localhost"),
+            )
+            .await;
+        let missing = store
+            .download(&pool, book.id, &format!("{images_url}/missing.png"))
+            .await;
+        let cover = store
+            .download(&pool, book.id, &format!("{images_url}/cover.png"))
+            .await
+            .unwrap();
+        let original = client
+            .get(format!("{covers_url}/covers/{}", book.id))
+            .send()
+            .await
+            .unwrap();
+        let original_etag = original.headers()[header::ETAG].clone();
+        let original_type = original.headers()[header::CONTENT_TYPE].clone();
+        let original_body = original.bytes().await.unwrap();
+        let revalidated = client
+            .get(format!("{covers_url}/covers/{}", book.id))
+            .header(header::IF_NONE_MATCH, original_etag.clone())
+            .send()
+            .await
+            .unwrap();
+        let thumbnail = client
+            .get(format!("{covers_url}/covers/{}/medium", book.id))
+            .send()
+            .await
+            .unwrap();
+        let unknown_size = client
+            .get(format!("{covers_url}/covers/{}/huge", book.id))
+            .send()
+            .await
+            .unwrap();
+        let no_cover = client
+            .get(format!("{covers_url}/covers/{}", uncovered.id))
+            .send()
+            .await
+            .unwrap();
+
+        // Cleanup
+        book.delete(&pool).await.unwrap();
+        uncovered.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(local, Err(CoverError::ForbiddenUrl)));
+        assert!(matches!(local_name, Err(CoverError::Download(_))));
+        assert!(matches!(missing, Err(CoverError::Status(404))));
+        assert_eq!(format!("\"{}\"", cover.hash), original_etag);
+        assert_eq!("image/png", original_type);
+        assert_eq!(data, original_body);
+        assert_eq!(StatusCode::NOT_MODIFIED, revalidated.status());
+        assert_eq!(StatusCode::OK, thumbnail.status());
+        assert_eq!("image/jpeg", thumbnail.headers()[header::CONTENT_TYPE]);
+        assert_eq!(
+            format!("\"{}-medium\"", cover.hash),
+            thumbnail.headers()[header::ETAG]
+        );
+        assert_eq!(StatusCode::NOT_FOUND, unknown_size.status());
+        assert_eq!(StatusCode::NOT_FOUND, no_cover.status());
+    }
+}
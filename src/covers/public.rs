@@ -0,0 +1,119 @@
+//! Keeps cover downloads to public http and https addresses, so a cover URL
+//! can't be used to reach the server's own network. Host names are checked
+//! as they are resolved, which covers redirects as well, and IP addresses in
+//! URLs are checked before they are requested.
+use reqwest::Url;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use std::io;
+use std::net::IpAddr;
+
+/// Redirects followed before giving up, as many as reqwest follows.
+const MAX_REDIRECTS: usize = 10;
+
+/// Whether `url` may be downloaded: an http or https URL whose host, if it
+/// is an IP address, is a public one.
+pub fn allowed(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    match url.host_str() {
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_or(true, is_public),
+        None => false,
+    }
+}
+
+/// Whether `ip` can be reached over the internet, as opposed to a loopback,
+/// private, link-local or otherwise reserved address.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// Resolves host names to their public addresses only.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(
+                    io::Error::other(format!("{} has no public address", name.as_str())).into(),
+                );
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Follows redirects to URLs that are `allowed`.
+pub fn redirect_policy() -> Policy {
+    Policy::custom(|attempt: Attempt| {
+        if !allowed(attempt.url()) {
+            attempt.error("redirected to a URL that isn't allowed")
+        } else if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_http_addresses_are_allowed() {
+        let allowed = |url: &str| allowed(&Url::parse(url).unwrap());
+
+        assert!(allowed("https://covers.openlibrary.org/b/id/1-L.jpg"));
+        assert!(allowed("http://93.184.215.14/cover.png"));
+        assert!(!allowed("ftp://example.com/cover.png"));
+        assert!(!allowed("file:///etc/passwd"));
+        for url in [
+            "http://127.0.0.1:8080/cover.png",
+            "http://10.0.0.5/cover.png",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/cover.png",
+            "http://0.0.0.0/cover.png",
+            "http://[::1]/cover.png",
+            "http://[fd00::1]/cover.png",
+            "http://[::ffff:192.168.1.1]/cover.png",
+        ] {
+            assert!(!allowed(url), "{url}");
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Finds books in the catalogue that are likely the same book entered twice,
+//! e.g. with a different ISBN or a typo in the title.
+//!
+//! Pairs are scored on their normalised title and authors, publication year
+//! and page count. Merge the duplicates found with `Book::merge_into`.
+use crate::models::book::Book;
+use deunicode::deunicode;
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+
+/// Score from which a pair is reported by `find_duplicates`.
+pub const DEFAULT_THRESHOLD: f64 = 0.85;
+
+const TITLE_WEIGHT: f64 = 0.45;
+const AUTHOR_WEIGHT: f64 = 0.35;
+const YEAR_WEIGHT: f64 = 0.1;
+const PAGES_WEIGHT: f64 = 0.1;
+
+/// Two books that are probably the same, `book_id` being the older one.
+#[derive(Debug, PartialEq)]
+pub struct DuplicateCandidate {
+    pub book_id: i64,
+    pub duplicate_id: i64,
+    /// From 0, nothing in common, to 1, identical.
+    pub score: f64,
+}
+
+/// Lowercases, transliterates to ASCII and strips punctuation, a leading
+/// article and redundant whitespace, so "The Brothers Karamazov" and
+/// "Brothers Karamázov." are the same.
+pub fn normalize_title(title: &str) -> String {
+    let words = normalized_words(title);
+    let words = match words.first().map(String::as_str) {
+        Some("the" | "a" | "an") if words.len() > 1 => &words[1..],
+        _ => &words[..],
+    };
+    words.join(" ")
+}
+
+/// The authors' names as sorted, normalised words, so the order of authors
+/// and of given and family names doesn't matter.
+pub fn normalize_authors(authors: &str) -> String {
+    let words: BTreeSet<String> = normalized_words(authors)
+        .into_iter()
+        // Initials are written too inconsistently to compare
+        .filter(|word| word.len() > 1)
+        .collect();
+    words.into_iter().collect::<Vec<_>>().join(" ")
+}
+
+fn normalized_words(value: &str) -> Vec<String> {
+    deunicode(value)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// How likely `a` and `b` are the same book, from 0 to 1.
+pub fn score(a: &Book, b: &Book) -> f64 {
+    let title = similarity(&normalize_title(&a.title), &normalize_title(&b.title));
+    let author = similarity(&normalize_authors(&a.author), &normalize_authors(&b.author));
+    let year = match (a.published_year - b.published_year).abs() {
+        0 => 1.0,
+        1 => 0.5,
+        _ => 0.0,
+    };
+    // An unknown page count neither speaks for nor against a match
+    let pages = match (a.pages, b.pages) {
+        (Some(a), Some(b)) => 1.0 - (a - b).abs() as f64 / a.max(b) as f64,
+        _ => 0.5,
+    };
+
+    TITLE_WEIGHT * title + AUTHOR_WEIGHT * author + YEAR_WEIGHT * year + PAGES_WEIGHT * pages
+}
+
+/// Levenshtein distance relative to the longer string, as 1 - distance.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    1.0 - previous[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+/// Scores pairs among `books` and returns those scoring at least
+/// `threshold`, best first.
+///
+/// Only books sharing the surname of an author or the first word of their
+/// title are compared, which keeps this fast on large catalogues while still
+/// catching a typo in either the title or the author.
+pub fn candidates(books: &[Book], threshold: f64) -> Vec<DuplicateCandidate> {
+    let mut blocks: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, book) in books.iter().enumerate() {
+        for key in blocking_keys(book) {
+            blocks.entry(key).or_default().push(idx);
+        }
+    }
+
+    let mut pairs = BTreeSet::new();
+    for block in blocks.values() {
+        for (n, &i) in block.iter().enumerate() {
+            for &j in &block[n + 1..] {
+                pairs.insert((i.min(j), i.max(j)));
+            }
+        }
+    }
+
+    let mut candidates: Vec<DuplicateCandidate> = pairs
+        .into_iter()
+        .filter_map(|(i, j)| {
+            let (a, b) = (&books[i], &books[j]);
+            let score = score(a, b);
+            (score >= threshold).then(|| DuplicateCandidate {
+                book_id: a.id.min(b.id),
+                duplicate_id: a.id.max(b.id),
+                score,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.book_id.cmp(&b.book_id))
+            .then(a.duplicate_id.cmp(&b.duplicate_id))
+    });
+
+    candidates
+}
+
+/// The keys of the blocks `book` is compared within: the first word of its
+/// normalised title and the surname of each author.
+fn blocking_keys(book: &Book) -> BTreeSet<String> {
+    let title = normalize_title(&book.title);
+    let title = title.split(' ').next().filter(|word| !word.is_empty());
+    // "Dostoevsky, Fyodor" and "Fyodor Dostoevsky" both give "dostoevsky"
+    let surnames = book
+        .author
+        .split(['&', ';', '/'])
+        .flat_map(|authors| authors.split(" and "))
+        .filter_map(|name| normalized_words(name.split(',').next()?).pop());
+
+    title
+        .map(|word| format!("t:{word}"))
+        .into_iter()
+        .chain(surnames.map(|surname| format!("a:{surname}")))
+        .collect()
+}
+
+/// Likely duplicates in the catalogue, best first.
+pub async fn find_duplicates(
+    pool: &PgPool,
+    threshold: f64,
+) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    Ok(candidates(&Book::all(pool).await?, threshold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::book::BookBuilder;
+
+    fn book(id: i64, title: &str, author: &str, year: i32, pages: Option<i32>) -> Book {
+        BookBuilder::default()
+            .id(id)
+            .title(title.to_string())
+            .author(author.to_string())
+            .isbn(format!("978000000000{id}"))
+            .published_year(year)
+            .pages(pages)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn normalizes_titles_and_authors() {
+        assert_eq!(
+            "brothers karamazov",
+            normalize_title("The Brothers Karamázov.")
+        );
+        assert_eq!("a", normalize_title("A"));
+        assert_eq!(
+            normalize_authors("Terry Pratchett & Neil Gaiman"),
+            normalize_authors("Gaiman, Neil & Pratchett, Terry")
+        );
+        assert_eq!("le tolkien", normalize_authors("J. R. R. Tolkien, Le"));
+    }
+
+    #[test]
+    fn blocks_on_surnames_and_first_title_word() {
+        let keys = |title, author| {
+            blocking_keys(&book(1, title, author, 2000, None))
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec!["a:dostoevsky", "t:brothers"],
+            keys("The Brothers Karamazov", "Dostoevsky, Fyodor")
+        );
+        assert_eq!(
+            vec!["a:dostoevsky", "t:brothers"],
+            keys("Brothers Karamazv", "Fyodor Dostoevsky")
+        );
+        assert_eq!(
+            vec!["a:gaiman", "a:pratchett", "t:good"],
+            keys("Good Omens", "Terry Pratchett & Neil Gaiman")
+        );
+        assert_eq!(vec!["a:tolkien"], keys("", "Tolkien, J. R. R."));
+    }
+
+    #[test]
+    fn finds_typos_and_other_editions() {
+        // Arrange
+        let books = [
+            book(
+                1,
+                "The Brothers Karamazov",
+                "Fyodor Dostoevsky",
+                1990,
+                Some(796),
+            ),
+            book(
+                2,
+                "Brothers Karamazv",
+                "Dostoevsky, Fyodor",
+                1990,
+                Some(796),
+            ),
+            book(
+                3,
+                "The Brothers Karamazov",
+                "Fyodor Dostoyevsky",
+                1991,
+                None,
+            ),
+            book(4, "The Idiot", "Fyodor Dostoevsky", 1990, Some(667)),
+            book(5, "Brothers", "Yu Hua", 2009, Some(641)),
+        ];
+
+        // Act
+        let found = candidates(&books, DEFAULT_THRESHOLD);
+
+        // Assert
+        let pairs: Vec<_> = found.iter().map(|c| (c.book_id, c.duplicate_id)).collect();
+        assert_eq!(vec![(1, 2), (1, 3), (2, 3)], pairs);
+        assert!(found[0].score > found[1].score);
+        assert!(score(&books[0], &books[3]) < DEFAULT_THRESHOLD);
+        assert!(score(&books[0], &books[4]) < 0.5);
+    }
+}
@@ -0,0 +1,371 @@
+//! Exports books as citations in BibTeX, RIS and CSL-JSON.
+//!
+//! Every book gets a citation key made of the first author's family name and
+//! the publication year, e.g. `dostoevsky2012`. Books in the same export that
+//! would share a key get `a`, `b`, ... appended in order of their IDs, so that
+//! exporting the same selection twice gives the same keys.
+use crate::models::book::Book;
+use deunicode::deunicode;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// A personal name split into its parts.
+#[derive(Debug, PartialEq)]
+pub struct Name<'a> {
+    pub family: &'a str,
+    pub given: Option<&'a str>,
+}
+
+/// Splits "Fyodor Dostoevsky" or "Dostoevsky, Fyodor" into given and family
+/// name. A single word is taken as a family name.
+pub fn split_name(name: &str) -> Name<'_> {
+    let name = name.trim();
+    if let Some((family, given)) = name.split_once(',') {
+        return Name {
+            family: family.trim(),
+            given: Some(given.trim()).filter(|g| !g.is_empty()),
+        };
+    }
+    match name.rsplit_once(' ') {
+        Some((given, family)) => Name {
+            family: family.trim(),
+            given: Some(given.trim()),
+        },
+        None => Name {
+            family: name,
+            given: None,
+        },
+    }
+}
+
+/// The citation key of a single book, without disambiguation.
+pub fn citation_key(book: &Book) -> String {
+    let base = match book.authors().first() {
+        Some(author) => split_name(author).family,
+        None => book.title.split_whitespace().next().unwrap_or("book"),
+    };
+    let base: String = deunicode(base)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+
+    format!("{base}{}", book.published_year)
+}
+
+/// Citation keys for a selection of books, in the same order as `books`.
+/// Colliding keys are made unique with a letter suffix.
+pub fn citation_keys(books: &[Book]) -> Vec<String> {
+    let keys: Vec<String> = books.iter().map(citation_key).collect();
+
+    let mut by_key: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, key) in keys.iter().enumerate() {
+        by_key.entry(key).or_default().push(idx);
+    }
+
+    let mut unique = keys.clone();
+    for (key, mut indices) in by_key {
+        if indices.len() < 2 {
+            continue;
+        }
+        indices.sort_by_key(|&idx| books[idx].id);
+        for (n, idx) in indices.into_iter().enumerate() {
+            unique[idx] = format!("{key}{}", suffix(n));
+        }
+    }
+
+    unique
+}
+
+/// `a` to `z`, then `aa`, `ab`, ...
+fn suffix(mut n: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    suffix.reverse();
+    String::from_utf8(suffix).expect("suffix is ASCII")
+}
+
+/// Escapes the characters that have a special meaning in (La)TeX.
+pub fn escape_bibtex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// BibTeX `@book` entries for the books.
+pub fn to_bibtex(books: &[Book]) -> String {
+    let mut bibtex = String::new();
+
+    for (book, key) in books.iter().zip(citation_keys(books)) {
+        let authors = book
+            .authors()
+            .iter()
+            .map(|author| match split_name(author) {
+                Name {
+                    family,
+                    given: Some(given),
+                } => format!("{}, {}", escape_bibtex(family), escape_bibtex(given)),
+                Name { family, .. } => format!("{{{}}}", escape_bibtex(family)),
+            })
+            .collect::<Vec<_>>()
+            .join(" and ");
+
+        bibtex.push_str(&format!("@book{{{key},\n"));
+        bibtex.push_str(&format!("  author = {{{authors}}},\n"));
+        // The extra braces keep BibTeX styles from changing the title's case
+        bibtex.push_str(&format!(
+            "  title = {{{{{}}}}},\n",
+            escape_bibtex(&book.title)
+        ));
+        bibtex.push_str(&format!("  year = {{{}}},\n", book.published_year));
+        bibtex.push_str(&format!("  isbn = {{{}}},\n", escape_bibtex(&book.isbn)));
+        if let Some(pages) = book.pages {
+            bibtex.push_str(&format!("  pagetotal = {{{pages}}},\n"));
+        }
+        bibtex.push_str("}\n\n");
+    }
+
+    bibtex.truncate(bibtex.trim_end().len());
+    bibtex.push('\n');
+    bibtex
+}
+
+/// RIS records for the books.
+pub fn to_ris(books: &[Book]) -> String {
+    // RIS values can't span lines
+    let line = |tag: &str, value: &str| {
+        format!(
+            "{tag}  - {}\r\n",
+            value.split_whitespace().collect::<Vec<_>>().join(" ")
+        )
+    };
+    let mut ris = String::new();
+
+    for (book, key) in books.iter().zip(citation_keys(books)) {
+        ris.push_str(&line("TY", "BOOK"));
+        ris.push_str(&line("ID", &key));
+        for author in book.authors() {
+            let name = match split_name(author) {
+                Name {
+                    family,
+                    given: Some(given),
+                } => format!("{family}, {given}"),
+                Name { family, .. } => family.to_string(),
+            };
+            ris.push_str(&line("AU", &name));
+        }
+        ris.push_str(&line("TI", &book.title));
+        ris.push_str(&line("PY", &book.published_year.to_string()));
+        ris.push_str(&line("SN", &book.isbn));
+        if let Some(description) = &book.description {
+            ris.push_str(&line("AB", description));
+        }
+        ris.push_str("ER  - \r\n");
+    }
+
+    ris
+}
+
+/// A CSL-JSON array of items for the books.
+pub fn to_csl_json(books: &[Book]) -> Value {
+    let items = books
+        .iter()
+        .zip(citation_keys(books))
+        .map(|(book, key)| {
+            let authors: Vec<Value> = book
+                .authors()
+                .iter()
+                .map(|author| match split_name(author) {
+                    Name {
+                        family,
+                        given: Some(given),
+                    } => json!({ "family": family, "given": given }),
+                    Name { family, .. } => json!({ "literal": family }),
+                })
+                .collect();
+
+            let mut item = json!({
+                "id": key,
+                "type": "book",
+                "title": book.title,
+                "author": authors,
+                "issued": { "date-parts": [[book.published_year]] },
+                "ISBN": book.isbn,
+            });
+            if let Some(pages) = book.pages {
+                item["number-of-pages"] = json!(pages);
+            }
+            if let Some(description) = &book.description {
+                item["abstract"] = json!(description);
+            }
+            item
+        })
+        .collect();
+
+    Value::Array(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::book::BookBuilder;
+
+    fn book(id: i64, title: &str, author: &str, year: i32) -> Book {
+        BookBuilder::default()
+            .id(id)
+            .title(title.to_string())
+            .author(author.to_string())
+            .isbn(format!("978000000000{id}"))
+            .published_year(year)
+            .pages(Some(300))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn splits_names() {
+        assert_eq!(
+            Name {
+                family: "Dostoevsky",
+                given: Some("Fyodor")
+            },
+            split_name("Fyodor Dostoevsky")
+        );
+        assert_eq!(
+            Name {
+                family: "Le Guin",
+                given: Some("Ursula K.")
+            },
+            split_name("Le Guin, Ursula K.")
+        );
+        assert_eq!(
+            Name {
+                family: "Homer",
+                given: None
+            },
+            split_name("Homer")
+        );
+    }
+
+    #[test]
+    fn keys_are_ascii_and_disambiguated_by_id() {
+        // Arrange
+        let books = [
+            book(7, "Anna Karenina", "Lev Tolstoj", 1878),
+            book(3, "War and Peace", "Lev Tolstoj", 1878),
+            book(5, "Hunger", "Knut Hamsun", 1890),
+            book(6, "Brand", "Henrik Ibsen & Someone Else", 1866),
+            book(8, "Die Verwandlung", "Franz Kafka", 1915),
+            book(9, "Zauberberg", "Thomas Mann", 1924),
+            book(10, "Der Prozess", "Kafka, Franz", 1915),
+            book(11, "Öl", "Ödön von Horváth", 1931),
+        ];
+
+        // Act
+        let keys = citation_keys(&books);
+
+        // Assert
+        assert_eq!(
+            vec![
+                "tolstoj1878b",
+                "tolstoj1878a",
+                "hamsun1890",
+                "ibsen1866",
+                "kafka1915a",
+                "mann1924",
+                "kafka1915b",
+                "horvath1931",
+            ],
+            keys
+        );
+    }
+
+    #[test]
+    fn bibtex_escapes_special_characters() {
+        // Arrange
+        let books = [book(
+            1,
+            "Cats & Dogs: 100% of $5_#1 {draft}",
+            "Jane Doe & Homer",
+            2001,
+        )];
+
+        // Act
+        let bibtex = to_bibtex(&books);
+
+        // Assert
+        assert_eq!(
+            "@book{doe2001,
+  author = {Doe, Jane and {Homer}},
+  title = {{Cats \\& Dogs: 100\\% of \\$5\\_\\#1 \\{draft\\}}},
+  year = {2001},
+  isbn = {9780000000001},
+  pagetotal = {300},
+}
+",
+            bibtex
+        );
+    }
+
+    #[test]
+    fn ris_record() {
+        // Arrange
+        let mut crime = book(1, "Crime and Punishment", "Fyodor Dostoevsky", 2012);
+        crime.description = Some("A novel.\nIn six parts.".to_string());
+
+        // Act
+        let ris = to_ris(&[crime]);
+
+        // Assert
+        assert_eq!(
+            "TY  - BOOK\r\nID  - dostoevsky2012\r\nAU  - Dostoevsky, Fyodor\r\nTI  - Crime and Punishment\r\nPY  - 2012\r\nSN  - 9780000000001\r\nAB  - A novel. In six parts.\r\nER  - \r\n",
+            ris
+        );
+    }
+
+    #[test]
+    fn csl_json_items() {
+        // Arrange
+        let books = [book(
+            1,
+            "Crime and \"Punishment\"",
+            "Fyodor Dostoevsky",
+            2012,
+        )];
+
+        // Act
+        let csl = to_csl_json(&books);
+
+        // Assert
+        assert_eq!(
+            json!([{
+                "id": "dostoevsky2012",
+                "type": "book",
+                "title": "Crime and \"Punishment\"",
+                "author": [{ "family": "Dostoevsky", "given": "Fyodor" }],
+                "issued": { "date-parts": [[2012]] },
+                "ISBN": "9780000000001",
+                "number-of-pages": 300,
+            }]),
+            csl
+        );
+        assert!(csl.to_string().contains(r#""Crime and \"Punishment\"""#));
+    }
+}
@@ -0,0 +1,86 @@
+//! Exports books as Dublin Core XML.
+//!
+//! Elements come from the Dublin Core element set (`dc:`), except the length
+//! of the book, which goes in `dcterms:extent`.
+use crate::models::book::{Book, BookFormat, Length};
+use quick_xml::escape::escape;
+
+pub const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+pub const DCTERMS_NAMESPACE: &str = "http://purl.org/dc/terms/";
+
+/// A `<metadata>` document describing the book.
+pub fn to_xml(book: &Book) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<metadata xmlns:dc=\"{DC_NAMESPACE}\" xmlns:dcterms=\"{DCTERMS_NAMESPACE}\">\n"
+    );
+    let mut element = |name: &str, value: &str| {
+        xml.push_str(&format!("  <{name}>{}</{name}>\n", escape(value)));
+    };
+
+    element("dc:title", &book.title);
+    for author in book.authors() {
+        element("dc:creator", author);
+    }
+    element("dc:date", &book.published_year.to_string());
+    element("dc:identifier", &format!("urn:isbn:{}", book.isbn));
+    element(
+        "dc:type",
+        match book.format {
+            BookFormat::Audiobook => "Sound",
+            _ => "Text",
+        },
+    );
+    match book.length() {
+        Some(Length::Pages(pages)) => element("dcterms:extent", &format!("{pages} pages")),
+        Some(Length::Minutes(minutes)) => element("dcterms:extent", &format!("{minutes} minutes")),
+        Some(Length::Words(words)) => element("dcterms:extent", &format!("{words} words")),
+        None => {}
+    }
+    if let Some(description) = &book.description {
+        element("dc:description", description);
+    }
+
+    xml.push_str("</metadata>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::book::BookBuilder;
+
+    #[test]
+    fn book_as_dublin_core() {
+        // Arrange
+        let book = BookBuilder::default()
+            .id(1)
+            .title("Crime & Punishment".to_string())
+            .author("Fyodor Dostoevsky & Richard Pevear".to_string())
+            .isbn("9780679734505".to_string())
+            .published_year(1993)
+            .description(Some("<p>A novel</p>".to_string()))
+            .pages(Some(565))
+            .build()
+            .unwrap();
+
+        // Act
+        let xml = to_xml(&book);
+
+        // Assert
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+  <dc:title>Crime &amp; Punishment</dc:title>
+  <dc:creator>Fyodor Dostoevsky</dc:creator>
+  <dc:creator>Richard Pevear</dc:creator>
+  <dc:date>1993</dc:date>
+  <dc:identifier>urn:isbn:9780679734505</dc:identifier>
+  <dc:type>Text</dc:type>
+  <dcterms:extent>565 pages</dcterms:extent>
+  <dc:description>&lt;p&gt;A novel&lt;/p&gt;</dc:description>
+</metadata>
+"#,
+            xml
+        );
+    }
+}
@@ -0,0 +1,4 @@
+//! Exporters turning bookshelf data into formats other tools understand.
+pub mod citation;
+pub mod dublin_core;
+pub mod schema_org;
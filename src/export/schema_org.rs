@@ -0,0 +1,202 @@
+//! Exports books and ratings as schema.org JSON-LD, for embedding in public
+//! shelf pages so search engines can index them.
+use crate::models::book::{Book, BookFormat};
+use crate::models::user::User;
+use crate::models::user_book::UserBook;
+use serde_json::{Value, json};
+
+/// Ratings are stored on a scale from 0 to 10.
+const WORST_RATING: i16 = 0;
+const BEST_RATING: i16 = 10;
+
+/// A schema.org `Book`.
+pub fn book(book: &Book) -> Value {
+    let mut value = json!({
+        "@context": "https://schema.org",
+        "@type": "Book",
+        "name": book.title,
+        "author": authors(book),
+        "isbn": book.isbn,
+        "datePublished": book.published_year.to_string(),
+        "bookFormat": match book.format {
+            BookFormat::Hardcover => "https://schema.org/Hardcover",
+            BookFormat::Paperback => "https://schema.org/Paperback",
+            BookFormat::Ebook => "https://schema.org/EBook",
+            BookFormat::Audiobook => "https://schema.org/AudiobookFormat",
+        },
+    });
+    if let Some(pages) = book.pages {
+        value["numberOfPages"] = json!(pages);
+    }
+    // schema.org durations are ISO 8601, e.g. "PT12H5M"
+    if let Some(minutes) = book.duration_minutes {
+        value["duration"] = json!(format!("PT{}H{}M", minutes / 60, minutes % 60));
+    }
+    if let Some(description) = &book.description {
+        value["description"] = json!(description);
+    }
+    if let Some(cover_url) = &book.cover_url {
+        value["image"] = json!(cover_url);
+    }
+    value
+}
+
+/// A schema.org `Review` of `book` by `reviewer`, holding the rating of their
+/// `UserBook`. Returns `None` if the book hasn't been rated.
+pub fn review(book: &Book, user_book: &UserBook, reviewer: &User) -> Option<Value> {
+    let rating = user_book.rating?;
+
+    let mut item_reviewed = self::book(book);
+    if let Some(item) = item_reviewed.as_object_mut() {
+        item.remove("@context");
+    }
+
+    Some(json!({
+        "@context": "https://schema.org",
+        "@type": "Review",
+        "itemReviewed": item_reviewed,
+        "author": { "@type": "Person", "name": reviewer.name },
+        "reviewRating": {
+            "@type": "Rating",
+            "ratingValue": rating,
+            "worstRating": WORST_RATING,
+            "bestRating": BEST_RATING,
+        },
+        "datePublished": user_book
+            .done_reading
+            .unwrap_or(user_book.added_at)
+            .format("%Y-%m-%d")
+            .to_string(),
+    }))
+}
+
+/// Wraps JSON-LD in a `<script>` element for an HTML page. `<` is escaped so
+/// a title containing `</script>` can't end the element early.
+pub fn script_tag(value: &Value) -> String {
+    format!(
+        "<script type=\"application/ld+json\">{}</script>",
+        value.to_string().replace('<', "\\u003c")
+    )
+}
+
+fn authors(book: &Book) -> Vec<Value> {
+    book.authors()
+        .iter()
+        .map(|name| json!({ "@type": "Person", "name": name }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::book::BookBuilder;
+    use crate::models::user::UserBuilder;
+    use crate::models::user_book::{ReadingStatus, UserBookBuilder};
+    use chrono::{TimeZone, Utc};
+
+    fn good_omens() -> Book {
+        BookBuilder::default()
+            .id(2)
+            .title("Good Omens".to_string())
+            .author("Terry Pratchett & Neil Gaiman".to_string())
+            .isbn("9780060853983".to_string())
+            .published_year(1990)
+            .pages(Some(412))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn book_json_ld() {
+        // Arrange
+        let book = good_omens();
+
+        // Act
+        let value = super::book(&book);
+
+        // Assert
+        assert_eq!(
+            json!({
+                "@context": "https://schema.org",
+                "@type": "Book",
+                "name": "Good Omens",
+                "author": [
+                    { "@type": "Person", "name": "Terry Pratchett" },
+                    { "@type": "Person", "name": "Neil Gaiman" },
+                ],
+                "isbn": "9780060853983",
+                "datePublished": "1990",
+                "bookFormat": "https://schema.org/Paperback",
+                "numberOfPages": 412,
+            }),
+            value
+        );
+    }
+
+    #[test]
+    fn audiobook_has_duration() {
+        // Arrange
+        let mut book = good_omens();
+        book.format = BookFormat::Audiobook;
+        book.pages = None;
+        book.duration_minutes = Some(765);
+
+        // Act
+        let value = super::book(&book);
+
+        // Assert
+        assert_eq!("https://schema.org/AudiobookFormat", value["bookFormat"]);
+        assert_eq!("PT12H45M", value["duration"]);
+        assert_eq!(None, value.get("numberOfPages"));
+    }
+
+    #[test]
+    fn rating_as_review() {
+        // Arrange
+        let book = good_omens();
+        let reviewer = UserBuilder::default()
+            .name("Anna".to_string())
+            .email("anna@example.com".to_string())
+            .password("secret".to_string())
+            .build()
+            .unwrap();
+        let mut user_book = UserBookBuilder::default()
+            .user_id(1)
+            .book_id(2)
+            .status(ReadingStatus::Completed)
+            .done_reading(Some(Utc.with_ymd_and_hms(2025, 3, 14, 20, 0, 0).unwrap()))
+            .build()
+            .unwrap();
+
+        // Act
+        let unrated = review(&book, &user_book, &reviewer);
+        user_book.rating = Some(8);
+        let rated = review(&book, &user_book, &reviewer).unwrap();
+
+        // Assert
+        assert_eq!(None, unrated);
+        assert_eq!("Review", rated["@type"]);
+        assert_eq!("Anna", rated["author"]["name"]);
+        assert_eq!(
+            json!({ "@type": "Rating", "ratingValue": 8, "worstRating": 0, "bestRating": 10 }),
+            rated["reviewRating"]
+        );
+        assert_eq!("2025-03-14", rated["datePublished"]);
+        assert_eq!("Good Omens", rated["itemReviewed"]["name"]);
+        assert_eq!(None, rated["itemReviewed"].get("@context"));
+    }
+
+    #[test]
+    fn script_tag_cannot_be_closed_by_content() {
+        // Arrange
+        let mut book = good_omens();
+        book.title = "</script><script>alert(1)</script>".to_string();
+
+        // Act
+        let tag = script_tag(&super::book(&book));
+
+        // Assert
+        assert_eq!(1, tag.matches("</script>").count());
+        assert!(tag.ends_with("</script>"));
+    }
+}
@@ -32,7 +32,7 @@ pub fn fake_book() -> Book {
         .published_year(NumberWithFormat("19##").fake::<String>().parse().unwrap())
         .description(Some(Sentence(5..10).fake()))
         .cover_url(Some(Faker.fake()))
-        .pages((100..=1000).fake())
+        .pages(Some((100..=1000).fake()))
         .build()
         .unwrap()
 }
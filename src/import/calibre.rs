@@ -0,0 +1,364 @@
+//! Imports books from a Calibre library by reading its `metadata.db` directly.
+//!
+//! The library is opened read-only, so it is safe to import from a library
+//! Calibre has open. Books are matched on ISBN: a book whose ISBN is already
+//! in the catalogue is reported as a conflict and left untouched.
+use crate::import::{normalize_isbn, strip_html};
+use crate::models::book::{Book, BookBuilder};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use sqlx::{PgPool, Row};
+use std::path::{Path, PathBuf};
+
+/// A book as it is stored in the Calibre library.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CalibreBook {
+    pub calibre_id: i64,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub isbn: Option<String>,
+    /// All identifiers, e.g. `("isbn", "9780307829603")` or `("goodreads", "7144")`.
+    pub identifiers: Vec<(String, String)>,
+    pub published_year: Option<i32>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub cover_path: Option<PathBuf>,
+    /// From a custom column labelled `pages`, as created by the Count Pages plugin.
+    pub pages: Option<i32>,
+}
+
+impl CalibreBook {
+    /// Turns the Calibre book into a `BookBuilder`, with the authors joined
+    /// by " & " and the cover as a `file://` URL. Calibre doesn't keep a page
+    /// count unless a plugin adds one, so `pages` is often `None`. ISBN and
+    /// publication year are left unset when the library lacks them.
+    pub fn to_builder(&self) -> BookBuilder {
+        let mut builder = BookBuilder::default();
+        builder
+            .title(self.title.clone())
+            .author(self.authors.join(" & "))
+            .description(self.description.clone())
+            .cover_url(
+                self.cover_path
+                    .as_ref()
+                    .map(|path| format!("file://{}", path.display())),
+            )
+            .pages(self.pages);
+        if let Some(isbn) = &self.isbn {
+            builder.isbn(isbn.clone());
+        }
+        if let Some(year) = self.published_year {
+            builder.published_year(year);
+        }
+        builder
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct IsbnConflict {
+    pub isbn: String,
+    pub calibre_title: String,
+    pub existing_book_id: i64,
+    pub existing_title: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SkipReason {
+    MissingIsbn,
+    MissingPublicationYear,
+}
+
+/// Summary of an import.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// IDs of the books that were created.
+    pub imported: Vec<i64>,
+    /// Books whose ISBN is already in the catalogue.
+    pub conflicts: Vec<IsbnConflict>,
+    /// Titles of books that lack data a `Book` requires.
+    pub skipped: Vec<(String, SkipReason)>,
+}
+
+/// Reads every book in the Calibre library at `library`, the directory
+/// containing `metadata.db`.
+pub async fn read_library(library: &Path) -> Result<Vec<CalibreBook>, sqlx::Error> {
+    let options = SqliteConnectOptions::new()
+        .filename(library.join("metadata.db"))
+        .read_only(true);
+    let db = SqlitePool::connect_with(options).await?;
+
+    let pages_column = pages_column(&db).await?;
+    let rows = sqlx::query(
+        r#"
+        SELECT id, title, CAST(substr(pubdate, 1, 4) AS INTEGER) AS year, series_index, path,
+               CAST(has_cover AS INTEGER) AS has_cover
+        FROM books
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(&db)
+    .await?;
+
+    let mut books = Vec::with_capacity(rows.len());
+    for row in rows {
+        let id: i64 = row.try_get("id")?;
+        let path: String = row.try_get("path")?;
+        // Calibre uses the year 101 for an unknown publication date
+        let year: Option<i64> = row.try_get("year")?;
+        let has_cover: Option<i64> = row.try_get("has_cover")?;
+        let series_index: Option<f64> = row.try_get("series_index")?;
+
+        let identifiers: Vec<(String, String)> =
+            sqlx::query_as("SELECT type, val FROM identifiers WHERE book = ? ORDER BY id")
+                .bind(id)
+                .fetch_all(&db)
+                .await?;
+        let isbn = identifiers
+            .iter()
+            .filter(|(kind, _)| kind == "isbn")
+            .find_map(|(_, value)| normalize_isbn(value));
+
+        let series: Option<String> = sqlx::query_scalar(
+            "SELECT s.name FROM series s JOIN books_series_link l ON l.series = s.id WHERE l.book = ?",
+        )
+        .bind(id)
+        .fetch_optional(&db)
+        .await?;
+        let description: Option<String> =
+            sqlx::query_scalar("SELECT text FROM comments WHERE book = ?")
+                .bind(id)
+                .fetch_optional(&db)
+                .await?;
+        let pages = match &pages_column {
+            Some(table) => {
+                sqlx::query_scalar::<_, i64>(&format!("SELECT value FROM {table} WHERE book = ?"))
+                    .bind(id)
+                    .fetch_optional(&db)
+                    .await?
+            }
+            None => None,
+        };
+
+        books.push(CalibreBook {
+            calibre_id: id,
+            title: row.try_get("title")?,
+            authors: sqlx::query_scalar(
+                "SELECT a.name FROM authors a JOIN books_authors_link l ON l.author = a.id WHERE l.book = ? ORDER BY l.id",
+            )
+            .bind(id)
+            .fetch_all(&db)
+            .await?,
+            isbn,
+            identifiers,
+            published_year: year.filter(|y| *y > 101).map(|y| y as i32),
+            series_index: series.as_ref().and(series_index),
+            series,
+            tags: sqlx::query_scalar(
+                "SELECT t.name FROM tags t JOIN books_tags_link l ON l.tag = t.id WHERE l.book = ? ORDER BY t.name",
+            )
+            .bind(id)
+            .fetch_all(&db)
+            .await?,
+            description: description
+                .map(|html| strip_html(&html))
+                .filter(|text| !text.is_empty()),
+            cover_path: (has_cover == Some(1)).then(|| library.join(&path).join("cover.jpg")),
+            pages: pages
+                .and_then(|p| i32::try_from(p).ok())
+                .filter(|p| *p > 0),
+        });
+    }
+
+    db.close().await;
+    Ok(books)
+}
+
+/// The table of the custom column holding page counts, if the library has one.
+async fn pages_column(db: &SqlitePool) -> Result<Option<String>, sqlx::Error> {
+    let id: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM custom_columns WHERE label = 'pages' AND datatype = 'int'",
+    )
+    .fetch_optional(db)
+    .await
+    // Libraries that never had a custom column have no such table
+    .or_else(|e| match e {
+        sqlx::Error::Database(_) => Ok(None),
+        e => Err(e),
+    })?;
+
+    Ok(id.map(|id| format!("custom_column_{id}")))
+}
+
+/// Creates a `Book` for every book in the Calibre library that isn't in the
+/// catalogue yet.
+pub async fn import(pool: &PgPool, library: &Path) -> Result<ImportReport, sqlx::Error> {
+    let mut report = ImportReport::default();
+
+    for calibre_book in read_library(library).await? {
+        let Some(isbn) = &calibre_book.isbn else {
+            report
+                .skipped
+                .push((calibre_book.title, SkipReason::MissingIsbn));
+            continue;
+        };
+        if calibre_book.published_year.is_none() {
+            report
+                .skipped
+                .push((calibre_book.title, SkipReason::MissingPublicationYear));
+            continue;
+        }
+
+        if let Some(existing) = Book::get_by_isbn(pool, isbn).await? {
+            report.conflicts.push(IsbnConflict {
+                isbn: isbn.clone(),
+                calibre_title: calibre_book.title,
+                existing_book_id: existing.id,
+                existing_title: existing.title,
+            });
+            continue;
+        }
+
+        let mut book = calibre_book
+            .to_builder()
+            .build()
+            .expect("ISBN and publication year are checked above");
+        book.create(pool).await?;
+        report.imported.push(book.id);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories;
+    use crate::test_utils::setup_db;
+    use fake::Fake;
+
+    /// Creates a Calibre library with the parts of the Calibre schema the
+    /// importer reads.
+    async fn create_library(dir: &Path, isbns: [&str; 2]) {
+        let options = SqliteConnectOptions::new()
+            .filename(dir.join("metadata.db"))
+            .create_if_missing(true);
+        let db = SqlitePool::connect_with(options).await.unwrap();
+
+        let statements = format!(
+            r#"
+            CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT NOT NULL, pubdate TIMESTAMP,
+                series_index REAL NOT NULL DEFAULT 1.0, path TEXT NOT NULL DEFAULT '', has_cover BOOL DEFAULT 0);
+            CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, author INTEGER NOT NULL);
+            CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, type TEXT NOT NULL, val TEXT NOT NULL);
+            CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, series INTEGER NOT NULL);
+            CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, tag INTEGER NOT NULL);
+            CREATE TABLE comments (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, text TEXT NOT NULL);
+            CREATE TABLE custom_columns (id INTEGER PRIMARY KEY, label TEXT NOT NULL, name TEXT NOT NULL, datatype TEXT NOT NULL);
+            CREATE TABLE custom_column_1 (id INTEGER PRIMARY KEY, book INTEGER, value INTEGER NOT NULL);
+
+            INSERT INTO books VALUES (1, 'Good Omens', '1990-05-01 00:00:00+00:00', 1.0, 'Terry Pratchett/Good Omens (1)', 1);
+            INSERT INTO books VALUES (2, 'Dune', '1965-08-01 00:00:00+00:00', 1.0, 'Frank Herbert/Dune (2)', 0);
+            INSERT INTO books VALUES (3, 'Notes', '0101-01-01 00:00:00+00:00', 1.0, 'Unknown/Notes (3)', 0);
+            INSERT INTO authors VALUES (1, 'Terry Pratchett'), (2, 'Neil Gaiman'), (3, 'Frank Herbert');
+            INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 1, 2), (3, 2, 3);
+            INSERT INTO identifiers VALUES (1, 1, 'isbn', '{}'), (2, 1, 'goodreads', '12067'), (3, 2, 'isbn', '{}');
+            INSERT INTO series VALUES (1, 'Dune');
+            INSERT INTO books_series_link VALUES (1, 2, 1);
+            INSERT INTO tags VALUES (1, 'Fantasy'), (2, 'Humor');
+            INSERT INTO books_tags_link VALUES (1, 1, 2), (2, 1, 1);
+            INSERT INTO comments VALUES (1, 1, '<p>The world will end on a <b>Saturday</b>.</p>');
+            INSERT INTO custom_columns VALUES (1, 'pages', 'Pages', 'int');
+            INSERT INTO custom_column_1 VALUES (1, 1, 412);
+            "#,
+            isbns[0], isbns[1]
+        );
+        sqlx::raw_sql(&statements).execute(&db).await.unwrap();
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn reads_library() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        create_library(dir.path(), ["978-0-06-085398-3", "9780441013593"]).await;
+
+        // Act
+        let books = read_library(dir.path()).await.unwrap();
+
+        // Assert
+        assert_eq!(3, books.len());
+        assert_eq!(
+            CalibreBook {
+                calibre_id: 1,
+                title: "Good Omens".to_string(),
+                authors: vec!["Terry Pratchett".to_string(), "Neil Gaiman".to_string()],
+                isbn: Some("9780060853983".to_string()),
+                identifiers: vec![
+                    ("isbn".to_string(), "978-0-06-085398-3".to_string()),
+                    ("goodreads".to_string(), "12067".to_string())
+                ],
+                published_year: Some(1990),
+                series: None,
+                series_index: None,
+                tags: vec!["Fantasy".to_string(), "Humor".to_string()],
+                description: Some("The world will end on a Saturday.".to_string()),
+                cover_path: Some(dir.path().join("Terry Pratchett/Good Omens (1)/cover.jpg")),
+                pages: Some(412),
+            },
+            books[0]
+        );
+        assert_eq!(Some("Dune".to_string()), books[1].series);
+        assert_eq!(Some(1.0), books[1].series_index);
+        assert_eq!(None, books[1].cover_path);
+        assert_eq!(None, books[2].published_year);
+    }
+
+    #[tokio::test]
+    async fn import_creates_books_and_reports_conflicts() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut existing = factories::fake_book();
+        existing.isbn = format!("978{:010}", fake_suffix());
+        existing.create(&pool).await.unwrap();
+        let new_isbn = format!("978{:010}", fake_suffix());
+        let dir = tempfile::tempdir().unwrap();
+        create_library(dir.path(), [&new_isbn, &existing.isbn]).await;
+
+        // Act
+        let report = import(&pool, dir.path()).await.unwrap();
+        let created = Book::get(&pool, report.imported[0]).await.unwrap();
+
+        created.delete(&pool).await.unwrap();
+        existing.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(1, report.imported.len());
+        assert_eq!("Good Omens", created.title);
+        assert_eq!("Terry Pratchett & Neil Gaiman", created.author);
+        assert_eq!(new_isbn, created.isbn);
+        assert_eq!(1990, created.published_year);
+        assert_eq!(Some(412), created.pages);
+        assert_eq!(
+            vec![IsbnConflict {
+                isbn: existing.isbn.clone(),
+                calibre_title: "Dune".to_string(),
+                existing_book_id: existing.id,
+                existing_title: existing.title.clone(),
+            }],
+            report.conflicts
+        );
+        assert_eq!(
+            vec![("Notes".to_string(), SkipReason::MissingIsbn)],
+            report.skipped
+        );
+    }
+
+    /// A random number to build ISBNs no other test uses.
+    fn fake_suffix() -> i64 {
+        (0..9_999_999_999i64).fake()
+    }
+}
@@ -0,0 +1,582 @@
+//! Reads the metadata of an EPUB file so a `Book` can be created from it.
+//!
+//! An EPUB is a zip archive whose `META-INF/container.xml` points at the OPF
+//! package document. The package's `<metadata>` holds the Dublin Core title,
+//! creators, identifiers, date and description, and its manifest and spine
+//! point at the cover image and the content documents.
+use crate::import::{normalize_isbn, strip_html};
+use crate::models::book::{BookBuilder, BookFormat};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use std::fmt;
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+
+/// Characters of text on a printed page, used to estimate page counts.
+pub const CHARS_PER_PAGE: usize = 1500;
+
+/// Largest file read from an EPUB, uncompressed. Chapters and covers are far
+/// smaller, so this only stops archives that unpack to more than they hold.
+pub const MAX_ENTRY_BYTES: u64 = 32 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum EpubError {
+    Zip(zip::result::ZipError),
+    Io(std::io::Error),
+    Xml(quick_xml::Error),
+    /// The container doesn't point at a package document.
+    MissingPackage,
+    MissingTitle,
+    /// A file in the EPUB unpacks to more than `MAX_ENTRY_BYTES`.
+    EntryTooLarge(String),
+}
+
+impl fmt::Display for EpubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpubError::Zip(e) => write!(f, "invalid EPUB archive: {e}"),
+            EpubError::Io(e) => write!(f, "could not read EPUB: {e}"),
+            EpubError::Xml(e) => write!(f, "invalid XML in EPUB: {e}"),
+            EpubError::MissingPackage => write!(f, "EPUB has no package document"),
+            EpubError::MissingTitle => write!(f, "EPUB has no title"),
+            EpubError::EntryTooLarge(name) => write!(f, "{name} in EPUB is too large"),
+        }
+    }
+}
+
+impl std::error::Error for EpubError {}
+
+impl From<zip::result::ZipError> for EpubError {
+    fn from(e: zip::result::ZipError) -> Self {
+        EpubError::Zip(e)
+    }
+}
+
+impl From<std::io::Error> for EpubError {
+    fn from(e: std::io::Error) -> Self {
+        EpubError::Io(e)
+    }
+}
+
+impl From<quick_xml::Error> for EpubError {
+    fn from(e: quick_xml::Error) -> Self {
+        EpubError::Xml(e)
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for EpubError {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        EpubError::Xml(e.into())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CoverImage {
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+/// The metadata of an EPUB.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Epub {
+    pub title: String,
+    pub creators: Vec<String>,
+    pub isbn: Option<String>,
+    pub published_year: Option<i32>,
+    pub description: Option<String>,
+    pub cover: Option<CoverImage>,
+    /// Page count of the print edition, if the publisher declared one.
+    pub declared_pages: Option<i32>,
+    /// Page count estimated from the amount of text, see `CHARS_PER_PAGE`.
+    pub estimated_pages: i32,
+    pub word_count: i32,
+}
+
+impl Epub {
+    /// Turns the metadata into a `BookBuilder`, using the estimated page count
+    /// when the EPUB doesn't declare one, and no page count when neither is
+    /// above 0. ISBN and publication year are left
+    /// unset when the EPUB lacks them, and the embedded cover has no URL yet.
+    pub fn to_builder(&self) -> BookBuilder {
+        let mut builder = BookBuilder::default();
+        builder
+            .title(self.title.clone())
+            .author(self.creators.join(" & "))
+            .description(self.description.clone())
+            .format(BookFormat::Ebook)
+            .pages(
+                self.declared_pages
+                    .filter(|pages| *pages > 0)
+                    .or((self.estimated_pages > 0).then_some(self.estimated_pages)),
+            )
+            .word_count(Some(self.word_count).filter(|words| *words > 0));
+        if let Some(isbn) = &self.isbn {
+            builder.isbn(isbn.clone());
+        }
+        if let Some(year) = self.published_year {
+            builder.published_year(year);
+        }
+        builder
+    }
+}
+
+struct ManifestItem {
+    id: String,
+    href: String,
+    media_type: String,
+    properties: String,
+}
+
+/// Reads the metadata of the EPUB in `file`.
+pub fn read<R: Read + Seek>(file: R) -> Result<Epub, EpubError> {
+    let mut archive = ZipArchive::new(file)?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let package_path = rootfile_path(&container)?.ok_or(EpubError::MissingPackage)?;
+    let package = read_entry(&mut archive, &package_path)?;
+    let package_dir = match package_path.rfind('/') {
+        Some(idx) => &package_path[..=idx],
+        None => "",
+    };
+
+    let mut reader = Reader::from_str(&package);
+    reader.config_mut().trim_text(true);
+
+    let mut title = None;
+    let mut creators = Vec::new();
+    let mut isbn = None;
+    let mut published_year = None;
+    let mut description = None;
+    let mut declared_pages = None;
+    let mut cover_id = None;
+    let mut manifest = Vec::new();
+    let mut spine = Vec::new();
+
+    // The element whose text is being read, with its attributes
+    let mut current: Option<(String, Vec<(String, String)>)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                current = Some((local_name(&e), attributes(&e)?));
+            }
+            Event::Empty(e) => {
+                let attrs = attributes(&e)?;
+                let attr = |name: &str| {
+                    attrs
+                        .iter()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, value)| value.clone())
+                };
+                match local_name(&e).as_str() {
+                    "item" => manifest.push(ManifestItem {
+                        id: attr("id").unwrap_or_default(),
+                        href: attr("href").unwrap_or_default(),
+                        media_type: attr("media-type").unwrap_or_default(),
+                        properties: attr("properties").unwrap_or_default(),
+                    }),
+                    "itemref" => spine.extend(attr("idref")),
+                    // EPUB 2 marks the cover with <meta name="cover" content="item-id"/>
+                    "meta" if attr("name").as_deref() == Some("cover") => {
+                        cover_id = attr("content")
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(e) => {
+                let Some((name, attrs)) = &current else {
+                    continue;
+                };
+                let text = text(&e).trim().to_string();
+                let attr = |key: &str| {
+                    attrs
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, v)| v.as_str())
+                };
+                match name.as_str() {
+                    "title" if title.is_none() => title = Some(text),
+                    "creator" if matches!(attr("role"), None | Some("aut")) => creators.push(text),
+                    "identifier" if isbn.is_none() => {
+                        // Identifiers without a scheme are often ISBNs too
+                        let scheme = attr("scheme").unwrap_or("isbn");
+                        if scheme.eq_ignore_ascii_case("isbn") {
+                            isbn = normalize_isbn(&text);
+                        }
+                    }
+                    "date" if matches!(attr("event"), None | Some("publication")) => {
+                        published_year =
+                            published_year.or(text.get(..4).and_then(|y| y.parse().ok()));
+                    }
+                    "description" => {
+                        description = Some(strip_html(&text)).filter(|d| !d.is_empty())
+                    }
+                    "meta" if attr("property") == Some("schema:numberOfPages") => {
+                        declared_pages = text.parse().ok()
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(_) => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // EPUB 3 marks the cover with a manifest property instead
+    let cover_item = manifest
+        .iter()
+        .find(|item| {
+            item.properties
+                .split_whitespace()
+                .any(|p| p == "cover-image")
+        })
+        .or_else(|| {
+            let id = cover_id.as_deref()?;
+            manifest.iter().find(|item| item.id == id)
+        });
+    let cover = match cover_item {
+        Some(item) => Some(CoverImage {
+            media_type: item.media_type.clone(),
+            data: read_entry_bytes(&mut archive, &resolve(package_dir, &item.href))?,
+        }),
+        None => None,
+    };
+
+    let mut text_length = 0;
+    let mut word_count = 0;
+    for idref in &spine {
+        let Some(item) = manifest.iter().find(|item| &item.id == idref) else {
+            continue;
+        };
+        let document = read_entry(&mut archive, &resolve(package_dir, &item.href))?;
+        let (chars, words) = body_text_length(&document)?;
+        text_length += chars;
+        word_count += words;
+    }
+
+    Ok(Epub {
+        title: title.ok_or(EpubError::MissingTitle)?,
+        creators,
+        isbn,
+        published_year,
+        description,
+        cover,
+        declared_pages,
+        estimated_pages: text_length.div_ceil(CHARS_PER_PAGE) as i32,
+        word_count: word_count as i32,
+    })
+}
+
+fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<String, EpubError> {
+    let bytes = read_entry_bytes(archive, name)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn read_entry_bytes<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>, EpubError> {
+    let entry = archive.by_name(name)?;
+    // The size in the header can't be trusted, so the reading is limited too
+    if entry.size() > MAX_ENTRY_BYTES {
+        return Err(EpubError::EntryTooLarge(name.to_string()));
+    }
+    let mut bytes = Vec::new();
+    entry.take(MAX_ENTRY_BYTES + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_ENTRY_BYTES {
+        return Err(EpubError::EntryTooLarge(name.to_string()));
+    }
+    Ok(bytes)
+}
+
+/// The path of the package document from `META-INF/container.xml`.
+fn rootfile_path(container: &str) -> Result<Option<String>, EpubError> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if local_name(&e) == "rootfile" => {
+                return Ok(attributes(&e)?
+                    .into_iter()
+                    .find(|(key, _)| key == "full-path")
+                    .map(|(_, value)| value));
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Number of characters and of words of text in the body of an XHTML content
+/// document.
+fn body_text_length(document: &str) -> Result<(usize, usize), EpubError> {
+    let mut reader = Reader::from_str(document);
+    reader.config_mut().trim_text(true);
+    let mut in_body = false;
+    let mut skipping = 0;
+    let mut length = 0;
+    let mut words = 0;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match local_name(&e).as_str() {
+                "body" => in_body = true,
+                "script" | "style" => skipping += 1,
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"body" => in_body = false,
+                b"script" | b"style" => skipping -= 1,
+                _ => {}
+            },
+            Event::Text(e) if in_body && skipping == 0 => {
+                for word in text(&e).split_whitespace() {
+                    length += word.chars().count() + 1;
+                    words += 1;
+                }
+            }
+            Event::Eof => return Ok((length, words)),
+            _ => {}
+        }
+    }
+}
+
+/// The text of an event with its entities resolved, or as it is written when
+/// it uses entities XML doesn't define, like the `&nbsp;` of HTML.
+fn text(e: &BytesText) -> String {
+    match e.unescape() {
+        Ok(text) => text.into_owned(),
+        Err(_) => String::from_utf8_lossy(e).into_owned(),
+    }
+}
+
+fn local_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.local_name().as_ref()).into_owned()
+}
+
+/// Attributes by local name, so that `opf:scheme` and `scheme` are the same.
+fn attributes(e: &BytesStart) -> Result<Vec<(String, String)>, EpubError> {
+    e.attributes()
+        .map(|attr| {
+            let attr = attr?;
+            Ok((
+                String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned(),
+                attr.unescape_value()?.into_owned(),
+            ))
+        })
+        .collect()
+}
+
+/// Resolves a manifest `href` against the directory of the package document.
+fn resolve(base: &str, href: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            part => parts.push(part),
+        }
+    }
+    parts.join("/").replace("%20", " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_db;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    fn build_epub(package: &str, chapter: &str) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, contents) in [
+            ("mimetype", "application/epub+zip".as_bytes()),
+            ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ("OEBPS/content.opf", package.as_bytes()),
+            ("OEBPS/text/chapter1.xhtml", chapter.as_bytes()),
+            ("OEBPS/images/cover.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]),
+        ] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn chapter(words: usize) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Chapter 1</title><style>p {{ margin: 0 }}</style></head>
+<body><p>{}</p></body></html>"#,
+            "word ".repeat(words)
+        )
+    }
+
+    #[test]
+    fn reads_epub2_metadata() {
+        // Arrange
+        let package = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>Crime and Punishment</dc:title>
+    <dc:creator opf:role="aut" opf:file-as="Dostoevsky, Fyodor">Fyodor Dostoevsky</dc:creator>
+    <dc:creator opf:role="trl">Constance Garnett</dc:creator>
+    <dc:identifier id="uid">urn:uuid:1b0b6c5e-9f3d-4a7c-9f4e-1d2c3b4a5f60</dc:identifier>
+    <dc:identifier opf:scheme="ISBN">978-0-307-82960-3</dc:identifier>
+    <dc:date opf:event="modification">2020-01-01</dc:date>
+    <dc:date opf:event="publication">2012-05-01</dc:date>
+    <dc:description>&lt;p&gt;A &lt;i&gt;dark&lt;/i&gt; novel.&lt;/p&gt;</dc:description>
+    <meta name="cover" content="cover-img"/>
+  </metadata>
+  <manifest>
+    <item id="cover-img" href="images/cover.jpg" media-type="image/jpeg"/>
+    <item id="ch1" href="text/chapter1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let epub = build_epub(package, &chapter(1000));
+
+        // Act
+        let metadata = read(Cursor::new(epub)).unwrap();
+
+        // Assert
+        assert_eq!(
+            Epub {
+                title: "Crime and Punishment".to_string(),
+                creators: vec!["Fyodor Dostoevsky".to_string()],
+                isbn: Some("9780307829603".to_string()),
+                published_year: Some(2012),
+                description: Some("A dark novel.".to_string()),
+                cover: Some(CoverImage {
+                    media_type: "image/jpeg".to_string(),
+                    data: vec![0xFF, 0xD8, 0xFF, 0xE0],
+                }),
+                declared_pages: None,
+                // 1000 words of four letters and a space
+                estimated_pages: 4,
+                word_count: 1000,
+            },
+            metadata
+        );
+    }
+
+    #[test]
+    fn reads_epub3_cover_and_declared_pages() {
+        // Arrange
+        let package = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:isbn:9780441013593</dc:identifier>
+    <dc:title>Dune</dc:title>
+    <dc:creator id="c1">Frank Herbert</dc:creator>
+    <dc:date>1965</dc:date>
+    <meta property="schema:numberOfPages">604</meta>
+  </metadata>
+  <manifest>
+    <item id="img" href="images/cover.jpg" media-type="image/jpeg" properties="cover-image"/>
+    <item id="ch1" href="text/chapter1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let epub = build_epub(package, &chapter(10).replace("<p>", "<p>&nbsp;"));
+
+        // Act
+        let metadata = read(Cursor::new(epub)).unwrap();
+        let book = metadata.to_builder().build().unwrap();
+
+        // Assert
+        assert!(metadata.cover.is_some());
+        assert_eq!(Some(604), metadata.declared_pages);
+        assert_eq!(10, metadata.word_count);
+        assert_eq!("Dune", book.title);
+        assert_eq!("Frank Herbert", book.author);
+        assert_eq!("9780441013593", book.isbn);
+        assert_eq!(1965, book.published_year);
+        assert_eq!(Some(604), book.pages);
+        assert_eq!(BookFormat::Ebook, book.format);
+    }
+
+    #[tokio::test]
+    async fn text_less_epub_is_imported_without_pages() {
+        // Arrange
+        let pool = setup_db().await;
+        let isbn = crate::factories::fake_book().isbn;
+        let package = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:9d3c2a1e-5b7f-4e0a-8c6d-2f1e0b9a8c7d</dc:identifier>
+    <dc:title>Picture Book</dc:title>
+    <dc:date>2021</dc:date>
+    <meta property="schema:numberOfPages">0</meta>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="text/chapter1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let epub = build_epub(package, &chapter(0));
+
+        // Act
+        let metadata = read(Cursor::new(epub)).unwrap();
+        let mut book = metadata.to_builder().isbn(isbn).build().unwrap();
+        let created = book.create(&pool).await;
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(0, metadata.estimated_pages);
+        assert!(created.is_ok());
+        assert_eq!(None, book.pages);
+    }
+
+    #[test]
+    fn oversized_entries_are_an_error() {
+        // Arrange
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("META-INF/container.xml", options).unwrap();
+        zip.write_all(CONTAINER.as_bytes()).unwrap();
+        zip.start_file("OEBPS/content.opf", options).unwrap();
+        let padding = vec![b' '; 1024 * 1024];
+        for _ in 0..=MAX_ENTRY_BYTES / padding.len() as u64 {
+            zip.write_all(&padding).unwrap();
+        }
+        let epub = zip.finish().unwrap().into_inner();
+
+        // Act
+        let result = read(Cursor::new(epub));
+
+        // Assert
+        assert!(
+            matches!(result, Err(EpubError::EntryTooLarge(name)) if name == "OEBPS/content.opf")
+        );
+    }
+
+    #[test]
+    fn missing_title_is_an_error() {
+        // Arrange
+        let package = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"></metadata>
+  <manifest/><spine/>
+</package>"#;
+        let epub = build_epub(package, &chapter(10));
+
+        // Act
+        let result = read(Cursor::new(epub));
+
+        // Assert
+        assert!(matches!(result, Err(EpubError::MissingTitle)));
+    }
+}
@@ -0,0 +1,458 @@
+//! Imports highlights, notes and bookmarks from a Kindle's `My Clippings.txt`.
+//!
+//! Every clipping in the file looks like this, with clippings separated by a
+//! line of `=`:
+//!
+//! ```text
+//! Crime and Punishment (Fyodor Dostoevsky)
+//! - Your Highlight on page 12 | Location 180-182 | Added on Sunday, March 3, 2019 10:00:00 PM
+//!
+//! Pain and suffering are always inevitable for a large intelligence.
+//! ==========
+//! ```
+//!
+//! Kindle stores a note separately from the highlight it was made on, so notes
+//! are attached to the highlight ending at the note's location when there is
+//! one. Clippings are matched to existing books by title and author, and
+//! importing the same file twice doesn't duplicate anything.
+use crate::models::book::Book;
+use crate::models::highlight::{Highlight, HighlightBuilder, HighlightKind};
+use crate::models::user_book::{UserBook, UserBookBuilder};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+
+const SEPARATOR: &str = "==========";
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Clipping {
+    pub title: String,
+    pub author: Option<String>,
+    pub kind: HighlightKind,
+    pub page: Option<i32>,
+    pub location: Option<String>,
+    pub added_at: Option<DateTime<Utc>>,
+    pub content: String,
+}
+
+/// Summary of an import.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// Number of highlights, notes and bookmarks added.
+    pub imported: usize,
+    /// Number of clippings that had already been imported.
+    pub duplicates: usize,
+    /// Titles of the books that couldn't be matched to a book.
+    pub unmatched: Vec<String>,
+}
+
+/// Parses the contents of a `My Clippings.txt` file. Entries that don't look
+/// like clippings are skipped.
+pub fn parse(contents: &str) -> Vec<Clipping> {
+    contents
+        .trim_start_matches('\u{feff}')
+        .split(SEPARATOR)
+        .filter_map(parse_clipping)
+        .collect()
+}
+
+fn parse_clipping(entry: &str) -> Option<Clipping> {
+    let mut lines = entry
+        .lines()
+        .map(|line| line.trim_matches(|c: char| c == '\u{feff}' || c.is_whitespace()))
+        .skip_while(|line| line.is_empty());
+
+    let (title, author) = parse_title_line(lines.next()?);
+    let metadata = lines.next()?.strip_prefix('-')?.trim();
+    let content = lines
+        .skip_while(|line| line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut segments = metadata.split('|').map(str::trim);
+    let description = segments.next()?;
+    let kind = parse_kind(description)?;
+
+    let mut page = None;
+    let mut location = None;
+    let mut added_at = None;
+    for segment in std::iter::once(description).chain(segments) {
+        let lower = segment.to_lowercase();
+        if let Some(date) = segment.strip_prefix("Added on ") {
+            added_at = parse_date(date);
+        }
+        // Lowercasing can change the length of a segment, so positions
+        // found in `lower` only index `lower`
+        if let Some(idx) = lower.find("page ") {
+            page = lower[idx + "page ".len()..]
+                .split_whitespace()
+                .next()
+                .and_then(|p| p.parse().ok());
+        }
+        if let Some(idx) = lower.find("location ").or_else(|| lower.find("loc. ")) {
+            let start = idx + lower[idx..].find(' ')? + 1;
+            location = lower[start..].split_whitespace().next().map(str::to_string);
+        }
+    }
+
+    Some(Clipping {
+        title,
+        author,
+        kind,
+        page,
+        location,
+        added_at,
+        content,
+    })
+}
+
+/// Splits `Title (Author)` into its parts. Titles can contain parentheses
+/// themselves, so only the last group is taken as the author.
+fn parse_title_line(line: &str) -> (String, Option<String>) {
+    if let Some(stripped) = line.strip_suffix(')')
+        && let Some(idx) = stripped.rfind('(')
+    {
+        let title = stripped[..idx].trim();
+        let author = stripped[idx + 1..].trim();
+        if !title.is_empty() && !author.is_empty() {
+            return (title.to_string(), Some(author.to_string()));
+        }
+    }
+    (line.to_string(), None)
+}
+
+fn parse_kind(description: &str) -> Option<HighlightKind> {
+    let description = description.to_lowercase();
+    if description.contains("highlight") {
+        Some(HighlightKind::Highlight)
+    } else if description.contains("note") {
+        Some(HighlightKind::Note)
+    } else if description.contains("bookmark") {
+        Some(HighlightKind::Bookmark)
+    } else {
+        None
+    }
+}
+
+/// Parses dates like `Sunday, March 3, 2019 10:00:00 PM`. Kindles don't record
+/// a timezone, so the time is taken as UTC.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    const FORMATS: [&str; 3] = [
+        "%A, %B %d, %Y %I:%M:%S %p",
+        "%A, %d %B %Y %H:%M:%S",
+        "%A, %B %d, %Y %H:%M:%S",
+    ];
+    FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date.trim(), format).ok())
+        .map(|date| date.and_utc())
+}
+
+/// The last location of a location or a location range, e.g. 182 for `180-182`.
+fn location_end(location: &str) -> Option<i64> {
+    location.rsplit('-').next()?.parse().ok()
+}
+
+/// Imports the clippings into the user's shelf, adding matched books that
+/// aren't on it yet.
+pub async fn import(
+    pool: &PgPool,
+    user_id: i64,
+    contents: &str,
+) -> Result<ImportReport, sqlx::Error> {
+    let mut report = ImportReport::default();
+
+    // Group the clippings per book, keeping the order of the file
+    let mut books: Vec<(String, Option<String>, Vec<Clipping>)> = Vec::new();
+    for clipping in parse(contents) {
+        match books
+            .iter_mut()
+            .find(|(title, author, _)| *title == clipping.title && *author == clipping.author)
+        {
+            Some((_, _, group)) => group.push(clipping),
+            None => books.push((
+                clipping.title.clone(),
+                clipping.author.clone(),
+                vec![clipping],
+            )),
+        }
+    }
+
+    for (title, author, group) in books {
+        let Some(book) = match_book(pool, &title, author.as_deref()).await? else {
+            report.unmatched.push(title);
+            continue;
+        };
+        if let Err(sqlx::Error::RowNotFound) = UserBook::get(pool, book.id, user_id).await {
+            UserBookBuilder::default()
+                .user_id(user_id)
+                .book_id(book.id)
+                .build()
+                .unwrap()
+                .import(pool)
+                .await?;
+        }
+
+        for mut highlight in to_highlights(user_id, book.id, group) {
+            if highlight.create_if_new(pool).await? {
+                report.imported += 1;
+            } else {
+                report.duplicates += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Turns a book's clippings into highlights, attaching notes to the highlight
+/// they were made on.
+fn to_highlights(user_id: i64, book_id: i64, clippings: Vec<Clipping>) -> Vec<Highlight> {
+    let (notes, others): (Vec<_>, Vec<_>) = clippings
+        .into_iter()
+        .partition(|c| c.kind == HighlightKind::Note);
+
+    let mut highlights: Vec<_> = others
+        .into_iter()
+        .map(|clipping| {
+            let quote = (clipping.kind == HighlightKind::Highlight && !clipping.content.is_empty())
+                .then_some(clipping.content);
+            HighlightBuilder::default()
+                .user_id(user_id)
+                .book_id(book_id)
+                .kind(clipping.kind)
+                .quote(quote)
+                .location(clipping.location)
+                .page(clipping.page)
+                .created_at(clipping.added_at.unwrap_or_else(Utc::now))
+                .build()
+                .expect("all required fields are set")
+        })
+        .collect();
+
+    for note in notes {
+        let end = note.location.as_deref().and_then(location_end);
+        let annotated = highlights.iter_mut().find(|h| {
+            h.kind == HighlightKind::Highlight
+                && h.note.is_none()
+                && end.is_some()
+                && h.location.as_deref().and_then(location_end) == end
+        });
+
+        match annotated {
+            Some(highlight) => highlight.note = Some(note.content),
+            None => highlights.push(
+                HighlightBuilder::default()
+                    .user_id(user_id)
+                    .book_id(book_id)
+                    .kind(HighlightKind::Note)
+                    .note(Some(note.content))
+                    .location(note.location)
+                    .page(note.page)
+                    .created_at(note.added_at.unwrap_or_else(Utc::now))
+                    .build()
+                    .expect("all required fields are set"),
+            ),
+        }
+    }
+
+    highlights
+}
+
+/// Finds the book the clippings belong to. Kindle titles often carry a
+/// subtitle the catalogue doesn't have, so the title before `:` is tried too.
+/// When the clipping names an author, the book's author has to match, which
+/// also decides between books sharing the title.
+async fn match_book(
+    pool: &PgPool,
+    title: &str,
+    author: Option<&str>,
+) -> Result<Option<Book>, sqlx::Error> {
+    let mut candidates = Book::find_by_title(pool, title).await?;
+    if candidates.is_empty()
+        && let Some((short_title, _)) = title.split_once(':')
+    {
+        candidates = Book::find_by_title(pool, short_title.trim()).await?;
+    }
+
+    let wanted = match author {
+        Some(author) => name_tokens(author),
+        None if candidates.len() <= 1 => return Ok(candidates.pop()),
+        None => return Ok(None),
+    };
+    let mut matching = candidates
+        .into_iter()
+        .filter(|book| name_tokens(&book.author) == wanted);
+
+    // Still ambiguous, better to not import than to import into the wrong book
+    match (matching.next(), matching.next()) {
+        (Some(book), None) => Ok(Some(book)),
+        _ => Ok(None),
+    }
+}
+
+/// Lowercased, sorted name parts, so that "Dostoevsky, Fyodor" and
+/// "Fyodor Dostoevsky" compare equal.
+fn name_tokens(name: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    tokens.sort();
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories;
+    use crate::models::activity::Activity;
+    use crate::test_utils::setup_db;
+    use chrono::TimeZone;
+
+    const CLIPPINGS: &str = "\u{feff}Crime and Punishment (Dostoevsky, Fyodor)
+- Your Highlight on page 12 | Location 180-182 | Added on Sunday, March 3, 2019 10:00:00 PM
+
+Pain and suffering are always inevitable for a large intelligence and a deep heart.
+==========
+Crime and Punishment (Dostoevsky, Fyodor)
+- Your Note on page 12 | Location 182 | Added on Sunday, March 3, 2019 10:01:00 PM
+
+Raskolnikov again
+==========
+Crime and Punishment (Dostoevsky, Fyodor)
+- Your Bookmark on page 40 | Location 610 | Added on Monday, March 4, 2019 8:15:30 AM
+
+
+==========
+Some Book (Nobody)
+- Your Highlight at location 5-6 | Added on Monday, March 4, 2019 8:15:30 AM
+
+Unmatched
+==========
+";
+
+    #[test]
+    fn parses_highlights_notes_and_bookmarks() {
+        // Act
+        let clippings = parse(CLIPPINGS);
+
+        // Assert
+        assert_eq!(4, clippings.len());
+        assert_eq!(
+            Clipping {
+                title: "Crime and Punishment".to_string(),
+                author: Some("Dostoevsky, Fyodor".to_string()),
+                kind: HighlightKind::Highlight,
+                page: Some(12),
+                location: Some("180-182".to_string()),
+                added_at: Some(Utc.with_ymd_and_hms(2019, 3, 3, 22, 0, 0).unwrap()),
+                content: "Pain and suffering are always inevitable for a large intelligence and a deep heart.".to_string(),
+            },
+            clippings[0]
+        );
+        assert_eq!(HighlightKind::Note, clippings[1].kind);
+        assert_eq!("Raskolnikov again", clippings[1].content);
+        assert_eq!(HighlightKind::Bookmark, clippings[2].kind);
+        assert_eq!(Some(40), clippings[2].page);
+        assert_eq!("", clippings[2].content);
+        assert_eq!(None, clippings[3].page);
+        assert_eq!(Some("5-6".to_string()), clippings[3].location);
+    }
+
+    #[test]
+    fn positions_survive_lowercasing_that_changes_lengths() {
+        // Act
+        let clippings = parse(
+            "İstanbul (Orhan Pamuk)
+- İşaretiniz Your Highlight on page 12 | Location 180-182 | Added on Sunday, March 3, 2019 10:00:00 PM
+
+Hüzün
+==========
+",
+        );
+
+        // Assert
+        assert_eq!(Some(12), clippings[0].page);
+        assert_eq!(Some("180-182".to_string()), clippings[0].location);
+    }
+
+    #[test]
+    fn title_keeps_inner_parentheses() {
+        // Act
+        let (title, author) = parse_title_line("Dune (Dune Chronicles, Book 1) (Frank Herbert)");
+
+        // Assert
+        assert_eq!("Dune (Dune Chronicles, Book 1)", title);
+        assert_eq!(Some("Frank Herbert".to_string()), author);
+    }
+
+    #[tokio::test]
+    async fn books_by_another_author_are_not_matched() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut book = factories::fake_book();
+        book.title = format!("Dune {}", book.isbn);
+        book.author = "Frank Herbert".to_string();
+        book.create(&pool).await.unwrap();
+
+        // Act
+        let other_author = match_book(&pool, &book.title, Some("Brian Herbert"))
+            .await
+            .unwrap();
+        let same_author = match_book(&pool, &book.title, Some("Herbert, Frank"))
+            .await
+            .unwrap();
+        let no_author = match_book(&pool, &book.title, None).await.unwrap();
+
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(None, other_author);
+        assert_eq!(Some(book.id), same_author.map(|b| b.id));
+        assert_eq!(Some(book.id), no_author.map(|b| b.id));
+    }
+
+    #[tokio::test]
+    async fn import_attaches_notes_and_deduplicates() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = factories::fake_book();
+        book.title = format!("Crime and Punishment {}", book.isbn);
+        book.author = "Fyodor Dostoevsky".to_string();
+        book.create(&pool).await.unwrap();
+        let contents = CLIPPINGS.replace(
+            "Crime and Punishment (",
+            &format!("{}: A Novel in Six Parts (", book.title),
+        );
+
+        // Act
+        let first = import(&pool, user.id, &contents).await.unwrap();
+        let second = import(&pool, user.id, &contents).await.unwrap();
+        let highlights = Highlight::for_user_book(&pool, user.id, book.id, user.id)
+            .await
+            .unwrap();
+        let shelved = UserBook::get(&pool, book.id, user.id).await;
+        let activities = Activity::for_user(&pool, user.id, user.id).await.unwrap();
+
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(2, first.imported);
+        assert_eq!(0, first.duplicates);
+        assert_eq!(vec!["Some Book".to_string()], first.unmatched);
+        assert_eq!(0, second.imported);
+        assert_eq!(2, second.duplicates);
+        assert!(shelved.is_ok());
+        assert!(activities.is_empty());
+
+        assert_eq!(2, highlights.len());
+        assert_eq!(HighlightKind::Highlight, highlights[0].kind);
+        assert_eq!(Some("Raskolnikov again".to_string()), highlights[0].note);
+        assert_eq!(HighlightKind::Bookmark, highlights[1].kind);
+        assert_eq!(Some(40), highlights[1].page);
+    }
+}
@@ -0,0 +1,103 @@
+//! Importers bringing data from other reading tools into the bookshelf.
+pub mod calibre;
+pub mod epub;
+pub mod kindle;
+pub mod open_library_dump;
+
+/// Strips hyphens and spaces from an ISBN, returning `None` if what is left
+/// isn't an ISBN-10 or ISBN-13.
+pub fn normalize_isbn(isbn: &str) -> Option<String> {
+    let isbn: String = isbn
+        .trim()
+        .trim_start_matches("urn:isbn:")
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .collect::<String>()
+        .to_uppercase();
+
+    // Checked first so the slicing below can't split a character
+    if !isbn.is_ascii() {
+        return None;
+    }
+    let valid = match isbn.len() {
+        10 => {
+            isbn[..9].chars().all(|c| c.is_ascii_digit())
+                && isbn[9..].chars().all(|c| c.is_ascii_digit() || c == 'X')
+        }
+        13 => isbn.chars().all(|c| c.is_ascii_digit()),
+        _ => false,
+    };
+
+    valid.then_some(isbn)
+}
+
+/// Turns an HTML fragment, as used for book descriptions, into plain text.
+/// Paragraph and line breaks become newlines and common entities are decoded.
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim_start_matches('/');
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        if matches!(name.as_str(), "p" | "br" | "div" | "li") && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_isbns() {
+        assert_eq!(
+            Some("9780307829603".to_string()),
+            normalize_isbn("978-0-307-82960-3")
+        );
+        assert_eq!(
+            Some("080442957X".to_string()),
+            normalize_isbn("urn:isbn:0-8044-2957-x")
+        );
+        assert_eq!(None, normalize_isbn("B00K1D2345"));
+        assert_eq!(None, normalize_isbn("12345"));
+        assert_eq!(None, normalize_isbn("12345678é"));
+    }
+
+    #[test]
+    fn strips_html() {
+        assert_eq!(
+            "A dark & dramatic novel.\nSecond paragraph",
+            strip_html(
+                "<div><p>A <b>dark</b> &amp; dramatic novel.</p><p>Second paragraph</p></div>"
+            )
+        );
+    }
+}
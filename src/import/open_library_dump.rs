@@ -0,0 +1,573 @@
+//! Loads Open Library's editions and works dumps into local lookup tables and
+//! enriches books from them, for deployments without network access.
+//!
+//! The dumps (https://openlibrary.org/developers/dumps) are tab separated
+//! files, usually gzipped, with one record per line: type, key, revision, last
+//! modified and the record as JSON. They run to tens of gigabytes, so they are
+//! read line by line on a blocking thread and written to the database in
+//! batches.
+use crate::import::normalize_isbn;
+use crate::metadata::open_library::{COVERS_URL, description};
+use flate2::read::MultiGzDecoder;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+/// Rows written to the database per statement.
+pub const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Io(e) => write!(f, "could not read dump: {e}"),
+            DumpError::Database(e) => write!(f, "could not store dump: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> Self {
+        DumpError::Io(e)
+    }
+}
+
+impl From<sqlx::Error> for DumpError {
+    fn from(e: sqlx::Error) -> Self {
+        DumpError::Database(e)
+    }
+}
+
+/// How far loading a dump has come.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub lines: u64,
+    /// Rows written to the lookup table.
+    pub loaded: u64,
+    /// Lines that aren't a record of the expected type or don't parse.
+    pub skipped: u64,
+    /// Bytes read from the file, which for a gzipped dump are compressed bytes.
+    pub bytes_read: u64,
+    pub total_bytes: Option<u64>,
+}
+
+impl Progress {
+    pub fn percent(&self) -> Option<f64> {
+        self.total_bytes
+            .filter(|total| *total > 0)
+            .map(|total| self.bytes_read as f64 / total as f64 * 100.0)
+    }
+
+    /// The progress of reading, with the rows loaded and the records skipped
+    /// by the loader added.
+    fn loading(self, loaded: u64, skipped: u64) -> Self {
+        Progress {
+            loaded,
+            skipped: self.skipped + skipped,
+            ..self
+        }
+    }
+}
+
+/// Records read from a dump, with how far reading had come after the last.
+struct Chunk {
+    records: Vec<(String, Value)>,
+    progress: Progress,
+}
+
+/// A dump being read.
+pub struct Dump {
+    reader: Box<dyn BufRead + Send>,
+    bytes_read: Arc<AtomicU64>,
+    total_bytes: Option<u64>,
+    line: Vec<u8>,
+}
+
+impl Dump {
+    /// Opens a dump, decompressing it if the file name ends with `.gz`.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let gzipped = path.extension().is_some_and(|ext| ext == "gz");
+        Ok(Self::from_reader(file, Some(size), gzipped))
+    }
+
+    pub fn from_reader(
+        reader: impl Read + Send + 'static,
+        total_bytes: Option<u64>,
+        gzipped: bool,
+    ) -> Self {
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        let counted = CountingReader {
+            inner: reader,
+            count: bytes_read.clone(),
+        };
+        let reader: Box<dyn BufRead + Send> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(counted)))
+        } else {
+            Box::new(BufReader::new(counted))
+        };
+
+        Dump {
+            reader,
+            bytes_read,
+            total_bytes,
+            line: Vec::new(),
+        }
+    }
+
+    /// Reads the records of type `record_type` on a blocking thread, so
+    /// reading and decompressing don't hold up the async runtime, and hands
+    /// them over in chunks of `BATCH_SIZE`. Reading stops when the receiver
+    /// is dropped.
+    fn spawn_reader(mut self, record_type: &'static str) -> mpsc::Receiver<io::Result<Chunk>> {
+        let (tx, rx) = mpsc::channel(2);
+        tokio::task::spawn_blocking(move || {
+            let mut progress = Progress::default();
+            loop {
+                let mut records = Vec::with_capacity(BATCH_SIZE);
+                let done = loop {
+                    match self.next_record(record_type, &mut progress) {
+                        Ok(Some(record)) => {
+                            records.push(record);
+                            if records.len() >= BATCH_SIZE {
+                                break false;
+                            }
+                        }
+                        Ok(None) => break true,
+                        Err(e) => {
+                            tx.blocking_send(Err(e)).ok();
+                            return;
+                        }
+                    }
+                };
+                let chunk = Chunk { records, progress };
+                if tx.blocking_send(Ok(chunk)).is_err() || done {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+
+    /// The next record of type `record_type` as its key and JSON.
+    fn next_record(
+        &mut self,
+        record_type: &str,
+        progress: &mut Progress,
+    ) -> io::Result<Option<(String, Value)>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+            progress.lines += 1;
+            progress.bytes_read = self.bytes_read.load(Ordering::Relaxed);
+            progress.total_bytes = self.total_bytes;
+
+            let record = std::str::from_utf8(&self.line).ok().and_then(|line| {
+                let mut columns = line.trim_end().splitn(5, '\t');
+                let (kind, key) = (columns.next()?, columns.next()?);
+                let json = columns.nth(2)?;
+                (kind == record_type)
+                    .then(|| serde_json::from_str(json).ok())
+                    .flatten()
+                    .map(|json| (key.to_string(), json))
+            });
+            match record {
+                Some(record) => return Ok(Some(record)),
+                None => progress.skipped += 1,
+            }
+        }
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+struct EditionRow {
+    edition_key: String,
+    work_key: Option<String>,
+    description: Option<String>,
+    cover_id: Option<i64>,
+    pages: Option<i32>,
+}
+
+struct WorkRow {
+    description: Option<String>,
+    cover_id: Option<i64>,
+}
+
+/// Loads an editions dump. Editions without an ISBN are skipped, and a dump
+/// loaded again replaces the rows it loaded before. `progress` is called after
+/// every batch.
+pub async fn load_editions(
+    pool: &PgPool,
+    dump: Dump,
+    mut progress: impl FnMut(&Progress),
+) -> Result<Progress, DumpError> {
+    let mut chunks = dump.spawn_reader("/type/edition");
+    let mut read = Progress::default();
+    let (mut loaded, mut skipped) = (0, 0);
+    // Keyed by ISBN, as a statement can't insert the same ISBN twice
+    let mut batch: HashMap<String, EditionRow> = HashMap::new();
+
+    while let Some(chunk) = chunks.recv().await {
+        let chunk = chunk?;
+        read = chunk.progress;
+        for (key, edition) in chunk.records {
+            let isbns: Vec<String> = ["isbn_13", "isbn_10"]
+                .iter()
+                .filter_map(|field| edition[field].as_array())
+                .flatten()
+                .filter_map(|isbn| normalize_isbn(isbn.as_str()?))
+                .collect();
+            if isbns.is_empty() {
+                skipped += 1;
+                continue;
+            }
+
+            for isbn in isbns {
+                batch.insert(
+                    isbn,
+                    EditionRow {
+                        edition_key: key.clone(),
+                        work_key: edition["works"][0]["key"].as_str().map(str::to_string),
+                        description: description(&edition["description"]),
+                        cover_id: cover_id(&edition),
+                        pages: edition["number_of_pages"]
+                            .as_i64()
+                            .filter(|pages| (1..=100_000).contains(pages))
+                            .map(|pages| pages as i32),
+                    },
+                );
+            }
+
+            if batch.len() >= BATCH_SIZE {
+                loaded += insert_editions(pool, &mut batch).await?;
+                progress(&read.loading(loaded, skipped));
+            }
+        }
+    }
+
+    loaded += insert_editions(pool, &mut batch).await?;
+    let state = read.loading(loaded, skipped);
+    progress(&state);
+    Ok(state)
+}
+
+/// Loads a works dump, whose descriptions and covers are used for editions
+/// that lack their own.
+pub async fn load_works(
+    pool: &PgPool,
+    dump: Dump,
+    mut progress: impl FnMut(&Progress),
+) -> Result<Progress, DumpError> {
+    let mut chunks = dump.spawn_reader("/type/work");
+    let mut read = Progress::default();
+    let (mut loaded, mut skipped) = (0, 0);
+    let mut batch: HashMap<String, WorkRow> = HashMap::new();
+
+    while let Some(chunk) = chunks.recv().await {
+        let chunk = chunk?;
+        read = chunk.progress;
+        for (key, work) in chunk.records {
+            let row = WorkRow {
+                description: description(&work["description"]),
+                cover_id: cover_id(&work),
+            };
+            // Works with neither are of no use for enrichment
+            if row.description.is_none() && row.cover_id.is_none() {
+                skipped += 1;
+                continue;
+            }
+            batch.insert(key, row);
+
+            if batch.len() >= BATCH_SIZE {
+                loaded += insert_works(pool, &mut batch).await?;
+                progress(&read.loading(loaded, skipped));
+            }
+        }
+    }
+
+    loaded += insert_works(pool, &mut batch).await?;
+    let state = read.loading(loaded, skipped);
+    progress(&state);
+    Ok(state)
+}
+
+/// Fills in missing descriptions, covers and page counts of books from the
+/// loaded dumps, matching on ISBN. Returns the number of books changed.
+pub async fn enrich_books(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE books b
+        SET description = COALESCE(b.description, e.description, w.description),
+            cover_url = COALESCE(b.cover_url, $1 || '/b/id/' || COALESCE(e.cover_id, w.cover_id) || '-L.jpg'),
+            pages = COALESCE(b.pages, e.pages)
+        FROM openlibrary_editions e
+        LEFT JOIN openlibrary_works w ON w.key = e.work_key
+        WHERE e.isbn = regexp_replace(upper(b.isbn), '[^0-9X]', '', 'g')
+          AND ((b.description IS NULL AND COALESCE(e.description, w.description) IS NOT NULL)
+            OR (b.cover_url IS NULL AND COALESCE(e.cover_id, w.cover_id) IS NOT NULL)
+            OR (b.pages IS NULL AND e.pages IS NOT NULL))
+        "#,
+        COVERS_URL
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// The first cover, negative IDs mark deleted covers.
+fn cover_id(record: &Value) -> Option<i64> {
+    record["covers"][0].as_i64().filter(|id| *id > 0)
+}
+
+async fn insert_editions(
+    pool: &PgPool,
+    batch: &mut HashMap<String, EditionRow>,
+) -> Result<u64, sqlx::Error> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    let mut isbns = Vec::with_capacity(batch.len());
+    let mut edition_keys = Vec::with_capacity(batch.len());
+    let mut work_keys = Vec::with_capacity(batch.len());
+    let mut descriptions = Vec::with_capacity(batch.len());
+    let mut cover_ids = Vec::with_capacity(batch.len());
+    let mut pages = Vec::with_capacity(batch.len());
+    for (isbn, row) in batch.drain() {
+        isbns.push(isbn);
+        edition_keys.push(row.edition_key);
+        work_keys.push(row.work_key);
+        descriptions.push(row.description);
+        cover_ids.push(row.cover_id);
+        pages.push(row.pages);
+    }
+
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO openlibrary_editions (isbn, edition_key, work_key, description, cover_id, pages)
+        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::bigint[], $6::integer[])
+        ON CONFLICT (isbn) DO UPDATE
+        SET edition_key = EXCLUDED.edition_key,
+            work_key = EXCLUDED.work_key,
+            description = EXCLUDED.description,
+            cover_id = EXCLUDED.cover_id,
+            pages = EXCLUDED.pages
+        "#,
+        &isbns,
+        &edition_keys,
+        &work_keys as &[Option<String>],
+        &descriptions as &[Option<String>],
+        &cover_ids as &[Option<i64>],
+        &pages as &[Option<i32>]
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+async fn insert_works(
+    pool: &PgPool,
+    batch: &mut HashMap<String, WorkRow>,
+) -> Result<u64, sqlx::Error> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    let mut keys = Vec::with_capacity(batch.len());
+    let mut descriptions = Vec::with_capacity(batch.len());
+    let mut cover_ids = Vec::with_capacity(batch.len());
+    for (key, row) in batch.drain() {
+        keys.push(key);
+        descriptions.push(row.description);
+        cover_ids.push(row.cover_id);
+    }
+
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO openlibrary_works (key, description, cover_id)
+        SELECT * FROM UNNEST($1::text[], $2::text[], $3::bigint[])
+        ON CONFLICT (key) DO UPDATE
+        SET description = EXCLUDED.description,
+            cover_id = EXCLUDED.cover_id
+        "#,
+        &keys,
+        &descriptions as &[Option<String>],
+        &cover_ids as &[Option<i64>]
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::fake_book;
+    use crate::models::book::Book;
+    use crate::test_utils::setup_db;
+    use fake::Fake;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{Cursor, Write};
+
+    fn gzip(contents: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn reads_gzipped_dump_with_progress() {
+        // Arrange
+        let contents = "/type/edition\t/books/OL1M\t3\t2020-01-01T00:00:00\t{\"title\": \"A\"}\n\
+                        /type/work\t/works/OL1W\t1\t2020-01-01T00:00:00\t{\"title\": \"A\"}\n\
+                        /type/edition\t/books/OL2M\t1\t2020-01-01T00:00:00\tnot json\n\
+                        /type/edition\t/books/OL3M\t1\t2020-01-01T00:00:00\t{\"title\": \"B\"}\n";
+        let gzipped = gzip(contents);
+        let size = gzipped.len() as u64;
+        let mut dump = Dump::from_reader(Cursor::new(gzipped), Some(size), true);
+        let mut progress = Progress::default();
+
+        // Act
+        let mut keys = Vec::new();
+        while let Some((key, _)) = dump.next_record("/type/edition", &mut progress).unwrap() {
+            keys.push(key);
+        }
+
+        // Assert
+        assert_eq!(vec!["/books/OL1M", "/books/OL3M"], keys);
+        assert_eq!(4, progress.lines);
+        assert_eq!(2, progress.skipped);
+        assert_eq!(Some(100.0), progress.percent());
+    }
+
+    #[tokio::test]
+    async fn reader_hands_records_over_in_chunks() {
+        // Arrange
+        let contents: String = (0..BATCH_SIZE + 1)
+            .map(|i| format!("/type/work\t/works/OL{i}W\t1\t2020-01-01\t{{}}\n"))
+            .collect();
+        let dump = Dump::from_reader(Cursor::new(contents), None, false);
+
+        // Act
+        let mut chunks = dump.spawn_reader("/type/work");
+        let mut sizes = Vec::new();
+        let mut last = Progress::default();
+        while let Some(chunk) = chunks.recv().await {
+            let chunk = chunk.unwrap();
+            sizes.push(chunk.records.len());
+            last = chunk.progress;
+        }
+
+        // Assert
+        assert_eq!(vec![BATCH_SIZE, 1], sizes);
+        assert_eq!(BATCH_SIZE as u64 + 1, last.lines);
+    }
+
+    #[tokio::test]
+    async fn enriches_books_from_dumps() {
+        // Arrange
+        let pool = setup_db().await;
+        let isbn13 = format!("978{:010}", (0..10_000_000_000i64).fake::<i64>());
+        let isbn10 = format!("{:09}X", (0..1_000_000_000i64).fake::<i64>());
+        let work_key = format!("/works/OL{}W", (0..u32::MAX).fake::<u32>());
+
+        let mut bare = fake_book();
+        bare.isbn = format!("{}-{}", &isbn13[..3], &isbn13[3..]);
+        bare.description = None;
+        bare.cover_url = None;
+        bare.pages = None;
+        bare.create(&pool).await.unwrap();
+        let mut complete = fake_book();
+        complete.isbn = isbn10.clone();
+        complete.create(&pool).await.unwrap();
+
+        let editions = format!(
+            "/type/edition\t/books/OL1M\t1\t2020-01-01\t{{\"isbn_13\": [\"{isbn13}\"], \"works\": [{{\"key\": \"{work_key}\"}}], \"number_of_pages\": 321}}\n\
+             /type/edition\t/books/OL2M\t1\t2020-01-01\t{{\"isbn_10\": [\"{isbn10}\"], \"description\": \"Other\", \"covers\": [5], \"number_of_pages\": 99}}\n\
+             /type/edition\t/books/OL3M\t1\t2020-01-01\t{{\"title\": \"No ISBN\"}}\n"
+        );
+        let works = format!(
+            "/type/work\t{work_key}\t1\t2020-01-01\t{{\"description\": {{\"type\": \"/type/text\", \"value\": \"From the work\"}}, \"covers\": [42]}}\n"
+        );
+        let mut reports = 0;
+
+        // Act
+        let edition_progress = load_editions(
+            &pool,
+            Dump::from_reader(Cursor::new(editions), None, false),
+            |_| reports += 1,
+        )
+        .await
+        .unwrap();
+        load_works(
+            &pool,
+            Dump::from_reader(Cursor::new(works), None, false),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        enrich_books(&pool).await.unwrap();
+        let bare_after = Book::get(&pool, bare.id).await.unwrap();
+        let complete_after = Book::get(&pool, complete.id).await.unwrap();
+
+        // Assert
+        assert_eq!(2, edition_progress.loaded);
+        assert_eq!(1, edition_progress.skipped);
+        assert_eq!(1, reports);
+        assert_eq!(Some("From the work"), bare_after.description.as_deref());
+        assert_eq!(
+            Some("https://covers.openlibrary.org/b/id/42-L.jpg"),
+            bare_after.cover_url.as_deref()
+        );
+        assert_eq!(Some(321), bare_after.pages);
+        assert_eq!(complete, complete_after);
+
+        // Cleanup
+        bare.delete(&pool).await.unwrap();
+        complete.delete(&pool).await.unwrap();
+        sqlx::query!(
+            "DELETE FROM openlibrary_editions WHERE isbn = ANY($1)",
+            &[isbn13, isbn10]
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query!("DELETE FROM openlibrary_works WHERE key = $1", work_key)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
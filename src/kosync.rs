@@ -0,0 +1,440 @@
+//! HTTP endpoints implementing the KOReader sync server protocol (kosync).
+//!
+//! Devices register with the email of their bookshelf user and that user's
+//! password, after which any progress reported for a document that is linked
+//! to a book through `book_documents` also moves the user's `UserBook` along.
+use crate::models::book::Book;
+use crate::models::book_document::BookDocument;
+use crate::models::kosync::{KosyncAccount, KosyncProgress};
+use crate::models::user::User;
+use crate::models::user_book::{Progress, UserBook};
+use crate::server::ServerError;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Extension, Json, Router};
+use chrono::Utc;
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+pub enum KosyncError {
+    Unauthorized,
+    UserExists,
+    InvalidRequest,
+    DocumentMissing,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for KosyncError {
+    fn from(e: sqlx::Error) -> Self {
+        KosyncError::Database(e)
+    }
+}
+
+impl IntoResponse for KosyncError {
+    fn into_response(self) -> Response {
+        // Codes and messages follow the reference koreader-sync-server
+        let (status, code, message) = match &self {
+            KosyncError::Unauthorized => (StatusCode::UNAUTHORIZED, 2001, "Unauthorized"),
+            KosyncError::UserExists => (
+                StatusCode::PAYMENT_REQUIRED,
+                2002,
+                "Username is already registered.",
+            ),
+            KosyncError::InvalidRequest => (StatusCode::FORBIDDEN, 2003, "Invalid request"),
+            KosyncError::DocumentMissing => (
+                StatusCode::FORBIDDEN,
+                2004,
+                "Field 'document' not provided.",
+            ),
+            KosyncError::Database(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                2000,
+                "Unknown server error.",
+            ),
+        };
+
+        let body = Json(json!({ "code": code, "message": message }));
+        match self {
+            KosyncError::Database(e) => {
+                let error = ServerError::new("kosync: database error", e);
+                (status, Extension(error), body).into_response()
+            }
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+/// Builds the kosync router. Serve it with `axum::serve`.
+pub fn router(pool: PgPool) -> Router {
+    Router::new()
+        .route("/healthcheck", get(healthcheck))
+        .route("/users/create", post(register))
+        .route("/users/auth", get(authorize))
+        .route("/syncs/progress", put(update_progress))
+        .route("/syncs/progress/{document}", get(get_progress))
+        .with_state(pool)
+}
+
+#[derive(Deserialize)]
+struct Registration {
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct ProgressUpdate {
+    document: Option<String>,
+    progress: String,
+    percentage: f64,
+    device: String,
+    device_id: String,
+}
+
+async fn healthcheck() -> Json<Value> {
+    Json(json!({ "state": "OK" }))
+}
+
+/// Registers a device account. KOReader sends the MD5 of the password, so the
+/// account is only created if it matches the password of the user with the
+/// email given as username.
+async fn register(State(pool): State<PgPool>, body: Bytes) -> Result<Response, KosyncError> {
+    let registration: Registration =
+        serde_json::from_slice(&body).map_err(|_| KosyncError::InvalidRequest)?;
+    if registration.username.is_empty() || registration.password.is_empty() {
+        return Err(KosyncError::InvalidRequest);
+    }
+
+    let user = match User::get_by_email(&pool, &registration.username).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Err(KosyncError::Unauthorized),
+        Err(e) => return Err(e.into()),
+    };
+    let expected_key = hex::encode(Md5::digest(user.password.as_bytes()));
+    if !expected_key.eq_ignore_ascii_case(&registration.password) {
+        return Err(KosyncError::Unauthorized);
+    }
+
+    let account = KosyncAccount {
+        user_id: user.id,
+        username: registration.username,
+        userkey: registration.password.to_lowercase(),
+    };
+    match account.create(&pool).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(KosyncError::UserExists);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "username": account.username })),
+    )
+        .into_response())
+}
+
+/// Resolves the account from the `x-auth-user` and `x-auth-key` headers.
+async fn authenticate(pool: &PgPool, headers: &HeaderMap) -> Result<KosyncAccount, KosyncError> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or(KosyncError::Unauthorized)
+    };
+    let username = header("x-auth-user")?;
+    let userkey = header("x-auth-key")?.to_lowercase();
+
+    KosyncAccount::authorize(pool, username, &userkey)
+        .await?
+        .ok_or(KosyncError::Unauthorized)
+}
+
+async fn authorize(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, KosyncError> {
+    authenticate(&pool, &headers).await?;
+    Ok(Json(json!({ "authorized": "OK" })))
+}
+
+async fn update_progress(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, KosyncError> {
+    let account = authenticate(&pool, &headers).await?;
+    let update: ProgressUpdate =
+        serde_json::from_slice(&body).map_err(|_| KosyncError::InvalidRequest)?;
+    let document = match update.document {
+        Some(document) if !document.is_empty() => document,
+        _ => return Err(KosyncError::DocumentMissing),
+    };
+
+    let progress = KosyncProgress {
+        user_id: account.user_id,
+        document,
+        progress: update.progress,
+        percentage: update.percentage,
+        device: update.device,
+        device_id: update.device_id,
+        updated_at: Utc::now(),
+    };
+    progress.save(&pool).await?;
+
+    match BookDocument::get(&pool, &progress.document).await {
+        Ok(book_document) => apply_to_shelf(&pool, &progress, book_document.book_id).await?,
+        // Documents nobody has linked to a book are only synced between devices
+        Err(sqlx::Error::RowNotFound) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(Json(json!({
+        "document": progress.document,
+        "timestamp": progress.updated_at.timestamp(),
+    })))
+}
+
+async fn get_progress(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Path(document): Path<String>,
+) -> Result<Json<Value>, KosyncError> {
+    let account = authenticate(&pool, &headers).await?;
+
+    // The protocol answers an empty object when nothing has been synced yet
+    let Some(progress) = KosyncProgress::get(&pool, account.user_id, &document).await? else {
+        return Ok(Json(json!({})));
+    };
+
+    Ok(Json(json!({
+        "document": progress.document,
+        "progress": progress.progress,
+        "percentage": progress.percentage,
+        "device": progress.device,
+        "device_id": progress.device_id,
+        "timestamp": progress.updated_at.timestamp(),
+    })))
+}
+
+/// Moves the user's shelf entry for the book to the synced position, adding
+/// the book to the shelf if it isn't there yet.
+async fn apply_to_shelf(
+    pool: &PgPool,
+    progress: &KosyncProgress,
+    book_id: i64,
+) -> Result<(), sqlx::Error> {
+    let book = Book::get(pool, book_id).await?;
+    let percent = progress.percentage.clamp(0.0, 1.0) * 100.0;
+
+    let mut user_book = UserBook::get_or_create(pool, book_id, progress.user_id).await?;
+    user_book.set_progress(Progress::Percent(percent), &book);
+    user_book.update(pool).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories;
+    use crate::models::user_book::ReadingStatus;
+    use crate::test_utils::setup_db;
+    use tokio::net::TcpListener;
+
+    /// Serves the router on a random local port and returns its base URL.
+    async fn spawn_server(pool: PgPool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(pool)).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn userkey(password: &str) -> String {
+        hex::encode(Md5::digest(password.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn register_then_authorize() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let base = spawn_server(pool.clone()).await;
+        let client = reqwest::Client::new();
+        let key = userkey(&user.password);
+
+        // Act
+        let wrong_password = client
+            .post(format!("{base}/users/create"))
+            .json(&json!({ "username": user.email, "password": userkey("wrong") }))
+            .send()
+            .await
+            .unwrap();
+        let registered = client
+            .post(format!("{base}/users/create"))
+            .json(&json!({ "username": user.email, "password": key }))
+            .send()
+            .await
+            .unwrap();
+        let registered_again = client
+            .post(format!("{base}/users/create"))
+            .json(&json!({ "username": user.email, "password": key }))
+            .send()
+            .await
+            .unwrap();
+        let authorized = client
+            .get(format!("{base}/users/auth"))
+            .header("x-auth-user", &user.email)
+            .header("x-auth-key", &key)
+            .send()
+            .await
+            .unwrap();
+        let unauthorized = client
+            .get(format!("{base}/users/auth"))
+            .header("x-auth-user", &user.email)
+            .header("x-auth-key", userkey("wrong"))
+            .send()
+            .await
+            .unwrap();
+
+        user.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(StatusCode::UNAUTHORIZED, wrong_password.status());
+        assert_eq!(StatusCode::CREATED, registered.status());
+        assert_eq!(StatusCode::PAYMENT_REQUIRED, registered_again.status());
+        let body: Value = registered_again.json().await.unwrap();
+        assert_eq!(2002, body["code"]);
+        assert_eq!(StatusCode::OK, authorized.status());
+        assert_eq!(StatusCode::UNAUTHORIZED, unauthorized.status());
+    }
+
+    #[tokio::test]
+    async fn progress_is_synced_and_moves_user_book() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = factories::fake_book();
+        book.pages = Some(200);
+        book.create(&pool).await.unwrap();
+        let document = BookDocument::new(format!("doc-{}", book.isbn), book.id);
+        document.create(&pool).await.unwrap();
+        let account = KosyncAccount {
+            user_id: user.id,
+            username: user.email.clone(),
+            userkey: userkey(&user.password),
+        };
+        account.create(&pool).await.unwrap();
+
+        let base = spawn_server(pool.clone()).await;
+        let client = reqwest::Client::new();
+        let authed = |request: reqwest::RequestBuilder| {
+            request
+                .header("x-auth-user", &account.username)
+                .header("x-auth-key", &account.userkey)
+        };
+
+        // Act
+        let before = authed(client.get(format!("{base}/syncs/progress/{}", document.document)))
+            .send()
+            .await
+            .unwrap();
+        let before: Value = before.json().await.unwrap();
+        let put = authed(client.put(format!("{base}/syncs/progress")))
+            .json(&json!({
+                "document": document.document,
+                "progress": "/body/DocFragment[12]/body/p[3]/text().0",
+                "percentage": 0.25,
+                "device": "Kobo",
+                "device_id": "ABC",
+            }))
+            .send()
+            .await
+            .unwrap();
+        let put_status = put.status();
+        let after = authed(client.get(format!("{base}/syncs/progress/{}", document.document)))
+            .send()
+            .await
+            .unwrap();
+        let after: Value = after.json().await.unwrap();
+        let reading = UserBook::get(&pool, book.id, user.id).await.unwrap();
+
+        authed(client.put(format!("{base}/syncs/progress")))
+            .json(&json!({
+                "document": document.document,
+                "progress": "/body/DocFragment[40]/body/p[1]/text().0",
+                "percentage": 1.0,
+                "device": "Kobo",
+                "device_id": "ABC",
+            }))
+            .send()
+            .await
+            .unwrap();
+        let finished = UserBook::get(&pool, book.id, user.id).await.unwrap();
+
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(json!({}), before);
+        assert_eq!(StatusCode::OK, put_status);
+        assert_eq!(0.25, after["percentage"]);
+        assert_eq!(
+            "/body/DocFragment[12]/body/p[3]/text().0",
+            after["progress"]
+        );
+        assert_eq!("Kobo", after["device"]);
+
+        assert_eq!(Some(50), reading.current_page);
+        assert_eq!(ReadingStatus::Reading, reading.status);
+        assert!(reading.began_reading.is_some());
+
+        assert_eq!(Some(200), finished.current_page);
+        assert_eq!(ReadingStatus::Completed, finished.status);
+        assert!(finished.done_reading.is_some());
+    }
+
+    #[tokio::test]
+    async fn progress_requires_document() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let account = KosyncAccount {
+            user_id: user.id,
+            username: user.email.clone(),
+            userkey: userkey(&user.password),
+        };
+        account.create(&pool).await.unwrap();
+        let base = spawn_server(pool.clone()).await;
+
+        // Act
+        let response = reqwest::Client::new()
+            .put(format!("{base}/syncs/progress"))
+            .header("x-auth-user", &account.username)
+            .header("x-auth-key", &account.userkey)
+            .json(&json!({
+                "progress": "",
+                "percentage": 0.5,
+                "device": "Kobo",
+                "device_id": "ABC",
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        user.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(2004, body["code"]);
+    }
+}
@@ -1,6 +1,19 @@
+pub mod achievements;
+pub mod covers;
 pub mod db;
+pub mod duplicates;
+pub mod export;
 pub mod factories;
+pub mod import;
+pub mod kosync;
+pub mod marc;
+pub mod metadata;
 pub mod models;
+pub mod recommendations;
+pub mod reminders;
 pub mod seed;
+pub mod server;
+pub mod similar;
+pub mod streaks;
 #[cfg(test)]
 pub mod test_utils;
@@ -0,0 +1,279 @@
+//! Binary MARC 21 records as specified by ISO 2709.
+//!
+//! A record is a 24 byte leader, a directory with a 12 byte entry (tag,
+//! length and offset) per field, and the fields themselves. All lengths and
+//! offsets are in bytes, and the record is encoded as UTF-8.
+use crate::marc::{Field, MarcError, Record};
+
+const SUBFIELD_DELIMITER: u8 = 0x1F;
+const FIELD_TERMINATOR: u8 = 0x1E;
+const RECORD_TERMINATOR: u8 = 0x1D;
+const LEADER_LENGTH: usize = 24;
+/// Longest field and record the 4 and 5 digit lengths can describe.
+const MAX_FIELD_LENGTH: usize = 9_999;
+const MAX_RECORD_LENGTH: usize = 99_999;
+
+/// Writes the records one after another. Fails if a record or field is
+/// longer than the format allows.
+pub fn write(records: &[Record]) -> Result<Vec<u8>, MarcError> {
+    let mut out = Vec::new();
+    for record in records {
+        write_record(record, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn write_record(record: &Record, out: &mut Vec<u8>) -> Result<(), MarcError> {
+    let mut directory = Vec::with_capacity(record.fields.len() * 12 + 1);
+    let mut data = Vec::new();
+
+    for field in &record.fields {
+        let start = data.len();
+        match field {
+            Field::Control { value, .. } => data.extend_from_slice(value.as_bytes()),
+            Field::Data {
+                ind1,
+                ind2,
+                subfields,
+                ..
+            } => {
+                data.extend_from_slice(ind1.to_string().as_bytes());
+                data.extend_from_slice(ind2.to_string().as_bytes());
+                for (code, value) in subfields {
+                    data.push(SUBFIELD_DELIMITER);
+                    data.extend_from_slice(code.to_string().as_bytes());
+                    data.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+        data.push(FIELD_TERMINATOR);
+        if data.len() - start > MAX_FIELD_LENGTH || start > MAX_RECORD_LENGTH {
+            return Err(MarcError::RecordTooLong);
+        }
+        directory.extend_from_slice(
+            format!("{:0>3}{:04}{:05}", field.tag(), data.len() - start, start).as_bytes(),
+        );
+    }
+    directory.push(FIELD_TERMINATOR);
+    data.push(RECORD_TERMINATOR);
+
+    let base_address = LEADER_LENGTH + directory.len();
+    let record_length = base_address + data.len();
+    if record_length > MAX_RECORD_LENGTH {
+        return Err(MarcError::RecordTooLong);
+    }
+
+    let mut leader: Vec<u8> = format!("{:<24}", record.leader).into_bytes();
+    leader.truncate(LEADER_LENGTH);
+    leader[0..5].copy_from_slice(format!("{record_length:05}").as_bytes());
+    leader[12..17].copy_from_slice(format!("{base_address:05}").as_bytes());
+
+    out.extend_from_slice(&leader);
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&data);
+
+    Ok(())
+}
+
+/// Reads all records in `bytes`.
+pub fn read(bytes: &[u8]) -> Result<Vec<Record>, MarcError> {
+    let mut records = Vec::new();
+    let mut rest = bytes;
+
+    // Files exported on some systems end with a newline or padding
+    while rest.iter().any(|b| !b.is_ascii_whitespace()) {
+        let length =
+            number(rest.get(0..5).ok_or(MarcError::Truncated)?).ok_or(MarcError::InvalidLeader)?;
+        let record = rest.get(..length).ok_or(MarcError::Truncated)?;
+        records.push(read_record(record)?);
+        rest = &rest[length..];
+    }
+
+    Ok(records)
+}
+
+fn read_record(record: &[u8]) -> Result<Record, MarcError> {
+    if record.len() < LEADER_LENGTH {
+        return Err(MarcError::Truncated);
+    }
+    let leader =
+        std::str::from_utf8(&record[..LEADER_LENGTH]).map_err(|_| MarcError::InvalidLeader)?;
+    let base_address = number(&record[12..17]).ok_or(MarcError::InvalidLeader)?;
+    let directory = record
+        .get(LEADER_LENGTH..base_address.saturating_sub(1))
+        .ok_or(MarcError::Truncated)?;
+    if directory.len() % 12 != 0 {
+        return Err(MarcError::InvalidDirectory);
+    }
+
+    let mut fields = Vec::with_capacity(directory.len() / 12);
+    for entry in directory.chunks(12) {
+        let tag = std::str::from_utf8(&entry[0..3]).map_err(|_| MarcError::InvalidDirectory)?;
+        let length = number(&entry[3..7]).ok_or(MarcError::InvalidDirectory)?;
+        let start = number(&entry[7..12]).ok_or(MarcError::InvalidDirectory)?;
+        let data = record
+            .get(base_address + start..base_address + start + length)
+            .ok_or(MarcError::Truncated)?;
+        let data = data.strip_suffix(&[FIELD_TERMINATOR]).unwrap_or(data);
+        let data = std::str::from_utf8(data).map_err(|_| MarcError::InvalidUtf8)?;
+
+        fields.push(if tag < "010" {
+            Field::Control {
+                tag: tag.to_string(),
+                value: data.to_string(),
+            }
+        } else {
+            let mut parts = data.split(SUBFIELD_DELIMITER as char);
+            let mut indicators = parts.next().unwrap_or_default().chars();
+            Field::Data {
+                tag: tag.to_string(),
+                ind1: indicators.next().unwrap_or(' '),
+                ind2: indicators.next().unwrap_or(' '),
+                subfields: parts
+                    .filter_map(|part| {
+                        let mut chars = part.chars();
+                        let code = chars.next()?;
+                        Some((code, chars.as_str().to_string()))
+                    })
+                    .collect(),
+            }
+        });
+    }
+
+    Ok(Record {
+        leader: leader.to_string(),
+        fields,
+    })
+}
+
+fn number(digits: &[u8]) -> Option<usize> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::book::BookBuilder;
+
+    #[test]
+    fn round_trips_books() {
+        // Arrange
+        let books = [
+            BookBuilder::default()
+                .id(1)
+                .title("Crime and Punishment".to_string())
+                .author("Fyodor Dostoevsky".to_string())
+                .isbn("9780307829603".to_string())
+                .published_year(2012)
+                .description(Some(
+                    "Raskolnikov, a destitute student in St. Petersburg…".to_string(),
+                ))
+                .pages(Some(592))
+                .build()
+                .unwrap(),
+            BookBuilder::default()
+                .id(2)
+                .title("Good Omens".to_string())
+                .author("Terry Pratchett & Neil Gaiman".to_string())
+                .isbn("9780060853983".to_string())
+                .published_year(1990)
+                .pages(Some(412))
+                .build()
+                .unwrap(),
+        ];
+        let records: Vec<Record> = books.iter().map(Record::from_book).collect();
+
+        // Act
+        let bytes = write(&records).unwrap();
+        let read_back = read(&bytes).unwrap();
+        let read_books: Vec<_> = read_back
+            .iter()
+            .map(|r| r.to_book_builder().id(0).build().unwrap())
+            .collect();
+
+        // Assert
+        assert_eq!(records.len(), read_back.len());
+        assert_eq!(records[0].fields, read_back[0].fields);
+        assert_eq!(records[1].fields, read_back[1].fields);
+        for (book, read_book) in books.iter().zip(read_books) {
+            assert_eq!(book.title, read_book.title);
+            assert_eq!(book.author, read_book.author);
+            assert_eq!(book.isbn, read_book.isbn);
+            assert_eq!(book.published_year, read_book.published_year);
+            assert_eq!(book.pages, read_book.pages);
+            assert_eq!(book.description, read_book.description);
+        }
+    }
+
+    #[test]
+    fn leader_has_lengths_in_bytes() {
+        // Arrange
+        let record = Record {
+            leader: crate::marc::DEFAULT_LEADER.to_string(),
+            fields: vec![Field::Data {
+                tag: "245".to_string(),
+                ind1: '1',
+                ind2: '0',
+                subfields: vec![('a', "Öl".to_string())],
+            }],
+        };
+
+        // Act
+        let bytes = write(&[record]).unwrap();
+
+        // Assert
+        // Leader, a directory of one entry and its terminator, then the field:
+        // indicators, delimiter, code, "Öl" in 3 bytes and both terminators
+        assert_eq!(b"00046nam a2200037 i 4500245000800000", &bytes[..36]);
+        assert_eq!(24 + 13 + 9, bytes.len());
+        assert_eq!(Some(&RECORD_TERMINATOR), bytes.last());
+    }
+
+    #[test]
+    fn truncated_record_is_an_error() {
+        // Arrange
+        let bytes = write(&[Record::from_book(
+            &BookBuilder::default()
+                .title("Dune".to_string())
+                .author("Frank Herbert".to_string())
+                .isbn("9780441013593".to_string())
+                .published_year(1965)
+                .pages(Some(604))
+                .build()
+                .unwrap(),
+        )])
+        .unwrap();
+
+        // Act
+        let result = read(&bytes[..bytes.len() - 10]);
+
+        // Assert
+        assert!(matches!(result, Err(MarcError::Truncated)));
+    }
+
+    #[test]
+    fn overlong_fields_and_records_are_errors() {
+        // Arrange
+        let field = |length| Field::Data {
+            tag: "520".to_string(),
+            ind1: ' ',
+            ind2: ' ',
+            subfields: vec![('a', "x".repeat(length))],
+        };
+        let record = |fields| Record {
+            leader: crate::marc::DEFAULT_LEADER.to_string(),
+            fields,
+        };
+
+        // Act
+        let longest_field = write(&[record(vec![field(9_994)])]);
+        let long_field = write(&[record(vec![field(9_995)])]);
+        let long_record = write(&[record(vec![field(9_000); 12])]);
+
+        // Assert
+        // Indicators, delimiter, code and terminator add 5 bytes to the text
+        assert!(longest_field.is_ok());
+        assert!(matches!(long_field, Err(MarcError::RecordTooLong)));
+        assert!(matches!(long_record, Err(MarcError::RecordTooLong)));
+    }
+}
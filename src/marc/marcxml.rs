@@ -0,0 +1,211 @@
+//! MARC 21 records in the MARCXML schema of the Library of Congress.
+use crate::marc::{Field, MarcError, Record};
+use quick_xml::Reader;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+
+pub const NAMESPACE: &str = "http://www.loc.gov/MARC21/slim";
+
+/// Writes the records as a MARCXML `<collection>`.
+pub fn write(records: &[Record]) -> String {
+    let mut xml =
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<collection xmlns=\"{NAMESPACE}\">\n");
+
+    for record in records {
+        xml.push_str("  <record>\n");
+        xml.push_str(&format!(
+            "    <leader>{}</leader>\n",
+            escape(&record.leader)
+        ));
+        for field in &record.fields {
+            match field {
+                Field::Control { tag, value } => xml.push_str(&format!(
+                    "    <controlfield tag=\"{}\">{}</controlfield>\n",
+                    escape(tag),
+                    escape(value)
+                )),
+                Field::Data {
+                    tag,
+                    ind1,
+                    ind2,
+                    subfields,
+                } => {
+                    xml.push_str(&format!(
+                        "    <datafield tag=\"{}\" ind1=\"{}\" ind2=\"{}\">\n",
+                        escape(tag),
+                        escape(ind1.to_string()),
+                        escape(ind2.to_string())
+                    ));
+                    for (code, value) in subfields {
+                        xml.push_str(&format!(
+                            "      <subfield code=\"{}\">{}</subfield>\n",
+                            escape(code.to_string()),
+                            escape(value)
+                        ));
+                    }
+                    xml.push_str("    </datafield>\n");
+                }
+            }
+        }
+        xml.push_str("  </record>\n");
+    }
+
+    xml.push_str("</collection>\n");
+    xml
+}
+
+/// Reads the records of a MARCXML `<collection>` or a single `<record>`.
+pub fn read(xml: &str) -> Result<Vec<Record>, MarcError> {
+    let mut reader = Reader::from_str(xml);
+    let mut records = Vec::new();
+    let mut record: Option<Record> = None;
+    // Text is collected until the element it belongs to ends
+    let mut text = String::new();
+    let mut tag = String::new();
+    let mut code = ' ';
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                text.clear();
+                match e.local_name().as_ref() {
+                    b"record" => {
+                        record = Some(Record {
+                            leader: String::new(),
+                            fields: Vec::new(),
+                        })
+                    }
+                    b"controlfield" => tag = attribute(&e, "tag")?.unwrap_or_default(),
+                    b"datafield" => {
+                        let char_attribute = |name| -> Result<char, MarcError> {
+                            Ok(attribute(&e, name)?
+                                .and_then(|v| v.chars().next())
+                                .unwrap_or(' '))
+                        };
+                        if let Some(record) = record.as_mut() {
+                            record.fields.push(Field::Data {
+                                tag: attribute(&e, "tag")?.unwrap_or_default(),
+                                ind1: char_attribute("ind1")?,
+                                ind2: char_attribute("ind2")?,
+                                subfields: Vec::new(),
+                            });
+                        }
+                    }
+                    b"subfield" => {
+                        code = attribute(&e, "code")?
+                            .and_then(|v| v.chars().next())
+                            .unwrap_or(' ')
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(e) => text.push_str(&e.unescape()?),
+            Event::CData(e) => text.push_str(&String::from_utf8_lossy(&e)),
+            Event::End(e) => {
+                let Some(current) = record.as_mut() else {
+                    continue;
+                };
+                match e.local_name().as_ref() {
+                    b"leader" => current.leader = text.clone(),
+                    b"controlfield" => current.fields.push(Field::Control {
+                        tag: tag.clone(),
+                        value: text.clone(),
+                    }),
+                    b"subfield" => {
+                        if let Some(Field::Data { subfields, .. }) = current.fields.last_mut() {
+                            subfields.push((code, text.clone()));
+                        }
+                    }
+                    b"record" => records.extend(record.take()),
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(records)
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<Option<String>, MarcError> {
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.local_name().as_ref() == name.as_bytes() {
+            return Ok(Some(attr.unescape_value()?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::book::BookBuilder;
+
+    #[test]
+    fn round_trips_books() {
+        // Arrange
+        let book = BookBuilder::default()
+            .id(3)
+            .title("Cats & Dogs <Illustrated>".to_string())
+            .author("Jane Doe & John \"Jack\" Smith".to_string())
+            .isbn("9780000000002".to_string())
+            .published_year(2001)
+            .description(Some("Line one\nLine two".to_string()))
+            .pages(Some(120))
+            .build()
+            .unwrap();
+        let record = Record::from_book(&book);
+
+        // Act
+        let xml = write(std::slice::from_ref(&record));
+        let read_back = read(&xml).unwrap();
+        let read_book = read_back[0].to_book_builder().id(3).build().unwrap();
+
+        // Assert
+        assert!(xml.contains("Cats &amp; Dogs &lt;Illustrated&gt;"));
+        assert_eq!(vec![record], read_back);
+        assert_eq!(book, read_book);
+    }
+
+    #[test]
+    fn reads_prefixed_marcxml() {
+        // Arrange
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<marc:record xmlns:marc="http://www.loc.gov/MARC21/slim">
+  <marc:leader>01142cam  2200301 a 4500</marc:leader>
+  <marc:controlfield tag="001">92005291</marc:controlfield>
+  <marc:datafield tag="020" ind1=" " ind2=" ">
+    <marc:subfield code="a">0441013597 :</marc:subfield>
+  </marc:datafield>
+  <marc:datafield tag="100" ind1="1" ind2=" ">
+    <marc:subfield code="a">Herbert, Frank.</marc:subfield>
+  </marc:datafield>
+  <marc:datafield tag="245" ind1="1" ind2="0">
+    <marc:subfield code="a">Dune /</marc:subfield>
+    <marc:subfield code="c">Frank Herbert.</marc:subfield>
+  </marc:datafield>
+  <marc:datafield tag="260" ind1=" " ind2=" ">
+    <marc:subfield code="c">[1965]</marc:subfield>
+  </marc:datafield>
+  <marc:datafield tag="300" ind1=" " ind2=" ">
+    <marc:subfield code="a">604 p. ;</marc:subfield>
+  </marc:datafield>
+</marc:record>"#;
+
+        // Act
+        let records = read(xml).unwrap();
+        let book = records[0].to_book_builder().build().unwrap();
+
+        // Assert
+        assert_eq!(1, records.len());
+        assert_eq!("01142cam  2200301 a 4500", records[0].leader);
+        assert_eq!("0441013597", book.isbn);
+        assert_eq!("Frank Herbert", book.author);
+        assert_eq!("Dune", book.title);
+        assert_eq!(1965, book.published_year);
+        assert_eq!(Some(604), book.pages);
+    }
+}
@@ -0,0 +1,325 @@
+//! MARC 21 bibliographic records, for exchanging books with library systems.
+//!
+//! Records can be read and written as ISO 2709 (`iso2709`) and as MARCXML
+//! (`marcxml`). Books map to and from these fields:
+//!
+//! | Field   | Book                                            |
+//! |---------|-------------------------------------------------|
+//! | 020 $a  | `isbn`                                          |
+//! | 100 $a  | first author, as "Family, Given"                |
+//! | 700 $a  | other authors                                   |
+//! | 245 $a  | `title` (with $b as subtitle when reading)      |
+//! | 264 $c  | `published_year` (260 $c is read as well)      |
+//! | 300 $a  | `pages`, as "592 pages"                         |
+//! | 520 $a  | `description`                                   |
+use crate::export::citation::{Name, split_name};
+use crate::import::normalize_isbn;
+use crate::models::book::{Book, BookBuilder};
+use std::fmt;
+
+pub mod iso2709;
+pub mod marcxml;
+
+/// Leader for a new, Unicode encoded, RDA cataloged monograph. The lengths
+/// and base address are filled in when the record is written.
+pub const DEFAULT_LEADER: &str = "00000nam a2200000 i 4500";
+
+#[derive(Debug)]
+pub enum MarcError {
+    /// A record, its leader or its directory is shorter than it claims to be.
+    Truncated,
+    InvalidLeader,
+    InvalidDirectory,
+    InvalidUtf8,
+    /// A record or one of its fields is too long for the lengths ISO 2709
+    /// has room for: 99 999 bytes for a record and 9 999 for a field.
+    RecordTooLong,
+    Xml(quick_xml::Error),
+}
+
+impl fmt::Display for MarcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarcError::Truncated => write!(f, "MARC record is truncated"),
+            MarcError::InvalidLeader => write!(f, "MARC record has an invalid leader"),
+            MarcError::InvalidDirectory => write!(f, "MARC record has an invalid directory"),
+            MarcError::InvalidUtf8 => write!(f, "MARC record is not valid UTF-8"),
+            MarcError::RecordTooLong => write!(f, "MARC record is too long for ISO 2709"),
+            MarcError::Xml(e) => write!(f, "invalid MARCXML: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MarcError {}
+
+impl From<quick_xml::Error> for MarcError {
+    fn from(e: quick_xml::Error) -> Self {
+        MarcError::Xml(e)
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for MarcError {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        MarcError::Xml(e.into())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Field {
+    /// Fields 001 to 009, which hold a plain value.
+    Control { tag: String, value: String },
+    Data {
+        tag: String,
+        ind1: char,
+        ind2: char,
+        subfields: Vec<(char, String)>,
+    },
+}
+
+impl Field {
+    pub fn tag(&self) -> &str {
+        match self {
+            Field::Control { tag, .. } | Field::Data { tag, .. } => tag,
+        }
+    }
+
+    fn data(tag: &str, ind1: char, ind2: char, subfields: Vec<(char, String)>) -> Self {
+        Field::Data {
+            tag: tag.to_string(),
+            ind1,
+            ind2,
+            subfields,
+        }
+    }
+
+    /// The first subfield with the given code, if this is a data field.
+    pub fn subfield(&self, code: char) -> Option<&str> {
+        match self {
+            Field::Control { .. } => None,
+            Field::Data { subfields, .. } => subfields
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map(|(_, value)| value.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    pub leader: String,
+    pub fields: Vec<Field>,
+}
+
+impl Record {
+    /// All fields with the given tag.
+    pub fn fields<'a, 't>(&'a self, tag: &'t str) -> impl Iterator<Item = &'a Field> + use<'a, 't> {
+        self.fields.iter().filter(move |f| f.tag() == tag)
+    }
+
+    /// The first subfield `code` of the first field tagged `tag` that has one.
+    pub fn subfield(&self, tag: &str, code: char) -> Option<&str> {
+        self.fields(tag).find_map(|f| f.subfield(code))
+    }
+
+    /// Catalogs a book as a MARC record.
+    pub fn from_book(book: &Book) -> Self {
+        let mut fields = vec![Field::Control {
+            tag: "001".to_string(),
+            value: book.id.to_string(),
+        }];
+
+        fields.push(Field::data("020", ' ', ' ', vec![('a', book.isbn.clone())]));
+
+        let authors = book.authors();
+        if let Some(first) = authors.first() {
+            fields.push(Field::data("100", '1', ' ', vec![('a', inverted(first))]));
+        }
+
+        // Second indicator 0: no leading article to skip when sorting
+        fields.push(Field::data(
+            "245",
+            if authors.is_empty() { '0' } else { '1' },
+            '0',
+            vec![('a', book.title.clone())],
+        ));
+        fields.push(Field::data(
+            "264",
+            ' ',
+            '1',
+            vec![('c', book.published_year.to_string())],
+        ));
+        if let Some(pages) = book.pages {
+            fields.push(Field::data(
+                "300",
+                ' ',
+                ' ',
+                vec![('a', format!("{pages} pages"))],
+            ));
+        }
+        if let Some(description) = &book.description {
+            fields.push(Field::data(
+                "520",
+                ' ',
+                ' ',
+                vec![('a', description.clone())],
+            ));
+        }
+        for author in authors.iter().skip(1) {
+            fields.push(Field::data("700", '1', ' ', vec![('a', inverted(author))]));
+        }
+
+        Record {
+            leader: DEFAULT_LEADER.to_string(),
+            fields,
+        }
+    }
+
+    /// Turns the record into a `BookBuilder`. ISBD punctuation is removed, so
+    /// records cataloged by libraries come out clean. Fields the record lacks
+    /// are left unset.
+    pub fn to_book_builder(&self) -> BookBuilder {
+        let mut builder = BookBuilder::default();
+
+        if let Some(isbn) = self
+            .fields("020")
+            .filter_map(|f| f.subfield('a'))
+            .find_map(|a| normalize_isbn(a.split_whitespace().next()?))
+        {
+            builder.isbn(isbn);
+        }
+
+        let authors: Vec<String> = self
+            .fields("100")
+            .chain(self.fields("700"))
+            .filter_map(|f| f.subfield('a'))
+            .map(|name| uninverted(strip_punctuation(name)))
+            .collect();
+        builder.author(authors.join(" & "));
+
+        if let Some(title) = self.subfield("245", 'a') {
+            let title = strip_punctuation(title);
+            match self.subfield("245", 'b') {
+                Some(subtitle) => {
+                    builder.title(format!("{title}: {}", strip_punctuation(subtitle)))
+                }
+                None => builder.title(title.to_string()),
+            };
+        }
+
+        if let Some(year) = self
+            .subfield("264", 'c')
+            .or_else(|| self.subfield("260", 'c'))
+            .and_then(first_number)
+        {
+            builder.published_year(year);
+        }
+
+        builder.pages(
+            self.subfield("300", 'a')
+                .and_then(first_number)
+                .filter(|pages| *pages > 0),
+        );
+        builder.description(self.subfield("520", 'a').map(|d| d.trim().to_string()));
+
+        builder
+    }
+}
+
+/// "Fyodor Dostoevsky" as "Dostoevsky, Fyodor".
+fn inverted(name: &str) -> String {
+    match split_name(name) {
+        Name {
+            family,
+            given: Some(given),
+        } => format!("{family}, {given}"),
+        Name { family, .. } => family.to_string(),
+    }
+}
+
+/// "Dostoevsky, Fyodor" as "Fyodor Dostoevsky".
+fn uninverted(name: &str) -> String {
+    match split_name(name) {
+        Name {
+            family,
+            given: Some(given),
+        } if name.contains(',') => format!("{given} {family}"),
+        _ => name.to_string(),
+    }
+}
+
+/// Removes the trailing ISBD punctuation catalogers end subfields with, like
+/// the " /" in "Crime and punishment /".
+fn strip_punctuation(value: &str) -> &str {
+    value
+        .trim()
+        .trim_end_matches([' ', '/', ':', ';', ',', '=', '.'])
+}
+
+/// The first run of digits in a value like "c2012." or "xii, 592 pages".
+fn first_number(value: &str) -> Option<i32> {
+    let start = value.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = value[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cataloged_record() {
+        // Arrange
+        let record = Record {
+            leader: DEFAULT_LEADER.to_string(),
+            fields: vec![
+                Field::data(
+                    "020",
+                    ' ',
+                    ' ',
+                    vec![('a', "0140449132 (pbk.)".to_string())],
+                ),
+                Field::data(
+                    "100",
+                    '1',
+                    ' ',
+                    vec![
+                        ('a', "Dostoyevsky, Fyodor,".to_string()),
+                        ('d', "1821-1881.".to_string()),
+                    ],
+                ),
+                Field::data(
+                    "245",
+                    '1',
+                    '0',
+                    vec![
+                        ('a', "Crime and punishment :".to_string()),
+                        ('b', "a novel in six parts /".to_string()),
+                        ('c', "Fyodor Dostoyevsky.".to_string()),
+                    ],
+                ),
+                Field::data(
+                    "260",
+                    ' ',
+                    ' ',
+                    vec![('b', "Penguin,".to_string()), ('c', "c2003.".to_string())],
+                ),
+                Field::data("300", ' ', ' ', vec![('a', "xxxix, 656 p. ;".to_string())]),
+                Field::data("700", '1', ' ', vec![('a', "McDuff, David,".to_string())]),
+            ],
+        };
+
+        // Act
+        let book = record.to_book_builder().build().unwrap();
+
+        // Assert
+        assert_eq!("0140449132", book.isbn);
+        assert_eq!("Fyodor Dostoyevsky & David McDuff", book.author);
+        assert_eq!("Crime and punishment: a novel in six parts", book.title);
+        assert_eq!(2003, book.published_year);
+        assert_eq!(Some(656), book.pages);
+        assert_eq!(None, book.description);
+    }
+}
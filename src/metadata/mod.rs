@@ -0,0 +1,56 @@
+//! Looking up book metadata from online catalogues, so books don't have to be
+//! entered by hand.
+//!
+//! Providers fill in a `BookBuilder` with whatever the catalogue knows, and
+//! leave the rest to the builder's defaults.
+use crate::models::book::BookBuilder;
+use std::fmt;
+
+pub mod open_library;
+
+#[derive(Debug)]
+pub enum MetadataError {
+    Http(reqwest::Error),
+    /// The catalogue answered with an unexpected status code.
+    Status(u16),
+    /// The provider was configured with a base URL that doesn't parse.
+    InvalidUrl(String),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::Http(e) => write!(f, "metadata request failed: {e}"),
+            MetadataError::Status(status) => {
+                write!(f, "metadata provider responded with status {status}")
+            }
+            MetadataError::InvalidUrl(url) => write!(f, "invalid metadata provider URL: {url}"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+impl From<reqwest::Error> for MetadataError {
+    fn from(e: reqwest::Error) -> Self {
+        MetadataError::Http(e)
+    }
+}
+
+/// A catalogue books can be looked up in.
+pub trait MetadataProvider {
+    /// The edition with the given ISBN, or `None` if the catalogue doesn't
+    /// have it.
+    fn lookup_isbn(
+        &self,
+        isbn: &str,
+    ) -> impl Future<Output = Result<Option<BookBuilder>, MetadataError>> + Send;
+
+    /// Books matching a title and, optionally, an author. Only results with
+    /// an ISBN are returned, best match first.
+    fn search(
+        &self,
+        title: &str,
+        author: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<BookBuilder>, MetadataError>> + Send;
+}
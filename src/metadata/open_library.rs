@@ -0,0 +1,564 @@
+//! Metadata from Open Library (https://openlibrary.org).
+//!
+//! An ISBN lookup reads the edition, its authors and, when the edition has no
+//! description, its work. Responses are cached, so looking up several
+//! editions by the same author only fetches the author once. The cache keeps
+//! a bounded number of responses, dropping expired ones and then the oldest
+//! when it is full.
+use crate::import::normalize_isbn;
+use crate::metadata::{MetadataError, MetadataProvider};
+use crate::models::book::{BookBuilder, BookFormat};
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const BASE_URL: &str = "https://openlibrary.org";
+pub const COVERS_URL: &str = "https://covers.openlibrary.org";
+
+/// How many results a search returns.
+const SEARCH_LIMIT: usize = 10;
+
+/// Most responses cached by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+pub struct OpenLibrary {
+    client: Client,
+    base_url: String,
+    cache_ttl: Duration,
+    cache_capacity: usize,
+    /// Response bodies by URL. `None` means the resource doesn't exist.
+    cache: Mutex<HashMap<String, (Instant, Option<Value>)>>,
+}
+
+impl OpenLibrary {
+    pub fn new() -> Self {
+        Self::with_base_url(BASE_URL)
+    }
+
+    /// A client for another Open Library instance, such as a mirror.
+    pub fn with_base_url(base_url: &str) -> Self {
+        OpenLibrary {
+            client: Client::builder()
+                // Open Library asks clients to identify themselves
+                .user_agent(concat!("bookshelf/", env!("CARGO_PKG_VERSION")))
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("HTTP client configuration is valid"),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            cache_ttl: Duration::from_secs(24 * 60 * 60),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how long responses are cached. Defaults to a day.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// Sets how many responses are cached at most. Defaults to
+    /// `DEFAULT_CACHE_CAPACITY`.
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Fetches `path` as JSON, from the cache if it was fetched recently.
+    async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Option<Value>, MetadataError> {
+        let url = reqwest::Url::parse_with_params(&format!("{}{path}", self.base_url), query)
+            .map_err(|_| MetadataError::InvalidUrl(self.base_url.clone()))?;
+
+        if let Some((fetched, body)) = self.cache.lock().unwrap().get(url.as_str())
+            && fetched.elapsed() < self.cache_ttl
+        {
+            return Ok(body.clone());
+        }
+
+        let response = self.client.get(url.clone()).send().await?;
+        let body = match response.status() {
+            StatusCode::NOT_FOUND => None,
+            status if status.is_success() => Some(response.json::<Value>().await?),
+            status => return Err(MetadataError::Status(status.as_u16())),
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.cache_capacity && !cache.contains_key(url.as_str()) {
+            cache.retain(|_, (fetched, _)| fetched.elapsed() < self.cache_ttl);
+            if cache.len() >= self.cache_capacity
+                && let Some(oldest) = cache
+                    .iter()
+                    .min_by_key(|(_, (fetched, _))| *fetched)
+                    .map(|(url, _)| url.clone())
+            {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(url.to_string(), (Instant::now(), body.clone()));
+        Ok(body)
+    }
+
+    async fn author_name(&self, key: &str) -> Result<Option<String>, MetadataError> {
+        Ok(self
+            .get(&format!("{key}.json"), &[])
+            .await?
+            .and_then(|author| author["name"].as_str().map(str::to_string)))
+    }
+}
+
+impl Default for OpenLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetadataProvider for OpenLibrary {
+    async fn lookup_isbn(&self, isbn: &str) -> Result<Option<BookBuilder>, MetadataError> {
+        let Some(isbn) = normalize_isbn(isbn) else {
+            return Ok(None);
+        };
+        let Some(edition) = self.get(&format!("/isbn/{isbn}.json"), &[]).await? else {
+            return Ok(None);
+        };
+
+        let work = match edition["works"][0]["key"].as_str() {
+            Some(key) => self.get(&format!("{key}.json"), &[]).await?,
+            None => None,
+        };
+
+        // Editions usually list their authors, otherwise the work does
+        let mut author_keys: Vec<&str> = keys(&edition["authors"], |a| &a["key"]);
+        if author_keys.is_empty()
+            && let Some(work) = &work
+        {
+            author_keys = keys(&work["authors"], |a| &a["author"]["key"]);
+        }
+        let mut authors = Vec::new();
+        for key in author_keys {
+            authors.extend(self.author_name(key).await?);
+        }
+
+        let mut builder = BookBuilder::default();
+        builder
+            .title(title_of(&edition))
+            .author(authors.join(" & "))
+            .isbn(
+                edition["isbn_13"][0]
+                    .as_str()
+                    .and_then(normalize_isbn)
+                    .unwrap_or(isbn),
+            )
+            .pages(
+                edition["number_of_pages"]
+                    .as_i64()
+                    .and_then(|pages| i32::try_from(pages).ok())
+                    .filter(|pages| *pages > 0),
+            )
+            .description(
+                description(&edition["description"])
+                    .or_else(|| work.as_ref().and_then(|w| description(&w["description"]))),
+            )
+            .cover_url(cover_url(&edition["covers"][0]));
+        if let Some(year) = edition["publish_date"].as_str().and_then(year) {
+            builder.published_year(year);
+        }
+        if let Some(format) = edition["physical_format"].as_str().and_then(format) {
+            builder.format(format);
+        }
+
+        Ok(Some(builder))
+    }
+
+    async fn search(
+        &self,
+        title: &str,
+        author: Option<&str>,
+    ) -> Result<Vec<BookBuilder>, MetadataError> {
+        let limit = SEARCH_LIMIT.to_string();
+        let mut query = vec![
+            ("title", title),
+            ("limit", limit.as_str()),
+            (
+                "fields",
+                "title,subtitle,author_name,isbn,first_publish_year,number_of_pages_median,cover_i",
+            ),
+        ];
+        if let Some(author) = author {
+            query.push(("author", author));
+        }
+
+        let Some(results) = self.get("/search.json", &query).await? else {
+            return Ok(Vec::new());
+        };
+
+        let books = results["docs"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|doc| {
+                // Prefer an ISBN-13, they are listed in no particular order
+                let isbns: Vec<String> = doc["isbn"]
+                    .as_array()?
+                    .iter()
+                    .filter_map(|isbn| normalize_isbn(isbn.as_str()?))
+                    .collect();
+                let isbn = isbns
+                    .iter()
+                    .find(|isbn| isbn.len() == 13)
+                    .or(isbns.first())?
+                    .clone();
+
+                let authors: Vec<&str> = doc["author_name"]
+                    .as_array()
+                    .map(|names| names.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+
+                let mut builder = BookBuilder::default();
+                builder
+                    .title(title_of(doc))
+                    .author(authors.join(" & "))
+                    .isbn(isbn)
+                    .pages(
+                        doc["number_of_pages_median"]
+                            .as_i64()
+                            .and_then(|pages| i32::try_from(pages).ok())
+                            .filter(|pages| *pages > 0),
+                    )
+                    .cover_url(cover_url(&doc["cover_i"]));
+                if let Some(year) = doc["first_publish_year"].as_i64() {
+                    builder.published_year(year as i32);
+                }
+                Some(builder)
+            })
+            .collect();
+
+        Ok(books)
+    }
+}
+
+/// "Title: Subtitle", the way titles are stored on books.
+fn title_of(value: &Value) -> String {
+    let title = value["title"].as_str().unwrap_or_default().trim();
+    match value["subtitle"].as_str() {
+        Some(subtitle) => format!("{title}: {}", subtitle.trim()),
+        None => title.to_string(),
+    }
+}
+
+/// The `key`s picked from each entry of a list.
+fn keys<'a>(list: &'a Value, key: impl Fn(&'a Value) -> &'a Value) -> Vec<&'a str> {
+    list.as_array()
+        .map(|entries| entries.iter().filter_map(|e| key(e).as_str()).collect())
+        .unwrap_or_default()
+}
+
+/// Descriptions are either a string or a `{"type": "/type/text", "value": ...}`.
+pub(crate) fn description(value: &Value) -> Option<String> {
+    value
+        .as_str()
+        .or_else(|| value["value"].as_str())
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+}
+
+/// Negative cover IDs mark deleted covers.
+fn cover_url(id: &Value) -> Option<String> {
+    id.as_i64()
+        .filter(|id| *id > 0)
+        .map(|id| format!("{COVERS_URL}/b/id/{id}-L.jpg"))
+}
+
+/// The format of a `physical_format` like "Hardcover", "E-book" or
+/// "Audio CD". The field is free text, so unrecognised formats are `None`.
+fn format(physical_format: &str) -> Option<BookFormat> {
+    let physical_format = physical_format.to_lowercase();
+    if physical_format.contains("audio") {
+        Some(BookFormat::Audiobook)
+    } else if physical_format.contains("hardcover") || physical_format.contains("hardback") {
+        Some(BookFormat::Hardcover)
+    } else if physical_format.contains("paperback") {
+        Some(BookFormat::Paperback)
+    } else if physical_format.contains("e-book") || physical_format.contains("ebook") {
+        Some(BookFormat::Ebook)
+    } else {
+        None
+    }
+}
+
+/// The year of a publish date like "1990", "May 1990" or "2006-01-02".
+fn year(date: &str) -> Option<i32> {
+    date.as_bytes()
+        .windows(4)
+        .enumerate()
+        .find(|(i, window)| {
+            window.iter().all(u8::is_ascii_digit)
+                && !date.as_bytes().get(i + 4).is_some_and(u8::is_ascii_digit)
+                && !(*i > 0 && date.as_bytes()[i - 1].is_ascii_digit())
+        })
+        .and_then(|(i, _)| date[i..i + 4].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, Query, State};
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Hits = Arc<AtomicUsize>;
+
+    /// Serves a handful of Open Library resources, counting the requests.
+    async fn mock_open_library() -> (String, Hits) {
+        let hits = Hits::default();
+        let app = Router::new()
+            .route("/isbn/{file}", get(edition))
+            .route("/works/{file}", get(work))
+            .route("/authors/{file}", get(author))
+            .route("/search.json", get(search))
+            .with_state(hits.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{addr}"), hits)
+    }
+
+    async fn edition(State(hits): State<Hits>, Path(file): Path<String>) -> impl IntoResponse {
+        hits.fetch_add(1, Ordering::SeqCst);
+        match file.as_str() {
+            "9780060853983.json" => Json(json!({
+                "title": "Good Omens",
+                "subtitle": "The Nice and Accurate Prophecies of Agnes Nutter, Witch",
+                "authors": [{ "key": "/authors/OL25712A" }, { "key": "/authors/OL1394865A" }],
+                "works": [{ "key": "/works/OL453936W" }],
+                "publish_date": "November 28, 2006",
+                "number_of_pages": 432,
+                "physical_format": "Mass Market Paperback",
+                "isbn_10": ["0060853980"],
+                "isbn_13": ["9780060853983"],
+                "covers": [8231856],
+            }))
+            .into_response(),
+            "0441013597.json" => Json(json!({
+                "title": "Dune",
+                "works": [{ "key": "/works/OL893415W" }],
+                "publish_date": "2005",
+                "description": { "type": "/type/text", "value": "Set on the desert planet Arrakis." },
+                "covers": [-1],
+            }))
+            .into_response(),
+            _ => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    async fn work(State(hits): State<Hits>, Path(file): Path<String>) -> impl IntoResponse {
+        hits.fetch_add(1, Ordering::SeqCst);
+        match file.as_str() {
+            "OL453936W.json" => Json(json!({
+                "title": "Good Omens",
+                "description": "Armageddon is near, and an angel and a demon would rather it wasn't.",
+            }))
+            .into_response(),
+            "OL893415W.json" => Json(json!({
+                "title": "Dune",
+                "authors": [{ "author": { "key": "/authors/OL79034A" }, "type": { "key": "/type/author_role" } }],
+            }))
+            .into_response(),
+            _ => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    async fn author(State(hits): State<Hits>, Path(file): Path<String>) -> impl IntoResponse {
+        hits.fetch_add(1, Ordering::SeqCst);
+        let name = match file.as_str() {
+            "OL25712A.json" => "Terry Pratchett",
+            "OL1394865A.json" => "Neil Gaiman",
+            "OL79034A.json" => "Frank Herbert",
+            _ => return StatusCode::NOT_FOUND.into_response(),
+        };
+        Json(json!({ "name": name })).into_response()
+    }
+
+    async fn search(
+        State(hits): State<Hits>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> impl IntoResponse {
+        hits.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(Some("dune"), query.get("title").map(String::as_str));
+        assert_eq!(Some("herbert"), query.get("author").map(String::as_str));
+        Json(json!({
+            "numFound": 2,
+            "docs": [
+                {
+                    "title": "Dune",
+                    "author_name": ["Frank Herbert"],
+                    "first_publish_year": 1965,
+                    "number_of_pages_median": 604,
+                    "isbn": ["0441013597", "9780441013593"],
+                    "cover_i": 11481354,
+                },
+                { "title": "Dune (no ISBN)", "author_name": ["Frank Herbert"] },
+            ],
+        }))
+    }
+
+    #[tokio::test]
+    async fn looks_up_isbn() {
+        // Arrange
+        let (url, _) = mock_open_library().await;
+        let provider = OpenLibrary::with_base_url(&url);
+
+        // Act
+        let book = provider
+            .lookup_isbn("978-0-06-085398-3")
+            .await
+            .unwrap()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            "Good Omens: The Nice and Accurate Prophecies of Agnes Nutter, Witch",
+            book.title
+        );
+        assert_eq!("Terry Pratchett & Neil Gaiman", book.author);
+        assert_eq!("9780060853983", book.isbn);
+        assert_eq!(2006, book.published_year);
+        assert_eq!(Some(432), book.pages);
+        assert_eq!(BookFormat::Paperback, book.format);
+        assert_eq!(
+            Some("Armageddon is near, and an angel and a demon would rather it wasn't."),
+            book.description.as_deref()
+        );
+        assert_eq!(
+            Some("https://covers.openlibrary.org/b/id/8231856-L.jpg"),
+            book.cover_url.as_deref()
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_work_authors() {
+        // Arrange
+        let (url, _) = mock_open_library().await;
+        let provider = OpenLibrary::with_base_url(&url);
+
+        // Act
+        let book = provider
+            .lookup_isbn("0441013597")
+            .await
+            .unwrap()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // Assert
+        assert_eq!("Frank Herbert", book.author);
+        assert_eq!(
+            Some("Set on the desert planet Arrakis."),
+            book.description.as_deref()
+        );
+        assert_eq!(None, book.cover_url);
+        assert_eq!(None, book.pages);
+    }
+
+    #[tokio::test]
+    async fn unknown_isbn_is_none() {
+        // Arrange
+        let (url, _) = mock_open_library().await;
+        let provider = OpenLibrary::with_base_url(&url);
+
+        // Act
+        let missing = provider.lookup_isbn("9780000000002").await.unwrap();
+        let invalid = provider.lookup_isbn("not an isbn").await.unwrap();
+
+        // Assert
+        assert!(missing.is_none());
+        assert!(invalid.is_none());
+    }
+
+    #[tokio::test]
+    async fn responses_are_cached() {
+        // Arrange
+        let (url, hits) = mock_open_library().await;
+        let provider = OpenLibrary::with_base_url(&url);
+        let expired = OpenLibrary::with_base_url(&url).cache_ttl(Duration::ZERO);
+        let small = OpenLibrary::with_base_url(&url).cache_capacity(2);
+
+        // Act
+        provider.lookup_isbn("9780060853983").await.unwrap();
+        let first = hits.swap(0, Ordering::SeqCst);
+        provider.lookup_isbn("9780060853983").await.unwrap();
+        provider.lookup_isbn("9780000000002").await.unwrap();
+        provider.lookup_isbn("9780000000002").await.unwrap();
+        let cached = hits.swap(0, Ordering::SeqCst);
+        expired.lookup_isbn("9780060853983").await.unwrap();
+        expired.lookup_isbn("9780060853983").await.unwrap();
+        let uncached = hits.swap(0, Ordering::SeqCst);
+        small.lookup_isbn("9780060853983").await.unwrap();
+        small.lookup_isbn("9780060853983").await.unwrap();
+        let evicted = hits.load(Ordering::SeqCst);
+        let kept = small.cache.lock().unwrap().len();
+
+        // Assert
+        // Edition, work and two authors
+        assert_eq!(4, first);
+        // Only the first request for the missing edition
+        assert_eq!(1, cached);
+        assert_eq!(8, uncached);
+        // Two of the four responses fit, each pushing out one needed later
+        assert_eq!(8, evicted);
+        assert_eq!(2, kept);
+    }
+
+    #[tokio::test]
+    async fn searches_by_title_and_author() {
+        // Arrange
+        let (url, _) = mock_open_library().await;
+        let provider = OpenLibrary::with_base_url(&url);
+
+        // Act
+        let books: Vec<_> = provider
+            .search("dune", Some("herbert"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|b| b.build().unwrap())
+            .collect();
+
+        // Assert
+        assert_eq!(1, books.len());
+        assert_eq!("Dune", books[0].title);
+        assert_eq!("Frank Herbert", books[0].author);
+        assert_eq!("9780441013593", books[0].isbn);
+        assert_eq!(1965, books[0].published_year);
+        assert_eq!(Some(604), books[0].pages);
+        assert_eq!(
+            Some("https://covers.openlibrary.org/b/id/11481354-L.jpg"),
+            books[0].cover_url.as_deref()
+        );
+    }
+
+    #[test]
+    fn years_from_publish_dates() {
+        assert_eq!(Some(1990), year("1990"));
+        assert_eq!(Some(1990), year("May 1990"));
+        assert_eq!(Some(2006), year("November 28, 2006"));
+        assert_eq!(Some(2006), year("2006-01-02"));
+        assert_eq!(None, year("12345"));
+        assert_eq!(None, year("n.d."));
+    }
+}
@@ -0,0 +1,389 @@
+// Events on users' shelves, like starting or finishing a book. Events are
+// stored once per user and gathered into followers' feeds when read.
+
+use crate::models::user_book::{ReadingStatus, UserBook};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+
+/// Most activities returned by one page of a feed.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "activity_kind", rename_all = "lowercase")]
+pub enum ActivityKind {
+    Added,
+    Started,
+    Progress,
+    Finished,
+    Rated,
+    Reviewed,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Activity {
+    pub id: i64,
+    pub user_id: i64,
+    pub book_id: i64,
+    pub kind: ActivityKind,
+    /// The user's progress in the book at the time.
+    pub progress_percent: Option<f64>,
+    /// The rating given, for `Rated` activities.
+    pub rating: Option<i16>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Where a feed page ends. Pass it to get the page after.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FeedCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FeedPage {
+    pub activities: Vec<Activity>,
+    /// `None` on the last page.
+    pub next: Option<FeedCursor>,
+}
+
+impl Activity {
+    /// Records an activity of a shelf entry, with the entry's current
+    /// progress and rating.
+    pub async fn record(
+        conn: &mut PgConnection,
+        user_book: &UserBook,
+        kind: ActivityKind,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO activities (user_id, book_id, kind, progress_percent, rating)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            user_book.user_id,
+            user_book.book_id,
+            kind as ActivityKind,
+            user_book.progress_percent,
+            (kind == ActivityKind::Rated)
+                .then_some(user_book.rating)
+                .flatten()
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records the activities of a shelf entry going from `before`, `None`
+    /// for a new entry, to `after`.
+    pub async fn record_changes(
+        conn: &mut PgConnection,
+        before: Option<&UserBook>,
+        after: &UserBook,
+    ) -> Result<(), sqlx::Error> {
+        for kind in changes(before, after) {
+            Self::record(conn, after, kind).await?;
+        }
+
+        Ok(())
+    }
+
+    /// A user's own activities that `viewer_id` can see, newest first.
+    pub async fn for_user(
+        pool: &PgPool,
+        user_id: i64,
+        viewer_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Activity,
+            r#"
+            SELECT a.id, a.user_id, a.book_id, a.kind as "kind: ActivityKind", a.progress_percent, a.rating, a.created_at
+            FROM activities a
+            JOIN user_books ub ON ub.user_id = a.user_id AND ub.book_id = a.book_id
+            JOIN users u ON u.id = a.user_id
+            LEFT JOIN reviews r ON r.user_id = a.user_id AND r.book_id = a.book_id AND a.kind = 'reviewed'
+            WHERE a.user_id = $1 AND visible_to(a.user_id, $2, LEAST(u.visibility, ub.visibility, r.visibility))
+            ORDER BY a.created_at DESC, a.id DESC
+            "#,
+            user_id,
+            viewer_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// A page of `user_id`'s home feed: their own activities and those of the
+    /// users they follow, minus the ones they muted or can't see, newest
+    /// first.
+    ///
+    /// Of repeated activities, like a user's progress in the same book, only
+    /// the latest is shown. Pages are at most `MAX_PAGE_SIZE` long, and the
+    /// page after `before` is returned when it is given.
+    pub async fn home_feed(
+        pool: &PgPool,
+        user_id: i64,
+        before: Option<FeedCursor>,
+        limit: i64,
+    ) -> Result<FeedPage, sqlx::Error> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        // Duplicates are removed before paginating, so an activity left out
+        // for a newer one never turns up on a later page
+        let mut activities = sqlx::query_as!(
+            Activity,
+            r#"
+            WITH latest AS (
+                SELECT DISTINCT ON (a.user_id, a.book_id, a.kind) a.*
+                FROM activities a
+                JOIN user_books ub ON ub.user_id = a.user_id AND ub.book_id = a.book_id
+                JOIN users u ON u.id = a.user_id
+                LEFT JOIN reviews r ON r.user_id = a.user_id AND r.book_id = a.book_id AND a.kind = 'reviewed'
+                WHERE (a.user_id = $1 OR EXISTS (
+                        SELECT 1 FROM follows f
+                        WHERE f.follower_id = $1 AND f.followee_id = a.user_id AND f.status = 'accepted'
+                    ))
+                  AND NOT EXISTS (SELECT 1 FROM mutes m WHERE m.muter_id = $1 AND m.muted_id = a.user_id)
+                  AND visible_to(a.user_id, $1, LEAST(u.visibility, ub.visibility, r.visibility))
+                ORDER BY a.user_id, a.book_id, a.kind, a.created_at DESC, a.id DESC
+            )
+            SELECT id AS "id!", user_id AS "user_id!", book_id AS "book_id!", kind AS "kind!: ActivityKind",
+                   progress_percent, rating, created_at AS "created_at!"
+            FROM latest
+            WHERE $2::timestamptz IS NULL OR (created_at, id) < ($2, $3)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before.map(|c| c.created_at),
+            before.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let next = if activities.len() as i64 > limit {
+            activities.truncate(limit as usize);
+            activities.last().map(|a| FeedCursor {
+                created_at: a.created_at,
+                id: a.id,
+            })
+        } else {
+            None
+        };
+
+        Ok(FeedPage { activities, next })
+    }
+}
+
+/// The activities of a shelf entry going from `before` to `after`.
+pub fn changes(before: Option<&UserBook>, after: &UserBook) -> Vec<ActivityKind> {
+    let mut kinds = Vec::new();
+    let status = before.map(|b| b.status);
+    if before.is_none() {
+        kinds.push(ActivityKind::Added);
+    }
+
+    match after.status {
+        ReadingStatus::Reading if status != Some(ReadingStatus::Reading) => {
+            kinds.push(ActivityKind::Started)
+        }
+        ReadingStatus::Completed if status != Some(ReadingStatus::Completed) => {
+            kinds.push(ActivityKind::Finished)
+        }
+        ReadingStatus::Reading
+            if before.is_some_and(|b| b.progress_percent != after.progress_percent) =>
+        {
+            kinds.push(ActivityKind::Progress)
+        }
+        _ => {}
+    }
+
+    if after.rating.is_some() && before.is_none_or(|b| b.rating != after.rating) {
+        kinds.push(ActivityKind::Rated);
+    }
+
+    kinds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user_book};
+    use crate::models::follow::{Follow, Mute};
+    use crate::models::review::Review;
+    use crate::models::user::{User, Visibility};
+    use crate::test_utils::{create_fake_users, setup_db, shelf_by_visibility};
+
+    #[test]
+    fn changes_between_entries() {
+        // Arrange
+        let mut before = fake_user_book(1, 1);
+        before.status = ReadingStatus::ToRead;
+        before.rating = None;
+        before.progress_percent = None;
+        let mut started = fake_user_book(1, 1);
+        started.status = ReadingStatus::Reading;
+        started.rating = None;
+        started.progress_percent = Some(10.0);
+        let mut progressed = fake_user_book(1, 1);
+        progressed.status = ReadingStatus::Reading;
+        progressed.rating = None;
+        progressed.progress_percent = Some(20.0);
+        let mut finished = fake_user_book(1, 1);
+        finished.status = ReadingStatus::Completed;
+        finished.rating = Some(8);
+        finished.progress_percent = Some(100.0);
+
+        // Act & Assert
+        assert_eq!(vec![ActivityKind::Added], changes(None, &before));
+        assert_eq!(
+            vec![
+                ActivityKind::Added,
+                ActivityKind::Finished,
+                ActivityKind::Rated
+            ],
+            changes(None, &finished)
+        );
+        assert_eq!(
+            vec![ActivityKind::Started],
+            changes(Some(&before), &started)
+        );
+        assert_eq!(
+            vec![ActivityKind::Progress],
+            changes(Some(&started), &progressed)
+        );
+        assert_eq!(
+            Vec::<ActivityKind>::new(),
+            changes(Some(&progressed), &progressed)
+        );
+        assert_eq!(
+            vec![ActivityKind::Finished, ActivityKind::Rated],
+            changes(Some(&progressed), &finished)
+        );
+    }
+
+    #[tokio::test]
+    async fn home_feed_from_followed_users() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 4).await;
+        let [reader, followed, muted, stranger]: [&User; 4] =
+            [&users[0], &users[1], &users[2], &users[3]];
+        Follow::request(&pool, reader.id, followed.id)
+            .await
+            .unwrap();
+        Follow::request(&pool, reader.id, muted.id).await.unwrap();
+        Mute {
+            muter_id: reader.id,
+            muted_id: muted.id,
+        }
+        .create(&pool)
+        .await
+        .unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+
+        for user in [followed, muted, stranger] {
+            let mut entry = fake_user_book(user.id, book.id);
+            entry.status = ReadingStatus::ToRead;
+            entry.rating = None;
+            entry.current_page = None;
+            entry.create(&pool).await.unwrap();
+        }
+        let mut entry = UserBook::get(&pool, book.id, followed.id).await.unwrap();
+        entry.status = ReadingStatus::Reading;
+        for percent in [10.0, 20.0, 30.0] {
+            entry.progress_percent = Some(percent);
+            entry.update(&pool).await.unwrap();
+        }
+
+        // Act
+        let first = Activity::home_feed(&pool, reader.id, None, 2)
+            .await
+            .unwrap();
+        let second = Activity::home_feed(&pool, reader.id, first.next, 2)
+            .await
+            .unwrap();
+        let own = Activity::for_user(&pool, followed.id, followed.id)
+            .await
+            .unwrap();
+
+        // Cleanup
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        let kinds: Vec<_> = first
+            .activities
+            .iter()
+            .chain(&second.activities)
+            .map(|a| (a.user_id, a.kind, a.progress_percent))
+            .collect();
+        assert_eq!(
+            vec![
+                (followed.id, ActivityKind::Progress, Some(30.0)),
+                (followed.id, ActivityKind::Started, Some(10.0)),
+                (followed.id, ActivityKind::Added, None),
+            ],
+            kinds
+        );
+        assert!(first.next.is_some());
+        assert_eq!(None, second.next);
+        // added, started, progress to 20% and to 30%
+        assert_eq!(4, own.len());
+    }
+
+    #[tokio::test]
+    async fn hidden_entries_left_out_of_feeds() {
+        // Arrange
+        let pool = setup_db().await;
+        let (owner_user, follower_user, stranger_user, books) = shelf_by_visibility(&pool).await;
+        let (owner, follower, stranger) = (owner_user.id, follower_user.id, stranger_user.id);
+        let mut review = Review::new(owner, books[0].id, "Dull.".to_string());
+        review.visibility = Visibility::Private;
+        review.create(&pool).await.unwrap();
+        let seen = |activities: Vec<Activity>| {
+            let mut seen: Vec<_> = activities.iter().map(|a| (a.book_id, a.kind)).collect();
+            seen.sort_by_key(|&(book_id, kind)| (book_id, kind as i32));
+            seen
+        };
+
+        // Act
+        let own = seen(Activity::for_user(&pool, owner, owner).await.unwrap());
+        let as_follower = seen(Activity::for_user(&pool, owner, follower).await.unwrap());
+        let as_stranger = seen(Activity::for_user(&pool, owner, stranger).await.unwrap());
+        let feed = Activity::home_feed(&pool, follower, None, MAX_PAGE_SIZE)
+            .await
+            .unwrap();
+
+        // Cleanup
+        for user in [&owner_user, &follower_user, &stranger_user] {
+            user.delete(&pool).await.unwrap();
+        }
+        for book in &books {
+            book.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert_eq!(
+            vec![
+                (books[0].id, ActivityKind::Added),
+                (books[0].id, ActivityKind::Reviewed),
+                (books[1].id, ActivityKind::Added),
+                (books[2].id, ActivityKind::Added),
+            ],
+            own
+        );
+        assert_eq!(
+            vec![
+                (books[0].id, ActivityKind::Added),
+                (books[1].id, ActivityKind::Added),
+            ],
+            as_follower
+        );
+        assert_eq!(vec![(books[0].id, ActivityKind::Added)], as_stranger);
+        assert_eq!(as_follower, seen(feed.activities));
+    }
+}
@@ -1,11 +1,54 @@
+use crate::models::user_book::ReadingStatus;
 use derive_builder::Builder;
 use sqlx::PgPool;
 
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "book_format", rename_all = "lowercase")]
+pub enum BookFormat {
+    Hardcover,
+    Paperback,
+    Ebook,
+    Audiobook,
+}
+
+/// How long a book is, in the unit that fits its format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pages(i32),
+    Minutes(i32),
+    Words(i32),
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    /// A book can't be merged into itself.
+    SameBook,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::SameBook => write!(f, "a book can't be merged into itself"),
+            MergeError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+impl From<sqlx::Error> for MergeError {
+    fn from(e: sqlx::Error) -> Self {
+        MergeError::Database(e)
+    }
+}
+
 #[derive(Builder, PartialEq, Debug)]
 pub struct Book {
     #[builder(default = 0)]
     pub id: i64,
     pub title: String,
+    /// Multiple authors are separated by ` & `, the way Calibre lists them.
     pub author: String,
     pub isbn: String,
     pub published_year: i32,
@@ -13,16 +56,51 @@ pub struct Book {
     pub description: Option<String>,
     #[builder(default= None)]
     pub cover_url: Option<String>,
-    pub pages: i32,
+    #[builder(default = BookFormat::Paperback)]
+    pub format: BookFormat,
+    /// Page count of the edition, `None` when unknown or for audiobooks.
+    #[builder(default = None)]
+    pub pages: Option<i32>,
+    /// Running time of an audiobook.
+    #[builder(default = None)]
+    pub duration_minutes: Option<i32>,
+    #[builder(default = None)]
+    pub word_count: Option<i32>,
+    /// The work this book is an edition of, if it has been grouped with others.
+    #[builder(default = None)]
+    pub work_id: Option<i64>,
 }
 
 impl Book {
+    /// The individual authors of the book.
+    pub fn authors(&self) -> Vec<&str> {
+        self.author
+            .split(" & ")
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .collect()
+    }
+
+    /// The length of the book as its readers measure it: the running time of
+    /// an audiobook, the pages of a printed book, and the pages or else the
+    /// word count of an ebook. `None` when that isn't known.
+    pub fn length(&self) -> Option<Length> {
+        match self.format {
+            BookFormat::Audiobook => self.duration_minutes.map(Length::Minutes),
+            BookFormat::Ebook => self
+                .pages
+                .map(Length::Pages)
+                .or(self.word_count.map(Length::Words)),
+            BookFormat::Hardcover | BookFormat::Paperback => self.pages.map(Length::Pages),
+        }
+    }
+
     /// Insert book into the DB
     pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
         let res = sqlx::query!(
             r#"
-            INSERT INTO books (title, author, isbn, published_year, description, cover_url, pages)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO books (title, author, isbn, published_year, description, cover_url, pages, work_id, format, duration_minutes, word_count)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
             RETURNING id
             "#,
             self.title,
@@ -32,6 +110,10 @@ impl Book {
             self.description,
             self.cover_url,
             self.pages,
+            self.work_id,
+            self.format as BookFormat,
+            self.duration_minutes,
+            self.word_count,
         )
         .fetch_one(pool)
         .await?;
@@ -44,7 +126,12 @@ impl Book {
     /// Update book into the DB. Returns the number of updated rows on Ok.
     pub async fn update(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
         let updated = sqlx::query!(
-            "UPDATE books SET title = $1, author = $2, isbn = $3, published_year = $4, description = $5, cover_url = $6, pages = $7 WHERE id = $8",
+            r#"
+            UPDATE books
+            SET title = $1, author = $2, isbn = $3, published_year = $4, description = $5, cover_url = $6,
+                pages = $7, work_id = $8, format = $9, duration_minutes = $10, word_count = $11
+            WHERE id = $12
+            "#,
             self.title,
             self.author,
             self.isbn,
@@ -52,6 +139,10 @@ impl Book {
             self.description,
             self.cover_url,
             self.pages,
+            self.work_id,
+            self.format as BookFormat,
+            self.duration_minutes,
+            self.word_count,
             self.id
         )
         .execute(pool)
@@ -64,7 +155,7 @@ impl Book {
     pub async fn fetch(&self, pool: &PgPool) -> Result<Self, sqlx::Error> {
         let record = sqlx::query_as!(
             Book,
-            "SELECT id, title, author, isbn, published_year, description, cover_url, pages FROM books WHERE id = $1",
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE id = $1"#,
             self.id
         )
         .fetch_one(pool)
@@ -76,7 +167,7 @@ impl Book {
     pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
         let record = sqlx::query_as!(
             Book,
-            "SELECT id, title, author, isbn, published_year, description, cover_url, pages  FROM books WHERE id = $1",
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE id = $1"#,
             id
         )
         .fetch_one(pool)
@@ -85,6 +176,277 @@ impl Book {
         Ok(record)
     }
 
+    /// Fetch the book with the given ISBN, if there is one.
+    pub async fn get_by_isbn(pool: &PgPool, isbn: &str) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Book,
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE isbn = $1"#,
+            isbn
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Fetch all books with the given title, ignoring case.
+    pub async fn find_by_title(pool: &PgPool, title: &str) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Book,
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE lower(title) = lower($1)"#,
+            title
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Fetch the books on a user's shelf that `viewer_id` can see, optionally
+    /// only those with the given reading status, most recently added first.
+    pub async fn on_shelf(
+        pool: &PgPool,
+        user_id: i64,
+        viewer_id: i64,
+        status: Option<ReadingStatus>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Book,
+            r#"
+            SELECT b.id, b.title, b.author, b.isbn, b.published_year, b.description, b.cover_url, b.pages, b.work_id,
+                   b.format as "format: BookFormat", b.duration_minutes, b.word_count
+            FROM books b
+            JOIN user_books ub ON ub.book_id = b.id
+            JOIN users u ON u.id = ub.user_id
+            WHERE ub.user_id = $1 AND ($3::reading_status IS NULL OR ub.status = $3)
+              AND visible_to(ub.user_id, $2, LEAST(u.visibility, ub.visibility))
+            ORDER BY ub.added_at DESC, b.id
+            "#,
+            user_id,
+            viewer_id,
+            status as Option<ReadingStatus>
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Fetch every book in the catalogue.
+    pub async fn all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Book,
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books ORDER BY id"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// The `n` books whose title, authors and description read most like
+    /// this book's, most similar first. Only books indexed by
+    /// `similar::reindex` are found, but this book needn't be.
+    pub async fn similar(&self, pool: &PgPool, n: usize) -> Result<Vec<Self>, sqlx::Error> {
+        let nearest = crate::similar::nearest(pool, self, n).await?;
+        let ids: Vec<i64> = nearest.iter().map(|&(id, _)| id).collect();
+        let mut records = sqlx::query_as!(
+            Book,
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE id = ANY($1)"#,
+            &ids
+        )
+        .fetch_all(pool)
+        .await?;
+        records.sort_by_key(|book| ids.iter().position(|&id| id == book.id));
+
+        Ok(records)
+    }
+
+    /// Merges this book into `other`, a duplicate of it, and deletes this book.
+    ///
+    /// Shelf entries with their highlights, library loans, reviews, progress
+    /// history and activities, KOReader documents, owned copies, club picks
+    /// and buddy reads move to `other`. A user with both books on their shelf
+    /// keeps the entry with the most advanced reading status and the stricter
+    /// visibility, and details missing on `other` are taken from this book.
+    /// `other` is refreshed from the database afterwards.
+    pub async fn merge_into(self, pool: &PgPool, other: &mut Book) -> Result<(), MergeError> {
+        if self.id == other.id {
+            return Err(MergeError::SameBook);
+        }
+        let mut tx = pool.begin().await?;
+
+        // Highlights reference shelf entries, so the entries are copied over
+        // before the highlights are moved and the old entries deleted
+        sqlx::query!(
+            r#"
+            INSERT INTO user_books (user_id, book_id, status, rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility)
+            SELECT user_id, $2, status, rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility
+            FROM user_books
+            WHERE book_id = $1
+            ON CONFLICT (user_id, book_id) DO UPDATE
+            SET status = GREATEST(user_books.status, EXCLUDED.status),
+                began_reading = CASE WHEN EXCLUDED.status > user_books.status
+                    THEN EXCLUDED.began_reading
+                    ELSE COALESCE(user_books.began_reading, EXCLUDED.began_reading) END,
+                done_reading = CASE WHEN EXCLUDED.status > user_books.status
+                    THEN EXCLUDED.done_reading
+                    ELSE COALESCE(user_books.done_reading, EXCLUDED.done_reading) END,
+                current_page = CASE WHEN EXCLUDED.status > user_books.status
+                    THEN EXCLUDED.current_page
+                    ELSE COALESCE(user_books.current_page, EXCLUDED.current_page) END,
+                current_seconds = CASE WHEN EXCLUDED.status > user_books.status
+                    THEN EXCLUDED.current_seconds
+                    ELSE COALESCE(user_books.current_seconds, EXCLUDED.current_seconds) END,
+                progress_percent = CASE WHEN EXCLUDED.status > user_books.status
+                    THEN EXCLUDED.progress_percent
+                    ELSE COALESCE(user_books.progress_percent, EXCLUDED.progress_percent) END,
+                rating = COALESCE(user_books.rating, EXCLUDED.rating),
+                added_at = LEAST(user_books.added_at, EXCLUDED.added_at),
+                visibility = LEAST(user_books.visibility, EXCLUDED.visibility)
+            "#,
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // The same highlight imported on both books is only kept once
+        sqlx::query!(
+            r#"
+            DELETE FROM highlights h
+            USING highlights o
+            WHERE h.book_id = $1 AND o.book_id = $2
+              AND o.user_id = h.user_id AND o.kind = h.kind
+              AND COALESCE(o.location, '') = COALESCE(h.location, '')
+              AND COALESCE(o.page, -1) = COALESCE(h.page, -1)
+              AND md5(COALESCE(o.quote, o.note, '')) = md5(COALESCE(h.quote, h.note, ''))
+            "#,
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE highlights SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        // A user only has one library loan per book, the one already on
+        // `other` is kept
+        sqlx::query!(
+            r#"
+            DELETE FROM library_loans l
+            USING library_loans o
+            WHERE l.book_id = $1 AND o.book_id = $2 AND o.user_id = l.user_id
+            "#,
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE library_loans SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        // Likewise for reviews
+        sqlx::query!(
+            r#"
+            DELETE FROM reviews r
+            USING reviews o
+            WHERE r.book_id = $1 AND o.book_id = $2 AND o.user_id = r.user_id
+            "#,
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE reviews SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE reading_progress SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE activities SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE book_documents SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE owned_copies SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE club_picks SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE buddy_reads SET book_id = $2 WHERE book_id = $1",
+            self.id,
+            other.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE books
+            SET description = COALESCE(description, $2),
+                cover_url = COALESCE(cover_url, $3),
+                pages = COALESCE(pages, $4),
+                work_id = COALESCE(work_id, $5),
+                duration_minutes = COALESCE(duration_minutes, $6),
+                word_count = COALESCE(word_count, $7)
+            WHERE id = $1
+            "#,
+            other.id,
+            self.description,
+            self.cover_url,
+            self.pages,
+            self.work_id,
+            self.duration_minutes,
+            self.word_count
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM books WHERE id = $1", self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        *other = other.fetch(pool).await?;
+
+        Ok(())
+    }
+
     /// Deletes the row associated with the record
     pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
         let deleted = sqlx::query_as!(Book, "DELETE FROM books WHERE id = $1", self.id)
@@ -98,7 +460,12 @@ impl Book {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::setup_db;
+    use crate::factories;
+    use crate::models::follow::Block;
+    use crate::models::highlight::{Highlight, HighlightBuilder};
+    use crate::models::user::Visibility;
+    use crate::models::user_book::UserBook;
+    use crate::test_utils::{create_fake_users, setup_db, shelf_by_visibility};
 
     #[tokio::test]
     async fn create_then_get_and_delete() {
@@ -111,7 +478,7 @@ mod tests {
             .published_year(2012)
             .description(Some("FYODOR MIKAILOVICH DOSTOEVSKY's life was as dark and dramatic as the great novels he wrote. He was born in Moscow in 1821. A short first novel, Poor Folk (1846) brought him instant success, but his writing career was cut short by his arrest for alleged subversion against Tsar Nicholas I in 1849. In prison he was given the “silent treatment” for eight months (guards even wore velvet soled boots) before he was led in front a firing squad. Dressed in a death shroud, he faced an open grave and awaited execution, when suddenly, an order arrived commuting his sentence. He then spent four years at hard labor in a Siberian prison, where he began to suffer from epilepsy, and he returned to St. Petersburg only a full ten years after he had left in chains.\n His prison experiences coupled with his conversion to a profoundly religious philosophy formed the basis for his great novels. But it was his fortuitous marriage to Anna Snitkina, following a period of utter destitution brought about by his compulsive gambling, that gave Dostoevsky the emotional stability to complete Crime and Punishment (1866), The Idiot (1868-69), The Possessed (1871-72),and The Brothers Karamazov (1879-80). When Dostoevsky died in 1881, he left a legacy of masterworks that influenced the great thinkers and writers of the Western world and immortalized him as a giant among writers of world literature.".to_string()))
             .cover_url(None)
-            .pages(592)
+            .pages(Some(592))
             .build()
             .unwrap();
 
@@ -130,7 +497,8 @@ mod tests {
         assert_eq!(
             Some("FYODOR MIKAILOVICH DOSTOEVSKY's life was as dark and dramatic as the great novels he wrote. He was born in Moscow in 1821. A short first novel, Poor Folk (1846) brought him instant success, but his writing career was cut short by his arrest for alleged subversion against Tsar Nicholas I in 1849. In prison he was given the “silent treatment” for eight months (guards even wore velvet soled boots) before he was led in front a firing squad. Dressed in a death shroud, he faced an open grave and awaited execution, when suddenly, an order arrived commuting his sentence. He then spent four years at hard labor in a Siberian prison, where he began to suffer from epilepsy, and he returned to St. Petersburg only a full ten years after he had left in chains.\n His prison experiences coupled with his conversion to a profoundly religious philosophy formed the basis for his great novels. But it was his fortuitous marriage to Anna Snitkina, following a period of utter destitution brought about by his compulsive gambling, that gave Dostoevsky the emotional stability to complete Crime and Punishment (1866), The Idiot (1868-69), The Possessed (1871-72),and The Brothers Karamazov (1879-80). When Dostoevsky died in 1881, he left a legacy of masterworks that influenced the great thinkers and writers of the Western world and immortalized him as a giant among writers of world literature.".to_string()), fetched.description
         );
-        assert_eq!(592, fetched.pages);
+        assert_eq!(Some(592), fetched.pages);
+        assert_eq!(BookFormat::Paperback, fetched.format);
 
         // Asserts the struct has the same values as the db
         assert_eq!(u.id, fetched.id);
@@ -156,7 +524,7 @@ mod tests {
             .isbn("9780307829604".to_string())
             .published_year(2012)
             .description(Some("FYODOR MIKAILOVICH DOSTOEVSKY's life was as dark and dramatic as the great novels he wrote. He was born in Moscow in 1821. A short first novel, Poor Folk (1846) brought him instant success, but his writing career was cut short by his arrest for alleged subversion against Tsar Nicholas I in 1849. In prison he was given the “silent treatment” for eight months (guards even wore velvet soled boots) before he was led in front a firing squad. Dressed in a death shroud, he faced an open grave and awaited execution, when suddenly, an order arrived commuting his sentence. He then spent four years at hard labor in a Siberian prison, where he began to suffer from epilepsy, and he returned to St. Petersburg only a full ten years after he had left in chains.\n His prison experiences coupled with his conversion to a profoundly religious philosophy formed the basis for his great novels. But it was his fortuitous marriage to Anna Snitkina, following a period of utter destitution brought about by his compulsive gambling, that gave Dostoevsky the emotional stability to complete Crime and Punishment (1866), The Idiot (1868-69), The Possessed (1871-72),and The Brothers Karamazov (1879-80). When Dostoevsky died in 1881, he left a legacy of masterworks that influenced the great thinkers and writers of the Western world and immortalized him as a giant among writers of world literature.".to_string()))
-            .pages(592)
+            .pages(Some(592))
             .build()
             .unwrap();
 
@@ -180,4 +548,234 @@ mod tests {
         assert_eq!(1, updated_rows);
         assert_eq!(1, deleted_rows);
     }
+
+    #[test]
+    fn length_depends_on_format() {
+        // Arrange
+        let mut book = factories::fake_book();
+        book.pages = Some(320);
+        book.duration_minutes = Some(610);
+        book.word_count = Some(90_000);
+
+        // Act
+        let lengths = [
+            BookFormat::Hardcover,
+            BookFormat::Paperback,
+            BookFormat::Ebook,
+            BookFormat::Audiobook,
+        ]
+        .map(|format| {
+            book.format = format;
+            book.length()
+        });
+        book.format = BookFormat::Ebook;
+        book.pages = None;
+        let ebook_without_pages = book.length();
+        book.format = BookFormat::Hardcover;
+        let unknown = book.length();
+
+        // Assert
+        assert_eq!(
+            [
+                Some(Length::Pages(320)),
+                Some(Length::Pages(320)),
+                Some(Length::Pages(320)),
+                Some(Length::Minutes(610)),
+            ],
+            lengths
+        );
+        assert_eq!(Some(Length::Words(90_000)), ebook_without_pages);
+        assert_eq!(None, unknown);
+    }
+
+    #[tokio::test]
+    async fn audiobook_round_trips() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut book = factories::fake_book();
+        book.format = BookFormat::Audiobook;
+        book.pages = None;
+        book.duration_minutes = Some(725);
+
+        // Act
+        book.create(&pool).await.unwrap();
+        let fetched = Book::get(&pool, book.id).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(book, fetched);
+        assert_eq!(Some(Length::Minutes(725)), fetched.length());
+    }
+
+    #[tokio::test]
+    async fn on_shelf_filters_by_status() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut reading = factories::fake_book();
+        reading.create(&pool).await.unwrap();
+        let mut to_read = factories::fake_book();
+        to_read.create(&pool).await.unwrap();
+
+        let mut reading_entry = factories::fake_user_book(user.id, reading.id);
+        reading_entry.status = ReadingStatus::Reading;
+        reading_entry.create(&pool).await.unwrap();
+        let mut to_read_entry = factories::fake_user_book(user.id, to_read.id);
+        to_read_entry.status = ReadingStatus::ToRead;
+        to_read_entry.create(&pool).await.unwrap();
+
+        // Act
+        let all = Book::on_shelf(&pool, user.id, user.id, None).await.unwrap();
+        let only_reading = Book::on_shelf(&pool, user.id, user.id, Some(ReadingStatus::Reading))
+            .await
+            .unwrap();
+
+        user.delete(&pool).await.unwrap();
+        reading.delete(&pool).await.unwrap();
+        to_read.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(2, all.len());
+        assert_eq!(vec![reading], only_reading);
+    }
+
+    #[tokio::test]
+    async fn on_shelf_hides_entries_from_other_users() {
+        // Arrange
+        let pool = setup_db().await;
+        let (mut owner_user, follower_user, stranger_user, books) =
+            shelf_by_visibility(&pool).await;
+        let (owner, follower, stranger) = (owner_user.id, follower_user.id, stranger_user.id);
+        let ids = |shelf: Vec<Book>| shelf.iter().map(|b| b.id).collect::<Vec<_>>();
+
+        // Act
+        let as_owner = ids(Book::on_shelf(&pool, owner, owner, None).await.unwrap());
+        let as_follower = ids(Book::on_shelf(&pool, owner, follower, None).await.unwrap());
+        let as_stranger = ids(Book::on_shelf(&pool, owner, stranger, None).await.unwrap());
+        owner_user.visibility = Visibility::Followers;
+        owner_user.update(&pool).await.unwrap();
+        let stranger_after_profile_hidden =
+            ids(Book::on_shelf(&pool, owner, stranger, None).await.unwrap());
+        Block {
+            blocker_id: owner,
+            blocked_id: follower,
+        }
+        .create(&pool)
+        .await
+        .unwrap();
+        let follower_after_block = ids(Book::on_shelf(&pool, owner, follower, None).await.unwrap());
+
+        // Cleanup
+        for user in [&owner_user, &follower_user, &stranger_user] {
+            user.delete(&pool).await.unwrap();
+        }
+        for book in &books {
+            book.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert_eq!(vec![books[2].id, books[1].id, books[0].id], as_owner);
+        assert_eq!(vec![books[1].id, books[0].id], as_follower);
+        assert_eq!(vec![books[0].id], as_stranger);
+        assert!(stranger_after_profile_hidden.is_empty());
+        assert!(follower_after_block.is_empty());
+    }
+
+    #[tokio::test]
+    async fn merge_into_keeps_most_advanced_status() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut duplicate = factories::fake_book();
+        duplicate.create(&pool).await.unwrap();
+        let mut book = factories::fake_book();
+        book.description = None;
+        book.create(&pool).await.unwrap();
+        let duplicate_id = duplicate.id;
+        let description = duplicate.description.clone();
+
+        let users = create_fake_users(&pool, 3).await;
+        // (user, book, status, rating)
+        let entries = [
+            (0, duplicate.id, ReadingStatus::Completed, Some(9)),
+            (0, book.id, ReadingStatus::Reading, None),
+            (1, duplicate.id, ReadingStatus::ToRead, Some(4)),
+            (2, duplicate.id, ReadingStatus::ToRead, None),
+            (2, book.id, ReadingStatus::Completed, Some(7)),
+        ];
+        for (user, book_id, status, rating) in entries {
+            let mut entry = factories::fake_user_book(users[user].id, book_id);
+            entry.status = status;
+            entry.rating = rating;
+            entry.create(&pool).await.unwrap();
+        }
+        let mut highlight = HighlightBuilder::default()
+            .user_id(users[0].id)
+            .book_id(duplicate.id)
+            .quote(Some("A quote".to_string()))
+            .build()
+            .unwrap();
+        highlight.create(&pool).await.unwrap();
+
+        // Act
+        duplicate.merge_into(&pool, &mut book).await.unwrap();
+        let mut merged = Vec::new();
+        for user in &users {
+            merged.push(UserBook::get(&pool, book.id, user.id).await.unwrap());
+        }
+        let gone = Book::get(&pool, duplicate_id).await;
+        let moved = Highlight::get(&pool, highlight.id, users[0].id)
+            .await
+            .unwrap();
+
+        // Cleanup
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(ReadingStatus::Completed, merged[0].status);
+        assert_eq!(Some(9), merged[0].rating);
+        assert_eq!(ReadingStatus::ToRead, merged[1].status);
+        assert_eq!(Some(4), merged[1].rating);
+        assert_eq!(ReadingStatus::Completed, merged[2].status);
+        assert_eq!(Some(7), merged[2].rating);
+        assert!(matches!(gone, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(book.id, moved.book_id);
+        assert_eq!(description, book.description);
+    }
+
+    #[tokio::test]
+    async fn merge_into_itself_is_refused() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut book = factories::fake_book();
+        book.create(&pool).await.unwrap();
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        factories::fake_user_book(user.id, book.id)
+            .create(&pool)
+            .await
+            .unwrap();
+        let mut same = Book::get(&pool, book.id).await.unwrap();
+
+        // Act
+        let merged = Book::get(&pool, book.id)
+            .await
+            .unwrap()
+            .merge_into(&pool, &mut same)
+            .await;
+        let kept = Book::get(&pool, book.id).await;
+        let entry = UserBook::get(&pool, book.id, user.id).await;
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(merged, Err(MergeError::SameBook)));
+        assert!(kept.is_ok());
+        assert!(entry.is_ok());
+    }
 }
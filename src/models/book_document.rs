@@ -0,0 +1,133 @@
+// Links a document, identified the way KOReader identifies it, to a book so
+// that progress reported for the document can be attributed to the book.
+
+use crate::models::book::Book;
+use md5::{Digest, Md5};
+use sqlx::PgPool;
+
+#[derive(Debug, PartialEq)]
+pub struct BookDocument {
+    pub document: String,
+    pub book_id: i64,
+}
+
+impl BookDocument {
+    pub fn new(document: String, book_id: i64) -> Self {
+        Self { document, book_id }
+    }
+
+    /// Inserts the mapping, or points an already known document at this book.
+    pub async fn create(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO book_documents (document, book_id) VALUES ($1, $2)
+            ON CONFLICT (document) DO UPDATE SET book_id = EXCLUDED.book_id
+            "#,
+            self.document,
+            self.book_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the mapping for a document hash.
+    pub async fn get(pool: &PgPool, document: &str) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            BookDocument,
+            "SELECT document, book_id FROM book_documents WHERE document = $1",
+            document
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Deletes the mapping.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM book_documents WHERE document = $1",
+            self.document
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+
+    /// Gets the book the document belongs to.
+    pub async fn get_book(&self, pool: &PgPool) -> Result<Book, sqlx::Error> {
+        Book::get(pool, self.book_id).await
+    }
+}
+
+/// Computes KOReader's "partial MD5" document hash of a file's contents.
+///
+/// Rather than hashing the entire file, KOReader hashes 1 KiB samples taken at
+/// exponentially growing offsets, which is what devices send as `document`.
+pub fn koreader_partial_md5(contents: &[u8]) -> String {
+    const STEP: usize = 1024;
+    let mut hasher = Md5::new();
+
+    for i in -1..=10 {
+        let offset = if i < 0 { 0 } else { STEP << (2 * i) };
+        if offset >= contents.len() {
+            break;
+        }
+        let end = usize::min(offset + STEP, contents.len());
+        hasher.update(&contents[offset..end]);
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories;
+    use crate::test_utils::setup_db;
+
+    #[test]
+    fn partial_md5_only_hashes_samples() {
+        // Arrange
+        let small = vec![7u8; 1024];
+        let large = vec![7u8; 4096];
+        // Bytes 0..2048 are sampled for a 4 KiB file, the rest is skipped
+        let mut unsampled_changed = large.clone();
+        unsampled_changed[3000] = 8;
+        let mut sampled_changed = large.clone();
+        sampled_changed[1500] = 8;
+
+        // Act
+        let small_hash = koreader_partial_md5(&small);
+        let large_hash = koreader_partial_md5(&large);
+
+        // Assert
+        assert_eq!(hex::encode(Md5::digest(&small)), small_hash);
+        assert_eq!(large_hash, koreader_partial_md5(&unsampled_changed));
+        assert_ne!(large_hash, koreader_partial_md5(&sampled_changed));
+    }
+
+    #[tokio::test]
+    async fn create_then_get_and_delete() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut book = factories::fake_book();
+        book.create(&pool).await.unwrap();
+        let document = BookDocument::new(koreader_partial_md5(book.isbn.as_bytes()), book.id);
+
+        // Act
+        document.create(&pool).await.unwrap();
+        let fetched = BookDocument::get(&pool, &document.document).await.unwrap();
+        let fetched_book = fetched.get_book(&pool).await.unwrap();
+        let deleted_rows = document.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(document, fetched);
+        assert_eq!(book, fetched_book);
+        assert_eq!(1, deleted_rows);
+    }
+}
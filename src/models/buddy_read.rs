@@ -0,0 +1,522 @@
+// Buddy reads: two or three friends reading a book together, following each
+// other's progress and leaving comments on pages. A comment stays hidden
+// from a reader until they've reached its page.
+
+use crate::models::user_book::UserBook;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Most readers in a buddy read.
+pub const MAX_READERS: i64 = 3;
+
+#[derive(Debug)]
+pub enum BuddyReadError {
+    /// Only friends of a reader can be invited.
+    NotFriends,
+    /// The buddy read has `MAX_READERS` readers already.
+    Full,
+    /// The user isn't one of the readers.
+    NotMember,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for BuddyReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuddyReadError::NotFriends => write!(f, "only friends can be invited"),
+            BuddyReadError::Full => write!(f, "the buddy read is full"),
+            BuddyReadError::NotMember => write!(f, "the user isn't reading along"),
+            BuddyReadError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BuddyReadError {}
+
+impl From<sqlx::Error> for BuddyReadError {
+    fn from(e: sqlx::Error) -> Self {
+        BuddyReadError::Database(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BuddyRead {
+    pub id: i64,
+    pub book_id: i64,
+    /// `None` once the user who started it deleted their account.
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A reader and the page they're on, `None` when their shelf entry is
+/// hidden from the one asking or has no position.
+#[derive(Debug, PartialEq)]
+pub struct BuddyReader {
+    pub user_id: i64,
+    pub current_page: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BuddyComment {
+    pub id: i64,
+    pub buddy_read_id: i64,
+    pub user_id: i64,
+    pub page: i32,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The comments a reader can see, and how many are further on in the book.
+#[derive(Debug, PartialEq)]
+pub struct BuddyThread {
+    pub comments: Vec<BuddyComment>,
+    pub hidden: i64,
+}
+
+impl BuddyRead {
+    /// Starts a buddy read of `book_id` by `user_id`, putting the book on
+    /// their shelf if it isn't yet.
+    pub async fn start(pool: &PgPool, user_id: i64, book_id: i64) -> Result<Self, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        UserBook::get_or_create_in(&mut tx, book_id, user_id).await?;
+        let record = sqlx::query_as!(
+            BuddyRead,
+            r#"
+            INSERT INTO buddy_reads (book_id, created_by)
+            VALUES ($1, $2)
+            RETURNING id, book_id, created_by, created_at
+            "#,
+            book_id,
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO buddy_read_members (buddy_read_id, user_id) VALUES ($1, $2)",
+            record.id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(record)
+    }
+
+    /// Fetch a buddy read by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            BuddyRead,
+            "SELECT id, book_id, created_by, created_at FROM buddy_reads WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The buddy reads `user_id` is reading along in, newest first.
+    pub async fn for_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            BuddyRead,
+            r#"
+            SELECT b.id, b.book_id, b.created_by, b.created_at
+            FROM buddy_reads b
+            JOIN buddy_read_members m ON m.buddy_read_id = b.id
+            WHERE m.user_id = $1
+            ORDER BY b.created_at DESC, b.id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Adds `user_id`, a friend of `by`, to the readers, and puts the book
+    /// on their shelf if it isn't yet. `by` has to be a reader already.
+    pub async fn invite(&self, pool: &PgPool, by: i64, user_id: i64) -> Result<(), BuddyReadError> {
+        self.check_member(pool, by).await?;
+        let friends = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM follows f
+                JOIN follows back ON back.follower_id = f.followee_id AND back.followee_id = f.follower_id
+                WHERE f.follower_id = $1 AND f.followee_id = $2
+                  AND f.status = 'accepted' AND back.status = 'accepted'
+            ) AS "friends!"
+            "#,
+            by,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+        if !friends {
+            return Err(BuddyReadError::NotFriends);
+        }
+        if self.check_member(pool, user_id).await.is_ok() {
+            return Ok(());
+        }
+
+        // Invitations to the same buddy read wait for each other here, so
+        // they can't both see room for one more reader
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "SELECT id FROM buddy_reads WHERE id = $1 FOR UPDATE",
+            self.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO buddy_read_members (buddy_read_id, user_id)
+            SELECT $1, $2
+            WHERE (SELECT COUNT(*) FROM buddy_read_members WHERE buddy_read_id = $1) < $3
+            ON CONFLICT DO NOTHING
+            "#,
+            self.id,
+            user_id,
+            MAX_READERS
+        )
+        .execute(&mut *tx)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Err(BuddyReadError::Full);
+        }
+        UserBook::get_or_create_in(&mut tx, self.book_id, user_id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Stops `user_id` reading along. Their comments stay. Returns whether
+    /// they were a reader.
+    pub async fn leave(&self, pool: &PgPool, user_id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM buddy_read_members WHERE buddy_read_id = $1 AND user_id = $2",
+            self.id,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// The readers and the page each is on as `viewer_id` sees it, furthest
+    /// first.
+    pub async fn readers(
+        &self,
+        pool: &PgPool,
+        viewer_id: i64,
+    ) -> Result<Vec<BuddyReader>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            BuddyReader,
+            r#"
+            SELECT m.user_id,
+                   CASE WHEN visible_to(m.user_id, $2, LEAST(u.visibility, ub.visibility))
+                        THEN reading_page(m.user_id, $3) END AS current_page
+            FROM buddy_read_members m
+            JOIN users u ON u.id = m.user_id
+            LEFT JOIN user_books ub ON ub.user_id = m.user_id AND ub.book_id = $3
+            WHERE m.buddy_read_id = $1
+            ORDER BY 2 DESC NULLS LAST, m.joined_at, m.user_id
+            "#,
+            self.id,
+            viewer_id,
+            self.book_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Leaves a comment on `page` by `user_id`, one of the readers.
+    pub async fn comment(
+        &self,
+        pool: &PgPool,
+        user_id: i64,
+        page: i32,
+        body: &str,
+    ) -> Result<BuddyComment, BuddyReadError> {
+        self.check_member(pool, user_id).await?;
+        let record = sqlx::query_as!(
+            BuddyComment,
+            r#"
+            INSERT INTO buddy_comments (buddy_read_id, user_id, page, body)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, buddy_read_id, user_id, page, body, created_at
+            "#,
+            self.id,
+            user_id,
+            page,
+            body
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The comments `viewer_id`, one of the readers, can see, in page order:
+    /// their own and the others' up to the page they're on. A reader who
+    /// finished the book sees every comment, even when it has no pages.
+    pub async fn comments(
+        &self,
+        pool: &PgPool,
+        viewer_id: i64,
+    ) -> Result<BuddyThread, BuddyReadError> {
+        self.check_member(pool, viewer_id).await?;
+        let reading = sqlx::query!(
+            r#"
+            SELECT reading_page($1, $2) AS page,
+                   EXISTS (
+                       SELECT 1 FROM user_books
+                       WHERE user_id = $1 AND book_id = $2 AND status = 'completed'
+                   ) AS "completed!"
+            "#,
+            viewer_id,
+            self.book_id
+        )
+        .fetch_one(pool)
+        .await?;
+        let page = match reading.page {
+            _ if reading.completed => i32::MAX,
+            page => page.unwrap_or(0),
+        };
+
+        let comments = sqlx::query_as!(
+            BuddyComment,
+            r#"
+            SELECT id, buddy_read_id, user_id, page, body, created_at
+            FROM buddy_comments
+            WHERE buddy_read_id = $1 AND (page <= $2 OR user_id = $3)
+            ORDER BY page, created_at, id
+            "#,
+            self.id,
+            page,
+            viewer_id
+        )
+        .fetch_all(pool)
+        .await?;
+        let hidden = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "hidden!"
+            FROM buddy_comments
+            WHERE buddy_read_id = $1 AND page > $2 AND user_id <> $3
+            "#,
+            self.id,
+            page,
+            viewer_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(BuddyThread { comments, hidden })
+    }
+
+    /// Deletes the row associated with the record, with its comments.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM buddy_reads WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+
+    async fn check_member(&self, pool: &PgPool, user_id: i64) -> Result<(), BuddyReadError> {
+        let member = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM buddy_read_members WHERE buddy_read_id = $1 AND user_id = $2
+            ) AS "member!"
+            "#,
+            self.id,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        if member {
+            Ok(())
+        } else {
+            Err(BuddyReadError::NotMember)
+        }
+    }
+}
+
+impl BuddyComment {
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM buddy_comments WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::fake_book;
+    use crate::models::activity::{Activity, ActivityKind};
+    use crate::models::follow::Follow;
+    use crate::models::user_book::ReadingStatus;
+    use crate::test_utils::{create_fake_users, setup_db};
+
+    async fn friends(pool: &PgPool, a: i64, b: i64) {
+        Follow::request(pool, a, b).await.unwrap();
+        Follow::request(pool, b, a).await.unwrap();
+    }
+
+    async fn read_to(pool: &PgPool, user_id: i64, book_id: i64, page: i32) {
+        let mut entry = UserBook::get(pool, book_id, user_id).await.unwrap();
+        entry.status = ReadingStatus::Reading;
+        entry.current_page = Some(page);
+        entry.update(pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn friends_read_along_and_comments_wait_for_them() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 5).await;
+        let [anna, ben, cleo, dan, stranger] = [
+            users[0].id,
+            users[1].id,
+            users[2].id,
+            users[3].id,
+            users[4].id,
+        ];
+        for friend in [ben, cleo, dan] {
+            friends(&pool, anna, friend).await;
+        }
+        let mut book = fake_book();
+        book.pages = Some(320);
+        book.create(&pool).await.unwrap();
+
+        // Act
+        let read = BuddyRead::start(&pool, anna, book.id).await.unwrap();
+        let not_friends = read.invite(&pool, anna, stranger).await;
+        let not_member = read.invite(&pool, ben, cleo).await;
+        read.invite(&pool, anna, ben).await.unwrap();
+        read.invite(&pool, anna, cleo).await.unwrap();
+        let full = read.invite(&pool, anna, dan).await;
+        read_to(&pool, anna, book.id, 120).await;
+        read_to(&pool, ben, book.id, 40).await;
+        let readers = read.readers(&pool, ben).await.unwrap();
+
+        let early = read
+            .comment(&pool, anna, 30, "Lovely opening")
+            .await
+            .unwrap();
+        let later = read.comment(&pool, anna, 110, "No way!").await.unwrap();
+        let own_ahead = read
+            .comment(&pool, ben, 200, "Guessing the twist")
+            .await
+            .unwrap();
+        let ben_sees = read.comments(&pool, ben).await.unwrap();
+        let cleo_sees = read.comments(&pool, cleo).await.unwrap();
+        read_to(&pool, ben, book.id, 150).await;
+        let ben_caught_up = read.comments(&pool, ben).await.unwrap();
+        let outsider = read.comments(&pool, dan).await;
+        let reads = BuddyRead::for_user(&pool, cleo).await.unwrap();
+        let cleos_activities = Activity::for_user(&pool, cleo, cleo).await.unwrap();
+
+        // Cleanup
+        read.delete(&pool).await.unwrap();
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(not_friends, Err(BuddyReadError::NotFriends)));
+        assert!(matches!(not_member, Err(BuddyReadError::NotMember)));
+        assert!(matches!(full, Err(BuddyReadError::Full)));
+        assert_eq!(
+            vec![
+                BuddyReader {
+                    user_id: anna,
+                    current_page: Some(120),
+                },
+                BuddyReader {
+                    user_id: ben,
+                    current_page: Some(40),
+                },
+                BuddyReader {
+                    user_id: cleo,
+                    current_page: None,
+                },
+            ],
+            readers
+        );
+        assert_eq!(
+            BuddyThread {
+                comments: vec![early.clone(), own_ahead.clone()],
+                hidden: 1,
+            },
+            ben_sees
+        );
+        assert_eq!(
+            BuddyThread {
+                comments: vec![],
+                hidden: 3,
+            },
+            cleo_sees
+        );
+        assert_eq!(vec![early, later, own_ahead], ben_caught_up.comments);
+        assert!(matches!(outsider, Err(BuddyReadError::NotMember)));
+        assert_eq!(vec![read], reads);
+        assert_eq!(
+            vec![ActivityKind::Added],
+            cleos_activities.iter().map(|a| a.kind).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn finished_readers_see_every_comment() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 2).await;
+        let (anna, ben) = (users[0].id, users[1].id);
+        friends(&pool, anna, ben).await;
+        let mut book = fake_book();
+        book.pages = None;
+        book.create(&pool).await.unwrap();
+        let read = BuddyRead::start(&pool, anna, book.id).await.unwrap();
+        read.invite(&pool, anna, ben).await.unwrap();
+        let comment = read
+            .comment(&pool, anna, 250, "What an ending")
+            .await
+            .unwrap();
+        let mut entry = UserBook::get(&pool, book.id, ben).await.unwrap();
+        entry.status = ReadingStatus::Completed;
+        entry.current_page = None;
+        entry.progress_percent = None;
+        entry.update(&pool).await.unwrap();
+
+        // Act
+        let ben_sees = read.comments(&pool, ben).await.unwrap();
+
+        // Cleanup
+        read.delete(&pool).await.unwrap();
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            BuddyThread {
+                comments: vec![comment],
+                hidden: 0,
+            },
+            ben_sees
+        );
+    }
+}
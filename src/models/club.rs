@@ -0,0 +1,887 @@
+// Reading clubs. Members read the club's picks along a schedule of
+// milestones, and discuss each milestone once they've read up to it.
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use derive_builder::Builder;
+use sqlx::{PgConnection, PgPool};
+
+/// What a member may do in a club. Ordered from least to most rights.
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "club_role", rename_all = "lowercase")]
+pub enum ClubRole {
+    /// Reads along and posts in the threads.
+    Member,
+    /// Adds members, picks and milestones as well.
+    Moderator,
+    /// Sets the roles of the other members as well.
+    Owner,
+}
+
+#[derive(Debug)]
+pub enum ClubError {
+    /// The user isn't a member of the club.
+    NotMember,
+    /// The user's role doesn't allow it.
+    NotAllowed,
+    /// The user is the club's only owner, and stepping down would leave it
+    /// without one.
+    LastOwner,
+    /// The user hasn't read up to the milestone yet, so its thread would
+    /// spoil the book for them.
+    NotReached {
+        end_page: i32,
+    },
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for ClubError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClubError::NotMember => write!(f, "the user isn't a member of the club"),
+            ClubError::NotAllowed => write!(f, "the user's role doesn't allow this"),
+            ClubError::LastOwner => write!(f, "the club's last owner can't step down"),
+            ClubError::NotReached { end_page } => {
+                write!(f, "the thread is hidden until page {end_page} is read")
+            }
+            ClubError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClubError {}
+
+impl From<sqlx::Error> for ClubError {
+    fn from(e: sqlx::Error) -> Self {
+        ClubError::Database(e)
+    }
+}
+
+#[derive(Debug, Builder, PartialEq, Clone)]
+pub struct Club {
+    #[builder(default = 0)]
+    pub id: i64,
+    pub name: String,
+    #[builder(default = None)]
+    pub description: Option<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClubMember {
+    pub club_id: i64,
+    pub user_id: i64,
+    pub role: ClubRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+/// A book a club reads together.
+#[derive(Debug, Builder, PartialEq, Clone)]
+pub struct ClubPick {
+    #[builder(default = 0)]
+    pub id: i64,
+    pub club_id: i64,
+    pub book_id: i64,
+    #[builder(default = Utc::now().date_naive())]
+    pub starts_on: NaiveDate,
+}
+
+/// A point on a pick's schedule, to be read up to by a date.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Milestone {
+    pub id: i64,
+    pub pick_id: i64,
+    /// What is read by the milestone, e.g. "Chapters 1-5".
+    pub label: String,
+    pub end_page: i32,
+    pub due_on: NaiveDate,
+}
+
+/// A post in the discussion thread of a milestone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClubPost {
+    pub id: i64,
+    pub milestone_id: i64,
+    pub user_id: i64,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How far a member is into a pick, from their shelf entry for the book.
+/// `None` where the entry is hidden from the one asking.
+#[derive(Debug, PartialEq)]
+pub struct MemberProgress {
+    pub user_id: i64,
+    pub current_page: Option<i32>,
+    pub milestones_reached: Option<i64>,
+    /// Whether a milestone due before today hasn't been reached.
+    pub behind: Option<bool>,
+}
+
+impl Club {
+    /// Insert the club into the DB, with `owner_id` as its owner.
+    pub async fn create(&mut self, pool: &PgPool, owner_id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let res = sqlx::query!(
+            "INSERT INTO clubs (name, description, created_at) VALUES ($1, $2, $3) RETURNING id",
+            self.name,
+            self.description,
+            self.created_at
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO club_members (club_id, user_id, role) VALUES ($1, $2, 'owner')",
+            res.id,
+            owner_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.id = res.id;
+
+        Ok(())
+    }
+
+    /// Update the club in the DB. Returns the number of updated rows on Ok.
+    pub async fn update(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let updated = sqlx::query!(
+            "UPDATE clubs SET name = $1, description = $2 WHERE id = $3",
+            self.name,
+            self.description,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected())
+    }
+
+    /// Fetch a club by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Club,
+            "SELECT id, name, description, created_at FROM clubs WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The clubs `user_id` is a member of, by name.
+    pub async fn for_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Club,
+            r#"
+            SELECT c.id, c.name, c.description, c.created_at
+            FROM clubs c
+            JOIN club_members m ON m.club_id = c.id
+            WHERE m.user_id = $1
+            ORDER BY c.name, c.id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// The role of `user_id` in the club, `None` if they aren't a member.
+    pub async fn role(&self, pool: &PgPool, user_id: i64) -> Result<Option<ClubRole>, sqlx::Error> {
+        role(pool, self.id, user_id).await
+    }
+
+    /// The members, owners first and then by when they joined.
+    pub async fn members(&self, pool: &PgPool) -> Result<Vec<ClubMember>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ClubMember,
+            r#"
+            SELECT club_id, user_id, role as "role: ClubRole", joined_at
+            FROM club_members
+            WHERE club_id = $1
+            ORDER BY role DESC, joined_at, user_id
+            "#,
+            self.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Adds `user_id` to the club with `role`, on behalf of `by`. Moderators
+    /// add members, and only owners add moderators and owners. Adding a
+    /// member twice keeps their role.
+    pub async fn add_member(
+        &self,
+        pool: &PgPool,
+        by: i64,
+        user_id: i64,
+        role: ClubRole,
+    ) -> Result<(), ClubError> {
+        let least = match role {
+            ClubRole::Member => ClubRole::Moderator,
+            ClubRole::Moderator | ClubRole::Owner => ClubRole::Owner,
+        };
+        require(pool, self.id, by, least).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO club_members (club_id, user_id, role)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            self.id,
+            user_id,
+            role as ClubRole
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Changes the role of a member, on behalf of `by`, who has to be an
+    /// owner. The last owner can't be given another role. Returns whether
+    /// `user_id` is a member.
+    pub async fn set_role(
+        &self,
+        pool: &PgPool,
+        by: i64,
+        user_id: i64,
+        role: ClubRole,
+    ) -> Result<bool, ClubError> {
+        require(pool, self.id, by, ClubRole::Owner).await?;
+        let mut tx = pool.begin().await?;
+        if role != ClubRole::Owner {
+            self.check_not_last_owner(&mut tx, user_id).await?;
+        }
+        let updated = sqlx::query!(
+            "UPDATE club_members SET role = $1 WHERE club_id = $2 AND user_id = $3",
+            role as ClubRole,
+            self.id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(updated.rows_affected() > 0)
+    }
+
+    /// Removes `user_id` from the club, on behalf of `by`. Members may leave
+    /// on their own, except for the last owner, others are removed by
+    /// someone with a higher role. Returns whether `user_id` was a member.
+    pub async fn remove_member(
+        &self,
+        pool: &PgPool,
+        by: i64,
+        user_id: i64,
+    ) -> Result<bool, ClubError> {
+        if by != user_id {
+            let by_role = require(pool, self.id, by, ClubRole::Moderator).await?;
+            if role(pool, self.id, user_id).await? >= Some(by_role) {
+                return Err(ClubError::NotAllowed);
+            }
+        }
+        let mut tx = pool.begin().await?;
+        self.check_not_last_owner(&mut tx, user_id).await?;
+        let deleted = sqlx::query!(
+            "DELETE FROM club_members WHERE club_id = $1 AND user_id = $2",
+            self.id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// Checks that the club keeps an owner without `user_id`. Locks the club
+    /// until the transaction ends, so two owners can't step down at once.
+    async fn check_not_last_owner(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i64,
+    ) -> Result<(), ClubError> {
+        sqlx::query!("SELECT id FROM clubs WHERE id = $1 FOR UPDATE", self.id)
+            .fetch_one(&mut *conn)
+            .await?;
+        let last = sqlx::query_scalar!(
+            r#"
+            SELECT NOT EXISTS (
+                SELECT 1 FROM club_members
+                WHERE club_id = $1 AND user_id <> $2 AND role = 'owner'
+            ) AND EXISTS (
+                SELECT 1 FROM club_members
+                WHERE club_id = $1 AND user_id = $2 AND role = 'owner'
+            ) AS "last!"
+            "#,
+            self.id,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+        if last {
+            return Err(ClubError::LastOwner);
+        }
+
+        Ok(())
+    }
+
+    /// The club's picks, the latest started first.
+    pub async fn picks(&self, pool: &PgPool) -> Result<Vec<ClubPick>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ClubPick,
+            r#"
+            SELECT id, club_id, book_id, starts_on
+            FROM club_picks
+            WHERE club_id = $1
+            ORDER BY starts_on DESC, id DESC
+            "#,
+            self.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Deletes the row associated with the record, along with its members,
+    /// picks and threads.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM clubs WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+impl ClubPick {
+    /// Insert the pick into the DB, on behalf of `by`, a moderator or owner
+    /// of the club.
+    pub async fn create(&mut self, pool: &PgPool, by: i64) -> Result<(), ClubError> {
+        require(pool, self.club_id, by, ClubRole::Moderator).await?;
+        let res = sqlx::query!(
+            "INSERT INTO club_picks (club_id, book_id, starts_on) VALUES ($1, $2, $3) RETURNING id",
+            self.club_id,
+            self.book_id,
+            self.starts_on
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.id = res.id;
+
+        Ok(())
+    }
+
+    /// Fetch a pick by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            ClubPick,
+            "SELECT id, club_id, book_id, starts_on FROM club_picks WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Adds a milestone to the schedule, on behalf of `by`, a moderator or
+    /// owner of the club.
+    pub async fn add_milestone(
+        &self,
+        pool: &PgPool,
+        by: i64,
+        label: &str,
+        end_page: i32,
+        due_on: NaiveDate,
+    ) -> Result<Milestone, ClubError> {
+        require(pool, self.club_id, by, ClubRole::Moderator).await?;
+        let record = sqlx::query_as!(
+            Milestone,
+            r#"
+            INSERT INTO milestones (pick_id, label, end_page, due_on)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, pick_id, label, end_page, due_on
+            "#,
+            self.id,
+            label,
+            end_page,
+            due_on
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The milestones of the pick, in reading order.
+    pub async fn schedule(&self, pool: &PgPool) -> Result<Vec<Milestone>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Milestone,
+            r#"
+            SELECT id, pick_id, label, end_page, due_on
+            FROM milestones
+            WHERE pick_id = $1
+            ORDER BY end_page
+            "#,
+            self.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// The progress of each member in the book as `viewer_id` sees it,
+    /// furthest first. A member's page is their current page, or where
+    /// their percentage falls in the book, or the last page once they've
+    /// completed it.
+    pub async fn progress(
+        &self,
+        pool: &PgPool,
+        viewer_id: i64,
+        today: NaiveDate,
+    ) -> Result<Vec<MemberProgress>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            MemberProgress,
+            r#"
+            WITH pages AS (
+                SELECT m.user_id,
+                       ub.user_id IS NULL OR visible_to(m.user_id, $2, LEAST(u.visibility, ub.visibility)) AS shown,
+                       reading_page(m.user_id, p.book_id) AS page
+                FROM club_picks p
+                JOIN club_members m ON m.club_id = p.club_id
+                JOIN users u ON u.id = m.user_id
+                LEFT JOIN user_books ub ON ub.user_id = m.user_id AND ub.book_id = p.book_id
+                WHERE p.id = $1
+            )
+            SELECT user_id AS "user_id!",
+                   CASE WHEN shown THEN page END AS current_page,
+                   CASE WHEN shown THEN (
+                       SELECT COUNT(*) FROM milestones ms
+                       WHERE ms.pick_id = $1 AND ms.end_page <= COALESCE(page, 0)
+                   ) END AS milestones_reached,
+                   CASE WHEN shown THEN EXISTS (
+                       SELECT 1 FROM milestones ms
+                       WHERE ms.pick_id = $1 AND ms.due_on < $3 AND ms.end_page > COALESCE(page, 0)
+                   ) END AS behind
+            FROM pages
+            ORDER BY CASE WHEN shown THEN page END DESC NULLS LAST, user_id
+            "#,
+            self.id,
+            viewer_id,
+            today
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM club_picks WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+impl Milestone {
+    /// Fetch a milestone by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Milestone,
+            "SELECT id, pick_id, label, end_page, due_on FROM milestones WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Posts in the milestone's thread. The user has to be a member who has
+    /// read up to the milestone.
+    pub async fn post(
+        &self,
+        pool: &PgPool,
+        user_id: i64,
+        body: &str,
+    ) -> Result<ClubPost, ClubError> {
+        self.check_reached(pool, user_id).await?;
+        let record = sqlx::query_as!(
+            ClubPost,
+            r#"
+            INSERT INTO club_posts (milestone_id, user_id, body)
+            VALUES ($1, $2, $3)
+            RETURNING id, milestone_id, user_id, body, created_at
+            "#,
+            self.id,
+            user_id,
+            body
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The posts of the milestone's thread, oldest first. Fails with
+    /// `ClubError::NotReached` until `viewer_id` has read up to the milestone.
+    pub async fn thread(&self, pool: &PgPool, viewer_id: i64) -> Result<Vec<ClubPost>, ClubError> {
+        self.check_reached(pool, viewer_id).await?;
+        let records = sqlx::query_as!(
+            ClubPost,
+            r#"
+            SELECT id, milestone_id, user_id, body, created_at
+            FROM club_posts
+            WHERE milestone_id = $1
+            ORDER BY created_at, id
+            "#,
+            self.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Checks that `user_id` is a member of the club who has read up to
+    /// the milestone. A member who finished the book has reached every
+    /// milestone, even when neither the book nor their entry has a page.
+    async fn check_reached(&self, pool: &PgPool, user_id: i64) -> Result<(), ClubError> {
+        let pick = ClubPick::get(pool, self.pick_id).await?;
+        require(pool, pick.club_id, user_id, ClubRole::Member).await?;
+        let reading = sqlx::query!(
+            r#"
+            SELECT reading_page($1, $2) AS page,
+                   EXISTS (
+                       SELECT 1 FROM user_books
+                       WHERE user_id = $1 AND book_id = $2 AND status = 'completed'
+                   ) AS "completed!"
+            "#,
+            user_id,
+            pick.book_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        match reading.page {
+            _ if reading.completed => Ok(()),
+            Some(page) if page >= self.end_page => Ok(()),
+            _ => Err(ClubError::NotReached {
+                end_page: self.end_page,
+            }),
+        }
+    }
+}
+
+impl ClubPost {
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM club_posts WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+/// Splits reading `pages` from `starts_on` to `ends_on` into `parts` even
+/// milestones, as the last page and the date of each.
+pub fn split_schedule(
+    pages: i32,
+    parts: i32,
+    starts_on: NaiveDate,
+    ends_on: NaiveDate,
+) -> Vec<(i32, NaiveDate)> {
+    let parts = parts.clamp(1, pages.max(1));
+    let days = (ends_on - starts_on).num_days().max(0);
+
+    (1..=parts)
+        .map(|part| {
+            let end_page = (pages.max(0) as u64 * part as u64).div_ceil(parts as u64) as i32;
+            let offset = days * part as i64 / parts as i64;
+            (end_page, starts_on + Days::new(offset as u64))
+        })
+        .collect()
+}
+
+async fn role(pool: &PgPool, club_id: i64, user_id: i64) -> Result<Option<ClubRole>, sqlx::Error> {
+    let role = sqlx::query_scalar!(
+        r#"SELECT role as "role: ClubRole" FROM club_members WHERE club_id = $1 AND user_id = $2"#,
+        club_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(role)
+}
+
+/// The role of `user_id` in the club, if it is at least `least`.
+async fn require(
+    pool: &PgPool,
+    club_id: i64,
+    user_id: i64,
+    least: ClubRole,
+) -> Result<ClubRole, ClubError> {
+    match role(pool, club_id, user_id).await? {
+        None => Err(ClubError::NotMember),
+        Some(role) if role < least => Err(ClubError::NotAllowed),
+        Some(role) => Ok(role),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user_book};
+    use crate::models::user::Visibility;
+    use crate::models::user_book::{ReadingStatus, UserBook};
+    use crate::test_utils::{create_fake_users, setup_db};
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    #[test]
+    fn splits_schedule_evenly() {
+        assert_eq!(
+            vec![
+                (100, date(3, 8)),
+                (200, date(3, 15)),
+                (300, date(3, 22)),
+                (400, date(3, 29))
+            ],
+            split_schedule(400, 4, date(3, 1), date(3, 29))
+        );
+        assert_eq!(
+            vec![(34, date(3, 1)), (67, date(3, 2)), (100, date(3, 3))],
+            split_schedule(100, 3, date(3, 1), date(3, 3))
+        );
+        assert_eq!(
+            vec![(2, date(3, 1)), (3, date(3, 1))],
+            split_schedule(3, 2, date(3, 1), date(2, 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn roles_limit_who_manages_the_club() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 4).await;
+        let [owner, moderator, member, outsider] =
+            [users[0].id, users[1].id, users[2].id, users[3].id];
+        let mut club = ClubBuilder::default()
+            .name("Slow Readers".to_string())
+            .build()
+            .unwrap();
+        club.create(&pool, owner).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+
+        // Act
+        club.add_member(&pool, owner, moderator, ClubRole::Moderator)
+            .await
+            .unwrap();
+        let moderator_adds_owner = club
+            .add_member(&pool, moderator, member, ClubRole::Owner)
+            .await;
+        club.add_member(&pool, moderator, member, ClubRole::Member)
+            .await
+            .unwrap();
+        let member_adds = club
+            .add_member(&pool, member, outsider, ClubRole::Member)
+            .await;
+        let outsider_picks = ClubPickBuilder::default()
+            .club_id(club.id)
+            .book_id(book.id)
+            .build()
+            .unwrap()
+            .create(&pool, outsider)
+            .await;
+        let mut pick = ClubPickBuilder::default()
+            .club_id(club.id)
+            .book_id(book.id)
+            .build()
+            .unwrap();
+        pick.create(&pool, moderator).await.unwrap();
+        let moderator_removes_owner = club.remove_member(&pool, moderator, owner).await;
+        let moderator_sets_role = club
+            .set_role(&pool, moderator, member, ClubRole::Moderator)
+            .await;
+        club.set_role(&pool, owner, member, ClubRole::Moderator)
+            .await
+            .unwrap();
+        let owner_demotes_self = club
+            .set_role(&pool, owner, owner, ClubRole::Moderator)
+            .await;
+        let owner_leaves = club.remove_member(&pool, owner, owner).await;
+        let members = club.members(&pool).await.unwrap();
+        let left = club.remove_member(&pool, member, member).await.unwrap();
+        let clubs = Club::for_user(&pool, moderator).await.unwrap();
+        let picks = club.picks(&pool).await.unwrap();
+
+        // Cleanup
+        club.delete(&pool).await.unwrap();
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(moderator_adds_owner, Err(ClubError::NotAllowed)));
+        assert!(matches!(member_adds, Err(ClubError::NotAllowed)));
+        assert!(matches!(outsider_picks, Err(ClubError::NotMember)));
+        assert!(matches!(
+            moderator_removes_owner,
+            Err(ClubError::NotAllowed)
+        ));
+        assert!(matches!(moderator_sets_role, Err(ClubError::NotAllowed)));
+        assert!(matches!(owner_demotes_self, Err(ClubError::LastOwner)));
+        assert!(matches!(owner_leaves, Err(ClubError::LastOwner)));
+        assert_eq!(
+            vec![
+                (owner, ClubRole::Owner),
+                (moderator, ClubRole::Moderator),
+                (member, ClubRole::Moderator)
+            ],
+            members
+                .iter()
+                .map(|m| (m.user_id, m.role))
+                .collect::<Vec<_>>()
+        );
+        assert!(left);
+        assert_eq!(
+            vec![club.id],
+            clubs.iter().map(|c| c.id).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![pick], picks);
+    }
+
+    #[tokio::test]
+    async fn progress_and_spoiler_gated_threads() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 3).await;
+        let [owner, ahead, behind] = [users[0].id, users[1].id, users[2].id];
+        let mut club = ClubBuilder::default()
+            .name("Tuesday Club".to_string())
+            .build()
+            .unwrap();
+        club.create(&pool, owner).await.unwrap();
+        for user in [ahead, behind] {
+            club.add_member(&pool, owner, user, ClubRole::Member)
+                .await
+                .unwrap();
+        }
+        let mut book = fake_book();
+        book.pages = Some(300);
+        book.create(&pool).await.unwrap();
+        let mut pick = ClubPickBuilder::default()
+            .club_id(club.id)
+            .book_id(book.id)
+            .starts_on(date(3, 1))
+            .build()
+            .unwrap();
+        pick.create(&pool, owner).await.unwrap();
+        let mut milestones = Vec::new();
+        for (n, (end_page, due_on)) in split_schedule(300, 3, date(3, 1), date(3, 22))
+            .into_iter()
+            .enumerate()
+        {
+            let label = format!("Part {}", n + 1);
+            milestones.push(
+                pick.add_milestone(&pool, owner, &label, end_page, due_on)
+                    .await
+                    .unwrap(),
+            );
+        }
+        for (user, page, visibility) in [
+            (owner, 300, Visibility::Public),
+            (ahead, 210, Visibility::Public),
+            (behind, 40, Visibility::Private),
+        ] {
+            let mut entry = fake_user_book(user, book.id);
+            entry.status = ReadingStatus::Reading;
+            entry.current_page = Some(page);
+            entry.visibility = visibility;
+            entry.create(&pool).await.unwrap();
+        }
+        let mut finished = UserBook::get(&pool, book.id, owner).await.unwrap();
+        finished.status = ReadingStatus::Completed;
+        finished.current_page = None;
+        finished.update(&pool).await.unwrap();
+
+        // Act
+        let schedule = pick.schedule(&pool).await.unwrap();
+        let progress = pick.progress(&pool, ahead, date(3, 16)).await.unwrap();
+        let own_progress = pick.progress(&pool, behind, date(3, 16)).await.unwrap();
+        let post = milestones[1]
+            .post(&pool, ahead, "Didn't see that coming")
+            .await
+            .unwrap();
+        let spoiler_post = milestones[2].post(&pool, ahead, "The ending!").await;
+        let thread = milestones[1].thread(&pool, owner).await.unwrap();
+        let gated = milestones[1].thread(&pool, behind).await;
+        book.pages = None;
+        book.update(&pool).await.unwrap();
+        let finished_without_pages = milestones[2].thread(&pool, owner).await;
+
+        // Cleanup
+        club.delete(&pool).await.unwrap();
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(milestones, schedule);
+        assert_eq!(
+            vec![
+                MemberProgress {
+                    user_id: owner,
+                    current_page: Some(300),
+                    milestones_reached: Some(3),
+                    behind: Some(false),
+                },
+                MemberProgress {
+                    user_id: ahead,
+                    current_page: Some(210),
+                    milestones_reached: Some(2),
+                    behind: Some(false),
+                },
+                MemberProgress {
+                    user_id: behind,
+                    current_page: None,
+                    milestones_reached: None,
+                    behind: None,
+                },
+            ],
+            progress
+        );
+        assert_eq!(Some(true), own_progress[2].behind);
+        assert!(matches!(
+            spoiler_post,
+            Err(ClubError::NotReached { end_page: 300 })
+        ));
+        assert_eq!(vec![post], thread);
+        assert!(matches!(
+            gated,
+            Err(ClubError::NotReached { end_page: 200 })
+        ));
+        assert!(finished_without_pages.is_ok());
+    }
+}
@@ -0,0 +1,90 @@
+// Cover images kept by the cover store, and which book uses which cover.
+
+use sqlx::PgPool;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cover {
+    /// Hex encoded SHA-256 hash of the original image.
+    pub hash: String,
+    pub media_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub size_bytes: i32,
+}
+
+impl Cover {
+    /// Inserts the cover unless a cover with the same hash is stored already.
+    pub async fn create_if_new(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO covers (hash, media_type, width, height, size_bytes)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (hash) DO NOTHING
+            "#,
+            self.hash,
+            self.media_type,
+            self.width,
+            self.height,
+            self.size_bytes
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a cover by its hash.
+    pub async fn get(pool: &PgPool, hash: &str) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Cover,
+            "SELECT hash, media_type, width, height, size_bytes FROM covers WHERE hash = $1",
+            hash
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The cover of a book, if one is stored.
+    pub async fn for_book(pool: &PgPool, book_id: i64) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Cover,
+            r#"
+            SELECT c.hash, c.media_type, c.width, c.height, c.size_bytes
+            FROM covers c
+            JOIN book_covers bc ON bc.hash = c.hash
+            WHERE bc.book_id = $1
+            "#,
+            book_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Makes this the cover of a book, replacing the book's previous cover.
+    pub async fn attach(
+        &self,
+        pool: &PgPool,
+        book_id: i64,
+        source_url: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO book_covers (book_id, hash, source_url)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (book_id) DO UPDATE
+            SET hash = EXCLUDED.hash, source_url = EXCLUDED.source_url, updated_at = CURRENT_TIMESTAMP
+            "#,
+            book_id,
+            self.hash,
+            source_url
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+// The follow graph between users, and the blocks and mutes that limit it.
+// Following a user whose profile isn't public makes a pending request they
+// accept.
+
+use crate::models::user::{User, Visibility};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "follow_status", rename_all = "lowercase")]
+pub enum FollowStatus {
+    Pending,
+    Accepted,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Follow {
+    pub follower_id: i64,
+    pub followee_id: i64,
+    pub status: FollowStatus,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum FollowError {
+    /// Users can't follow themselves.
+    SelfFollow,
+    /// One of the users blocked the other.
+    Blocked,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for FollowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FollowError::SelfFollow => write!(f, "users can't follow themselves"),
+            FollowError::Blocked => write!(f, "the user is blocked"),
+            FollowError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FollowError {}
+
+impl From<sqlx::Error> for FollowError {
+    fn from(e: sqlx::Error) -> Self {
+        FollowError::Database(e)
+    }
+}
+
+/// Number of accepted followers and follows of a user, and of follow
+/// requests waiting for them.
+#[derive(Debug, Default, PartialEq)]
+pub struct FollowCounts {
+    pub followers: i64,
+    pub following: i64,
+    pub pending: i64,
+}
+
+impl Follow {
+    /// Makes `follower_id` follow `followee_id`, or asks to if the followee's
+    /// profile isn't public. Following someone already followed, or asked to be
+    /// followed, returns the existing follow.
+    pub async fn request(
+        pool: &PgPool,
+        follower_id: i64,
+        followee_id: i64,
+    ) -> Result<Self, FollowError> {
+        if follower_id == followee_id {
+            return Err(FollowError::SelfFollow);
+        }
+        if Block::between(pool, follower_id, followee_id).await? {
+            return Err(FollowError::Blocked);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO follows (follower_id, followee_id, status)
+            SELECT $1, id, CASE WHEN visibility = 'public' THEN 'accepted'::follow_status ELSE 'pending' END
+            FROM users
+            WHERE id = $2
+            ON CONFLICT DO NOTHING
+            "#,
+            follower_id,
+            followee_id
+        )
+        .execute(pool)
+        .await?;
+
+        Self::get(pool, follower_id, followee_id)
+            .await?
+            .ok_or(FollowError::Database(sqlx::Error::RowNotFound))
+    }
+
+    /// The follow of `followee_id` by `follower_id`, if there is one.
+    pub async fn get(
+        pool: &PgPool,
+        follower_id: i64,
+        followee_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Follow,
+            r#"
+            SELECT follower_id, followee_id, status as "status: FollowStatus", created_at
+            FROM follows
+            WHERE follower_id = $1 AND followee_id = $2
+            "#,
+            follower_id,
+            followee_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Accepts a pending follow request. Returns whether there was one.
+    pub async fn accept(&mut self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE follows SET status = 'accepted'
+            WHERE follower_id = $1 AND followee_id = $2 AND status = 'pending'
+            "#,
+            self.follower_id,
+            self.followee_id
+        )
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() > 0 {
+            self.status = FollowStatus::Accepted;
+        }
+
+        Ok(updated.rows_affected() > 0)
+    }
+
+    /// The users following `user_id`.
+    pub async fn followers(pool: &PgPool, user_id: i64) -> Result<Vec<User>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.name, u.email, u.password, u.visibility as "visibility: Visibility", u.timezone, u.reminder_hour
+            FROM follows f
+            JOIN users u ON u.id = f.follower_id
+            WHERE f.followee_id = $1 AND f.status = 'accepted'
+            ORDER BY f.created_at DESC, u.id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// The users `user_id` follows.
+    pub async fn following(pool: &PgPool, user_id: i64) -> Result<Vec<User>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.name, u.email, u.password, u.visibility as "visibility: Visibility", u.timezone, u.reminder_hour
+            FROM follows f
+            JOIN users u ON u.id = f.followee_id
+            WHERE f.follower_id = $1 AND f.status = 'accepted'
+            ORDER BY f.created_at DESC, u.id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Follow requests waiting for `user_id` to accept them, oldest first.
+    pub async fn pending_requests(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Follow,
+            r#"
+            SELECT follower_id, followee_id, status as "status: FollowStatus", created_at
+            FROM follows
+            WHERE followee_id = $1 AND status = 'pending'
+            ORDER BY created_at, follower_id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Friends of `user_id`: the users who follow them and whom they follow.
+    pub async fn friends(pool: &PgPool, user_id: i64) -> Result<Vec<User>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.name, u.email, u.password, u.visibility as "visibility: Visibility", u.timezone, u.reminder_hour
+            FROM follows f
+            JOIN follows back ON back.follower_id = f.followee_id AND back.followee_id = f.follower_id
+            JOIN users u ON u.id = f.followee_id
+            WHERE f.follower_id = $1 AND f.status = 'accepted' AND back.status = 'accepted'
+            ORDER BY u.name, u.id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Follower, following and pending request counts of `user_id`.
+    pub async fn counts(pool: &PgPool, user_id: i64) -> Result<FollowCounts, sqlx::Error> {
+        let record = sqlx::query_as!(
+            FollowCounts,
+            r#"
+            SELECT COUNT(*) FILTER (WHERE followee_id = $1 AND status = 'accepted') AS "followers!",
+                   COUNT(*) FILTER (WHERE follower_id = $1 AND status = 'accepted') AS "following!",
+                   COUNT(*) FILTER (WHERE followee_id = $1 AND status = 'pending') AS "pending!"
+            FROM follows
+            WHERE follower_id = $1 OR followee_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Unfollows, or withdraws or declines the request.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM follows WHERE follower_id = $1 AND followee_id = $2",
+            self.follower_id,
+            self.followee_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+/// A user blocking another. Blocking removes the follows between the two
+/// either way and keeps them from following each other again.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub blocker_id: i64,
+    pub blocked_id: i64,
+}
+
+impl Block {
+    /// Insert the block into the DB, removing the follows between the users.
+    pub async fn create(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM follows
+            WHERE (follower_id = $1 AND followee_id = $2) OR (follower_id = $2 AND followee_id = $1)
+            "#,
+            self.blocker_id,
+            self.blocked_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO blocks (blocker_id, blocked_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            self.blocker_id,
+            self.blocked_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Whether either user blocked the other.
+    pub async fn between(pool: &PgPool, a: i64, b: i64) -> Result<bool, sqlx::Error> {
+        let record = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $1 AND blocked_id = $2) OR (blocker_id = $2 AND blocked_id = $1)
+            ) AS "blocked!"
+            "#,
+            a,
+            b
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record.blocked)
+    }
+
+    /// Unblocks. Follows removed by the block aren't restored.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM blocks WHERE blocker_id = $1 AND blocked_id = $2",
+            self.blocker_id,
+            self.blocked_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+/// A user muting another, hiding the muted user's activity from them without
+/// unfollowing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Mute {
+    pub muter_id: i64,
+    pub muted_id: i64,
+}
+
+impl Mute {
+    /// Insert the mute into the DB, unless the user is muted already.
+    pub async fn create(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO mutes (muter_id, muted_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            self.muter_id,
+            self.muted_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether `muter_id` muted `muted_id`.
+    pub async fn exists(pool: &PgPool, muter_id: i64, muted_id: i64) -> Result<bool, sqlx::Error> {
+        let record = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = $2) AS "muted!""#,
+            muter_id,
+            muted_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record.muted)
+    }
+
+    /// Unmutes.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM mutes WHERE muter_id = $1 AND muted_id = $2",
+            self.muter_id,
+            self.muted_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_fake_users, setup_db};
+
+    #[tokio::test]
+    async fn follows_requests_and_friends() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut users = create_fake_users(&pool, 3).await;
+        let [anna, ben, cleo] = [users[0].id, users[1].id, users[2].id];
+        users[2].visibility = Visibility::Followers;
+        users[2].update(&pool).await.unwrap();
+
+        // Act
+        let self_follow = Follow::request(&pool, anna, anna).await;
+        Follow::request(&pool, anna, ben).await.unwrap();
+        Follow::request(&pool, ben, anna).await.unwrap();
+        let again = Follow::request(&pool, ben, anna).await.unwrap();
+        let mut to_private = Follow::request(&pool, anna, cleo).await.unwrap();
+        let pending = Follow::pending_requests(&pool, cleo).await.unwrap();
+        let counts_before = Follow::counts(&pool, cleo).await.unwrap();
+        to_private.accept(&pool).await.unwrap();
+        let counts_after = Follow::counts(&pool, cleo).await.unwrap();
+        let anna_counts = Follow::counts(&pool, anna).await.unwrap();
+        let friends = Follow::friends(&pool, anna).await.unwrap();
+        let followers = Follow::followers(&pool, anna).await.unwrap();
+        let following = Follow::following(&pool, anna).await.unwrap();
+
+        // Cleanup
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert!(matches!(self_follow, Err(FollowError::SelfFollow)));
+        assert_eq!(FollowStatus::Accepted, again.status);
+        assert_eq!(
+            vec![(anna, FollowStatus::Pending)],
+            pending
+                .iter()
+                .map(|f| (f.follower_id, f.status))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(FollowStatus::Accepted, to_private.status);
+        assert_eq!(
+            FollowCounts {
+                followers: 0,
+                following: 0,
+                pending: 1,
+            },
+            counts_before
+        );
+        assert_eq!(1, counts_after.followers);
+        assert_eq!(
+            FollowCounts {
+                followers: 1,
+                following: 2,
+                pending: 0,
+            },
+            anna_counts
+        );
+        assert_eq!(vec![ben], friends.iter().map(|u| u.id).collect::<Vec<_>>());
+        assert_eq!(
+            vec![ben],
+            followers.iter().map(|u| u.id).collect::<Vec<_>>()
+        );
+        assert_eq!(2, following.len());
+    }
+
+    #[tokio::test]
+    async fn blocks_and_mutes() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 2).await;
+        let [anna, ben] = [users[0].id, users[1].id];
+        let block = Block {
+            blocker_id: anna,
+            blocked_id: ben,
+        };
+        let mute = Mute {
+            muter_id: anna,
+            muted_id: ben,
+        };
+
+        // Act
+        Follow::request(&pool, ben, anna).await.unwrap();
+        block.create(&pool).await.unwrap();
+        let follow_after_block = Follow::get(&pool, ben, anna).await.unwrap();
+        let follow_blocker = Follow::request(&pool, ben, anna).await;
+        let follow_blocked = Follow::request(&pool, anna, ben).await;
+        block.delete(&pool).await.unwrap();
+        let after_unblock = Follow::request(&pool, ben, anna).await;
+        mute.create(&pool).await.unwrap();
+        let muted = Mute::exists(&pool, anna, ben).await.unwrap();
+        let muted_back = Mute::exists(&pool, ben, anna).await.unwrap();
+        mute.delete(&pool).await.unwrap();
+        let unmuted = Mute::exists(&pool, anna, ben).await.unwrap();
+
+        // Cleanup
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert_eq!(None, follow_after_block);
+        assert!(matches!(follow_blocker, Err(FollowError::Blocked)));
+        assert!(matches!(follow_blocked, Err(FollowError::Blocked)));
+        assert!(after_unblock.is_ok());
+        assert!(muted);
+        assert!(!muted_back);
+        assert!(!unmuted);
+    }
+}
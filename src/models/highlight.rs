@@ -0,0 +1,288 @@
+// Highlights, notes and bookmarks a user has made in a book on their shelf.
+
+use chrono::{DateTime, Utc};
+use derive_builder::Builder;
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, sqlx::Type, PartialEq, Copy)]
+#[sqlx(type_name = "highlight_kind", rename_all = "lowercase")]
+pub enum HighlightKind {
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+#[derive(Debug, Builder, PartialEq, Clone)]
+pub struct Highlight {
+    #[builder(default = 0)]
+    pub id: i64,
+    pub user_id: i64,
+    pub book_id: i64,
+    #[builder(default = HighlightKind::Highlight)]
+    pub kind: HighlightKind,
+    /// The highlighted passage. `None` for notes and bookmarks.
+    #[builder(default = None)]
+    pub quote: Option<String>,
+    #[builder(default = None)]
+    pub note: Option<String>,
+    /// Reader specific position, e.g. a Kindle location range like `180-182`.
+    #[builder(default = None)]
+    pub location: Option<String>,
+    #[builder(default = None)]
+    pub page: Option<i32>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl Highlight {
+    /// Insert highlight into the DB
+    pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let res = sqlx::query!(
+            r#"
+            INSERT INTO highlights (user_id, book_id, kind, quote, note, location, page, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id
+            "#,
+            self.user_id,
+            self.book_id,
+            self.kind as HighlightKind,
+            self.quote,
+            self.note,
+            self.location,
+            self.page,
+            self.created_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.id = res.id;
+
+        Ok(())
+    }
+
+    /// Inserts the highlight unless the same highlight is already stored.
+    /// An existing highlight without a note gets this highlight's note.
+    /// Returns whether a new row was inserted.
+    pub async fn create_if_new(&mut self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#"
+            INSERT INTO highlights (user_id, book_id, kind, quote, note, location, page, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (user_id, book_id, kind, COALESCE(location, ''), COALESCE(page, -1), md5(COALESCE(quote, note, '')))
+            DO UPDATE SET note = EXCLUDED.note
+            WHERE highlights.note IS NULL AND EXCLUDED.note IS NOT NULL
+            RETURNING id, (xmax = 0) AS "inserted!"
+            "#,
+            self.user_id,
+            self.book_id,
+            self.kind as HighlightKind,
+            self.quote,
+            self.note,
+            self.location,
+            self.page,
+            self.created_at
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        match res {
+            Some(res) => {
+                self.id = res.id;
+                Ok(res.inserted)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Fetch a highlight by its ID, if `viewer_id` can see the user's entry
+    /// for the book.
+    pub async fn get(pool: &PgPool, id: i64, viewer_id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Highlight,
+            r#"
+            SELECT h.id, h.user_id, h.book_id, h.kind as "kind: HighlightKind", h.quote, h.note, h.location, h.page, h.created_at
+            FROM highlights h
+            JOIN user_books ub ON ub.user_id = h.user_id AND ub.book_id = h.book_id
+            JOIN users u ON u.id = h.user_id
+            WHERE h.id = $1 AND visible_to(h.user_id, $2, LEAST(u.visibility, ub.visibility))
+            "#,
+            id,
+            viewer_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Gets all highlights a user has in a book, in reading order. They are
+    /// only returned if `viewer_id` can see the user's entry for the book.
+    pub async fn for_user_book(
+        pool: &PgPool,
+        user_id: i64,
+        book_id: i64,
+        viewer_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Highlight,
+            r#"
+            SELECT h.id, h.user_id, h.book_id, h.kind as "kind: HighlightKind", h.quote, h.note, h.location, h.page, h.created_at
+            FROM highlights h
+            JOIN user_books ub ON ub.user_id = h.user_id AND ub.book_id = h.book_id
+            JOIN users u ON u.id = h.user_id
+            WHERE h.user_id = $1 AND h.book_id = $2
+              AND visible_to(h.user_id, $3, LEAST(u.visibility, ub.visibility))
+            ORDER BY h.page NULLS LAST, h.created_at
+            "#,
+            user_id,
+            book_id,
+            viewer_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM highlights WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories;
+    use crate::models::user::Visibility;
+    use crate::test_utils::setup_db;
+    use chrono::Timelike;
+
+    #[tokio::test]
+    async fn create_then_get_and_delete() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = factories::fake_book();
+        book.create(&pool).await.unwrap();
+        let mut user_book = factories::fake_user_book(user.id, book.id);
+        user_book.create(&pool).await.unwrap();
+
+        let mut highlight = HighlightBuilder::default()
+            .user_id(user.id)
+            .book_id(book.id)
+            .quote(Some("Pain and suffering are always inevitable".to_string()))
+            .location(Some("180-182".to_string()))
+            .page(Some(12))
+            .created_at(Utc::now().with_nanosecond(0).unwrap())
+            .build()
+            .unwrap();
+
+        // Act
+        highlight.create(&pool).await.unwrap();
+        let fetched = Highlight::get(&pool, highlight.id, user.id).await.unwrap();
+        let deleted_rows = highlight.delete(&pool).await.unwrap();
+
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(highlight, fetched);
+        assert_eq!(1, deleted_rows);
+    }
+
+    #[tokio::test]
+    async fn create_if_new_deduplicates_and_fills_in_notes() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = factories::fake_book();
+        book.create(&pool).await.unwrap();
+        let mut user_book = factories::fake_user_book(user.id, book.id);
+        user_book.create(&pool).await.unwrap();
+
+        let mut highlight = HighlightBuilder::default()
+            .user_id(user.id)
+            .book_id(book.id)
+            .quote(Some(
+                "Man is a creature that can get used to anything".to_string(),
+            ))
+            .location(Some("1200-1201".to_string()))
+            .build()
+            .unwrap();
+        let mut with_note = highlight.clone();
+        with_note.note = Some("So true".to_string());
+
+        // Act
+        let first = highlight.create_if_new(&pool).await.unwrap();
+        let second = highlight.clone().create_if_new(&pool).await.unwrap();
+        let noted = with_note.create_if_new(&pool).await.unwrap();
+        let stored = Highlight::for_user_book(&pool, user.id, book.id, user.id)
+            .await
+            .unwrap();
+
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(first);
+        assert!(!second);
+        assert!(!noted);
+        assert_eq!(1, stored.len());
+        assert_eq!(Some("So true".to_string()), stored[0].note);
+    }
+
+    #[tokio::test]
+    async fn reads_follow_entry_visibility() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut owner = factories::fake_user();
+        owner.create(&pool).await.unwrap();
+        let mut other = factories::fake_user();
+        other.create(&pool).await.unwrap();
+        let mut book = factories::fake_book();
+        book.create(&pool).await.unwrap();
+        let mut user_book = factories::fake_user_book(owner.id, book.id);
+        user_book.visibility = Visibility::Private;
+        user_book.create(&pool).await.unwrap();
+        let mut highlight = HighlightBuilder::default()
+            .user_id(owner.id)
+            .book_id(book.id)
+            .quote(Some("Beauty will save the world".to_string()))
+            .build()
+            .unwrap();
+        highlight.create(&pool).await.unwrap();
+
+        // Act
+        let as_owner = Highlight::for_user_book(&pool, owner.id, book.id, owner.id)
+            .await
+            .unwrap();
+        let as_other = Highlight::for_user_book(&pool, owner.id, book.id, other.id)
+            .await
+            .unwrap();
+        let got_as_other = Highlight::get(&pool, highlight.id, other.id).await;
+        user_book.visibility = Visibility::Public;
+        user_book.update(&pool).await.unwrap();
+        let made_public = Highlight::for_user_book(&pool, owner.id, book.id, other.id)
+            .await
+            .unwrap();
+        let got_made_public = Highlight::get(&pool, highlight.id, other.id).await;
+
+        owner.delete(&pool).await.unwrap();
+        other.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(1, as_owner.len());
+        assert!(as_other.is_empty());
+        assert_eq!(as_owner, made_public);
+        assert!(matches!(got_as_other, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(highlight.id, got_made_public.unwrap().id);
+    }
+}
@@ -0,0 +1,125 @@
+// Accounts and raw progress records for the KOReader sync (kosync) protocol.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+#[derive(Debug, PartialEq)]
+pub struct KosyncAccount {
+    pub user_id: i64,
+    pub username: String,
+    pub userkey: String,
+}
+
+impl KosyncAccount {
+    /// Inserts the account into the DB.
+    pub async fn create(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO kosync_accounts (user_id, username, userkey) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            self.userkey
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch the account with the given username.
+    pub async fn get_by_username(pool: &PgPool, username: &str) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            KosyncAccount,
+            "SELECT user_id, username, userkey FROM kosync_accounts WHERE username = $1",
+            username
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Returns the account if the username and userkey match, `None` otherwise.
+    pub async fn authorize(
+        pool: &PgPool,
+        username: &str,
+        userkey: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            KosyncAccount,
+            "SELECT user_id, username, userkey FROM kosync_accounts WHERE username = $1 AND userkey = $2",
+            username,
+            userkey
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM kosync_accounts WHERE user_id = $1",
+            self.user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct KosyncProgress {
+    pub user_id: i64,
+    pub document: String,
+    pub progress: String,
+    pub percentage: f64,
+    pub device: String,
+    pub device_id: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl KosyncProgress {
+    /// Inserts or replaces the progress the user has on the document.
+    pub async fn save(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO kosync_progress (user_id, document, progress, percentage, device, device_id, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (user_id, document) DO UPDATE
+            SET progress = EXCLUDED.progress, percentage = EXCLUDED.percentage, device = EXCLUDED.device,
+                device_id = EXCLUDED.device_id, updated_at = EXCLUDED.updated_at
+            "#,
+            self.user_id,
+            self.document,
+            self.progress,
+            self.percentage,
+            self.device,
+            self.device_id,
+            self.updated_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the last progress the user reported on the document, if any.
+    pub async fn get(
+        pool: &PgPool,
+        user_id: i64,
+        document: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            KosyncProgress,
+            "SELECT user_id, document, progress, percentage, device, device_id, updated_at FROM kosync_progress WHERE user_id = $1 AND document = $2",
+            user_id,
+            document
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+}
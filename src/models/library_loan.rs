@@ -0,0 +1,356 @@
+// Books on a user's shelf that are borrowed from a public library, with when
+// they have to go back.
+
+use crate::models::user_book::{ProgressEntry, ReadingStatus, UserBook};
+use chrono::{DateTime, Days, NaiveDate, TimeDelta, Utc};
+use derive_builder::Builder;
+use sqlx::PgPool;
+
+/// Days of progress history the reading pace is worked out from.
+pub const PACE_WINDOW_DAYS: i64 = 14;
+
+#[derive(Debug, Builder, PartialEq, Clone)]
+pub struct LibraryLoan {
+    pub user_id: i64,
+    pub book_id: i64,
+    /// Name of the library, e.g. "Central Library".
+    pub library: String,
+    #[builder(default = Utc::now().date_naive())]
+    pub checked_out_on: NaiveDate,
+    pub due_on: NaiveDate,
+    #[builder(default = 0)]
+    pub renewals: i32,
+}
+
+/// Whether a user will finish a borrowed book before it is due, at their
+/// recent pace.
+#[derive(Debug, PartialEq)]
+pub enum FinishEstimate {
+    /// The book is read already.
+    Finished,
+    OnTime {
+        finish_on: NaiveDate,
+    },
+    /// `finish_on` is `None` when the user hasn't made progress lately.
+    Late {
+        finish_on: Option<NaiveDate>,
+    },
+    /// Too little progress has been logged to tell.
+    Unknown,
+}
+
+impl LibraryLoan {
+    /// Insert the loan into the DB. The book has to be on the user's shelf.
+    pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO library_loans (user_id, book_id, library, checked_out_on, due_on, renewals)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            self.user_id,
+            self.book_id,
+            self.library,
+            self.checked_out_on,
+            self.due_on,
+            self.renewals
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Update the loan in the DB. Returns the number of updated rows on Ok.
+    pub async fn update(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE library_loans
+            SET library = $1, checked_out_on = $2, due_on = $3, renewals = $4
+            WHERE user_id = $5 AND book_id = $6
+            "#,
+            self.library,
+            self.checked_out_on,
+            self.due_on,
+            self.renewals,
+            self.user_id,
+            self.book_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected())
+    }
+
+    /// Renews the loan until `due_on`.
+    pub async fn renew(&mut self, pool: &PgPool, due_on: NaiveDate) -> Result<(), sqlx::Error> {
+        self.due_on = due_on;
+        self.renewals += 1;
+        self.update(pool).await?;
+
+        Ok(())
+    }
+
+    /// The library loan of a shelf entry, if the book is borrowed.
+    pub async fn get(
+        pool: &PgPool,
+        book_id: i64,
+        user_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            LibraryLoan,
+            r#"
+            SELECT user_id, book_id, library, checked_out_on, due_on, renewals
+            FROM library_loans
+            WHERE book_id = $1 AND user_id = $2
+            "#,
+            book_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The user's loans due by `until`, overdue ones included, soonest first.
+    pub async fn due_by(
+        pool: &PgPool,
+        user_id: i64,
+        until: NaiveDate,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            LibraryLoan,
+            r#"
+            SELECT user_id, book_id, library, checked_out_on, due_on, renewals
+            FROM library_loans
+            WHERE user_id = $1 AND due_on <= $2
+            ORDER BY due_on, book_id
+            "#,
+            user_id,
+            until
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Estimates whether the user finishes the book before it is due, from
+    /// their progress over the last `PACE_WINDOW_DAYS` days.
+    pub async fn finish_estimate(
+        &self,
+        pool: &PgPool,
+        now: DateTime<Utc>,
+    ) -> Result<FinishEstimate, sqlx::Error> {
+        let user_book = UserBook::get(pool, self.book_id, self.user_id).await?;
+        if user_book.status == ReadingStatus::Completed {
+            return Ok(FinishEstimate::Finished);
+        }
+        let history = user_book.progress_history(pool).await?;
+
+        Ok(estimate(
+            user_book.progress_percent.unwrap_or(0.0),
+            recent_pace(&history, now),
+            now.date_naive(),
+            self.due_on,
+        ))
+    }
+
+    /// Deletes the row associated with the record, e.g. once the book is
+    /// returned.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM library_loans WHERE user_id = $1 AND book_id = $2",
+            self.user_id,
+            self.book_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+/// Percent of the book read per day, measured from the last logged progress
+/// before the window, or the first one in it, up to `now`. Time without
+/// progress counts, so a user who stopped reading has a pace of 0. `None`
+/// when there's no earlier progress to measure from.
+pub fn recent_pace(history: &[ProgressEntry], now: DateTime<Utc>) -> Option<f64> {
+    let window_start = now - TimeDelta::days(PACE_WINDOW_DAYS);
+    let latest = history.last()?;
+    let baseline = history
+        .iter()
+        .rev()
+        .find(|entry| entry.recorded_at <= window_start)
+        .or(history.first())?;
+    if std::ptr::eq(baseline, latest) && baseline.recorded_at > window_start {
+        return None;
+    }
+
+    let days = ((now - baseline.recorded_at).num_minutes() as f64 / (24.0 * 60.0)).max(1.0);
+    Some(((latest.progress_percent - baseline.progress_percent) / days).max(0.0))
+}
+
+/// When a book `percent` read is finished at `pace` percent a day, compared
+/// to `due_on`.
+pub fn estimate(
+    percent: f64,
+    pace: Option<f64>,
+    today: NaiveDate,
+    due_on: NaiveDate,
+) -> FinishEstimate {
+    if percent >= 100.0 {
+        return FinishEstimate::Finished;
+    }
+    let Some(pace) = pace else {
+        return FinishEstimate::Unknown;
+    };
+    let finish_on = (pace > 0.0)
+        .then(|| today.checked_add_days(Days::new(((100.0 - percent) / pace).ceil() as u64)))
+        .flatten();
+
+    match finish_on {
+        Some(finish_on) if finish_on <= due_on => FinishEstimate::OnTime { finish_on },
+        finish_on => FinishEstimate::Late { finish_on },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user, fake_user_book};
+    use crate::models::user_book::Progress;
+    use crate::test_utils::setup_db;
+    use chrono::TimeZone;
+
+    fn entry(day: u32, percent: f64) -> ProgressEntry {
+        ProgressEntry {
+            progress_percent: percent,
+            recorded_at: Utc.with_ymd_and_hms(2025, 6, day, 20, 0, 0).unwrap(),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 6, day).unwrap()
+    }
+
+    #[test]
+    fn pace_from_recent_progress() {
+        // Arrange
+        let now = Utc.with_ymd_and_hms(2025, 6, 25, 20, 0, 0).unwrap();
+        let history = [entry(1, 5.0), entry(10, 20.0), entry(20, 40.0)];
+
+        // Act
+        let pace = recent_pace(&history, now);
+        let started_lately = recent_pace(&history[2..], now);
+        let stopped = recent_pace(&history[..1], now);
+
+        // Assert
+        // From 20% on the 10th, the last entry before the window, to 40%
+        assert_eq!(Some(20.0 / 15.0), pace);
+        assert_eq!(None, started_lately);
+        assert_eq!(Some(0.0), stopped);
+        assert_eq!(None, recent_pace(&[], now));
+    }
+
+    #[test]
+    fn estimates_finish_against_due_date() {
+        assert_eq!(
+            FinishEstimate::OnTime {
+                finish_on: date(11)
+            },
+            estimate(40.0, Some(6.0), date(1), date(14))
+        );
+        assert_eq!(
+            FinishEstimate::Late {
+                finish_on: Some(date(21))
+            },
+            estimate(40.0, Some(3.0), date(1), date(14))
+        );
+        assert_eq!(
+            FinishEstimate::Late { finish_on: None },
+            estimate(40.0, Some(0.0), date(1), date(14))
+        );
+        assert_eq!(
+            FinishEstimate::Unknown,
+            estimate(40.0, None, date(1), date(14))
+        );
+        assert_eq!(
+            FinishEstimate::Finished,
+            estimate(100.0, None, date(1), date(14))
+        );
+    }
+
+    #[tokio::test]
+    async fn loans_by_due_date_and_progress_history() {
+        // Arrange
+        let pool = setup_db().await;
+        let today = Utc::now().date_naive();
+        let in_days = |days| today.checked_add_days(Days::new(days)).unwrap();
+        let mut user = fake_user();
+        user.create(&pool).await.unwrap();
+        let mut books = [fake_book(), fake_book(), fake_book()];
+        let mut loans = Vec::new();
+        for (book, due) in books.iter_mut().zip([in_days(20), in_days(5), in_days(30)]) {
+            book.pages = Some(200);
+            book.create(&pool).await.unwrap();
+            let mut user_book = fake_user_book(user.id, book.id);
+            user_book.status = ReadingStatus::ToRead;
+            user_book.current_page = None;
+            user_book.create(&pool).await.unwrap();
+            let mut loan = LibraryLoanBuilder::default()
+                .user_id(user.id)
+                .book_id(book.id)
+                .library("Central Library".to_string())
+                .checked_out_on(today)
+                .due_on(due)
+                .build()
+                .unwrap();
+            loan.create(&pool).await.unwrap();
+            loans.push(loan);
+        }
+        let mut user_book = UserBook::get(&pool, books[0].id, user.id).await.unwrap();
+
+        // Act
+        loans[2].renew(&pool, in_days(28)).await.unwrap();
+        let due = LibraryLoan::due_by(&pool, user.id, in_days(28))
+            .await
+            .unwrap();
+        for page in [50, 50, 100] {
+            user_book.set_progress(Progress::Page(page), &books[0]);
+            user_book.update(&pool).await.unwrap();
+        }
+        let history = user_book.progress_history(&pool).await.unwrap();
+        let estimate = loans[0].finish_estimate(&pool, Utc::now()).await.unwrap();
+        loans[1].delete(&pool).await.unwrap();
+        let returned = LibraryLoan::get(&pool, books[1].id, user.id).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        for book in &books {
+            book.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert_eq!(
+            vec![&loans[1], &loans[0], &loans[2]],
+            due.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(1, due[2].renewals);
+        assert_eq!(
+            vec![25.0, 50.0],
+            history
+                .iter()
+                .map(|e| e.progress_percent)
+                .collect::<Vec<_>>()
+        );
+        // 25% read today is taken as a day's reading, the rest takes two more
+        assert_eq!(
+            FinishEstimate::OnTime {
+                finish_on: in_days(2)
+            },
+            estimate
+        );
+        assert_eq!(None, returned);
+    }
+}
@@ -0,0 +1,299 @@
+// Owned copies lent out, to another user or to someone without an account.
+// A copy can't be lent to two people over the same days.
+
+use chrono::{NaiveDate, Utc};
+use derive_builder::Builder;
+use sqlx::PgPool;
+
+#[derive(Debug)]
+pub enum LoanError {
+    /// The copy is lent to someone else during the loan.
+    AlreadyLent,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for LoanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoanError::AlreadyLent => write!(f, "the copy is already lent out"),
+            LoanError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for LoanError {}
+
+impl From<sqlx::Error> for LoanError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::Database(db) if db.constraint() == Some("loans_no_overlap") => {
+                LoanError::AlreadyLent
+            }
+            _ => LoanError::Database(e),
+        }
+    }
+}
+
+#[derive(Debug, Builder, PartialEq, Clone)]
+pub struct Loan {
+    #[builder(default = 0)]
+    pub id: i64,
+    /// The `OwnedCopy` lent out.
+    pub copy_id: i64,
+    /// The borrower, if they have an account.
+    #[builder(default = None)]
+    pub borrower_id: Option<i64>,
+    /// The borrower's name. Filled in from their account when lending to a
+    /// user, so the loan is remembered if they delete it.
+    #[builder(default = None)]
+    pub borrower_name: Option<String>,
+    #[builder(default = Utc::now().date_naive())]
+    pub lent_on: NaiveDate,
+    #[builder(default = None)]
+    pub due_on: Option<NaiveDate>,
+    #[builder(default = None)]
+    pub returned_on: Option<NaiveDate>,
+}
+
+impl Loan {
+    /// Records the loan. Fails with `LoanError::AlreadyLent` if the copy is
+    /// lent to someone else on any of the days of this loan.
+    pub async fn create(&mut self, pool: &PgPool) -> Result<(), LoanError> {
+        let res = sqlx::query!(
+            r#"
+            INSERT INTO loans (copy_id, borrower_id, borrower_name, lent_on, due_on, returned_on)
+            VALUES ($1, $2, COALESCE($3, (SELECT name FROM users WHERE id = $2)), $4, $5, $6)
+            RETURNING borrower_name, id
+            "#,
+            self.copy_id,
+            self.borrower_id,
+            self.borrower_name,
+            self.lent_on,
+            self.due_on,
+            self.returned_on
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.id = res.id;
+        self.borrower_name = res.borrower_name;
+
+        Ok(())
+    }
+
+    /// Update loan in the DB. Returns the number of updated rows on Ok.
+    pub async fn update(&mut self, pool: &PgPool) -> Result<u64, LoanError> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE loans
+            SET borrower_id = $1, borrower_name = $2, lent_on = $3, due_on = $4, returned_on = $5
+            WHERE id = $6
+            "#,
+            self.borrower_id,
+            self.borrower_name,
+            self.lent_on,
+            self.due_on,
+            self.returned_on,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected())
+    }
+
+    /// Marks the copy as given back on `returned_on`.
+    pub async fn mark_returned(
+        &mut self,
+        pool: &PgPool,
+        returned_on: NaiveDate,
+    ) -> Result<(), LoanError> {
+        self.returned_on = Some(returned_on);
+        self.update(pool).await?;
+
+        Ok(())
+    }
+
+    /// Fetch a loan by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Loan,
+            "SELECT id, copy_id, borrower_id, borrower_name, lent_on, due_on, returned_on FROM loans WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The copies `user_id` has lent out and not got back, soonest due first.
+    pub async fn outstanding(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Loan,
+            r#"
+            SELECT l.id, l.copy_id, l.borrower_id, l.borrower_name, l.lent_on, l.due_on, l.returned_on
+            FROM loans l
+            JOIN owned_copies c ON c.id = l.copy_id
+            WHERE c.user_id = $1 AND l.returned_on IS NULL
+            ORDER BY l.due_on NULLS LAST, l.lent_on, l.id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Outstanding loans of `user_id` that were due before `today`, most
+    /// overdue first.
+    pub async fn overdue(
+        pool: &PgPool,
+        user_id: i64,
+        today: NaiveDate,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Loan,
+            r#"
+            SELECT l.id, l.copy_id, l.borrower_id, l.borrower_name, l.lent_on, l.due_on, l.returned_on
+            FROM loans l
+            JOIN owned_copies c ON c.id = l.copy_id
+            WHERE c.user_id = $1 AND l.returned_on IS NULL AND l.due_on < $2
+            ORDER BY l.due_on, l.id
+            "#,
+            user_id,
+            today
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Books `user_id` has borrowed from other users and not given back.
+    pub async fn borrowed_by(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Loan,
+            r#"
+            SELECT id, copy_id, borrower_id, borrower_name, lent_on, due_on, returned_on
+            FROM loans
+            WHERE borrower_id = $1 AND returned_on IS NULL
+            ORDER BY due_on NULLS LAST, id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Every loan of `user_id`'s copies of a book, most recent first.
+    pub async fn history_for_book(
+        pool: &PgPool,
+        user_id: i64,
+        book_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Loan,
+            r#"
+            SELECT l.id, l.copy_id, l.borrower_id, l.borrower_name, l.lent_on, l.due_on, l.returned_on
+            FROM loans l
+            JOIN owned_copies c ON c.id = l.copy_id
+            WHERE c.user_id = $1 AND c.book_id = $2
+            ORDER BY l.lent_on DESC, l.id DESC
+            "#,
+            user_id,
+            book_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM loans WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user};
+    use crate::models::owned_copy::OwnedCopyBuilder;
+    use crate::test_utils::setup_db;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 6, d).unwrap()
+    }
+
+    #[tokio::test]
+    async fn copy_cannot_be_lent_twice_at_once() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut owner = fake_user();
+        owner.create(&pool).await.unwrap();
+        let mut friend = fake_user();
+        friend.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        let mut copy = OwnedCopyBuilder::default()
+            .user_id(owner.id)
+            .book_id(book.id)
+            .build()
+            .unwrap();
+        copy.create(&pool).await.unwrap();
+
+        let mut to_friend = LoanBuilder::default()
+            .copy_id(copy.id)
+            .borrower_id(Some(friend.id))
+            .lent_on(day(1))
+            .due_on(Some(day(10)))
+            .build()
+            .unwrap();
+        let mut to_neighbour = LoanBuilder::default()
+            .copy_id(copy.id)
+            .borrower_name(Some("The neighbour".to_string()))
+            .lent_on(day(5))
+            .build()
+            .unwrap();
+
+        // Act
+        to_friend.create(&pool).await.unwrap();
+        let while_lent = to_neighbour.create(&pool).await;
+        let overdue = Loan::overdue(&pool, owner.id, day(12)).await.unwrap();
+        let borrowed = Loan::borrowed_by(&pool, friend.id).await.unwrap();
+        to_friend.mark_returned(&pool, day(12)).await.unwrap();
+        to_neighbour.lent_on = day(12);
+        to_neighbour.create(&pool).await.unwrap();
+        let outstanding = Loan::outstanding(&pool, owner.id).await.unwrap();
+        let history = Loan::history_for_book(&pool, owner.id, book.id)
+            .await
+            .unwrap();
+        friend.delete(&pool).await.unwrap();
+        let after_account_deleted = Loan::get(&pool, to_friend.id).await.unwrap();
+
+        // Cleanup
+        owner.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(while_lent, Err(LoanError::AlreadyLent)));
+        assert_eq!(
+            vec![to_friend.id],
+            overdue.iter().map(|l| l.id).collect::<Vec<_>>()
+        );
+        assert_eq!(1, borrowed.len());
+        assert_eq!(vec![to_neighbour.clone()], outstanding);
+        assert_eq!(vec![to_neighbour, to_friend], history);
+        assert_eq!(None, after_account_deleted.borrower_id);
+        assert_eq!(Some(friend.name), after_account_deleted.borrower_name);
+    }
+}
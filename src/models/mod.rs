@@ -1,3 +1,17 @@
+pub mod activity;
 pub mod book;
+pub mod book_document;
+pub mod buddy_read;
+pub mod club;
+pub mod cover;
+pub mod follow;
+pub mod highlight;
+pub mod kosync;
+pub mod library_loan;
+pub mod loan;
+pub mod notification;
+pub mod owned_copy;
+pub mod review;
 pub mod user;
 pub mod user_book;
+pub mod work;
@@ -0,0 +1,116 @@
+// Messages for users, such as reading reminders. They're stored when created
+// and marked delivered once a notifier has sent them.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "notification_kind", rename_all = "kebab-case")]
+pub enum NotificationKind {
+    ReadingReminder,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Notification {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: NotificationKind,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    /// `None` until a notifier has sent it.
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// When it is no longer sent, if ever.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Notification {
+    /// Stores a notification for `user_id`, created at `created_at` and not
+    /// sent after `expires_at`.
+    pub async fn create(
+        pool: &PgPool,
+        user_id: i64,
+        kind: NotificationKind,
+        body: &str,
+        created_at: DateTime<Utc>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Notification,
+            r#"
+            INSERT INTO notifications (user_id, kind, body, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, kind as "kind: NotificationKind", body, created_at, delivered_at, expires_at
+            "#,
+            user_id,
+            kind as NotificationKind,
+            body,
+            created_at,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The notifications of `user_id`, newest first.
+    pub async fn for_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Notification,
+            r#"
+            SELECT id, user_id, kind as "kind: NotificationKind", body, created_at, delivered_at, expires_at
+            FROM notifications
+            WHERE user_id = $1
+            ORDER BY created_at DESC, id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Every notification not delivered yet and not expired at `now`, oldest
+    /// first.
+    pub async fn undelivered(pool: &PgPool, now: DateTime<Utc>) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Notification,
+            r#"
+            SELECT id, user_id, kind as "kind: NotificationKind", body, created_at, delivered_at, expires_at
+            FROM notifications
+            WHERE delivered_at IS NULL AND (expires_at IS NULL OR expires_at > $1)
+            ORDER BY id
+            "#,
+            now
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Marks the notification delivered now.
+    pub async fn mark_delivered(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let delivered_at = Utc::now();
+        let updated = sqlx::query!(
+            "UPDATE notifications SET delivered_at = $2 WHERE id = $1",
+            self.id,
+            delivered_at
+        )
+        .execute(pool)
+        .await?;
+        self.delivered_at = Some(delivered_at);
+
+        Ok(updated.rows_affected())
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM notifications WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
@@ -0,0 +1,315 @@
+// Physical copies of books a user owns, with where they are kept and what
+// they cost. A user can own several copies of the same book.
+
+use chrono::NaiveDate;
+use derive_builder::Builder;
+use sqlx::PgPool;
+
+/// Condition of a copy, using the grades booksellers use.
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "copy_condition", rename_all = "kebab-case")]
+pub enum CopyCondition {
+    New,
+    Fine,
+    VeryGood,
+    Good,
+    Fair,
+    Poor,
+}
+
+#[derive(Debug, Builder, PartialEq, Clone)]
+pub struct OwnedCopy {
+    #[builder(default = 0)]
+    pub id: i64,
+    pub user_id: i64,
+    pub book_id: i64,
+    #[builder(default = None)]
+    pub condition: Option<CopyCondition>,
+    #[builder(default = None)]
+    pub acquired_on: Option<NaiveDate>,
+    /// In the smallest unit of the user's currency, e.g. cents.
+    #[builder(default = None)]
+    pub price_paid_cents: Option<i32>,
+    /// Where the copy is kept, e.g. "Living room shelf".
+    #[builder(default = None)]
+    pub location: Option<String>,
+    #[builder(default = false)]
+    pub signed: bool,
+    #[builder(default = false)]
+    pub first_edition: bool,
+}
+
+/// A place copies are kept and how many are kept there.
+#[derive(Debug, PartialEq)]
+pub struct Location {
+    pub location: String,
+    pub copies: i64,
+}
+
+impl OwnedCopy {
+    /// Insert copy into the DB
+    pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let res = sqlx::query!(
+            r#"
+            INSERT INTO owned_copies (user_id, book_id, condition, acquired_on, price_paid_cents, location, signed, first_edition)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id
+            "#,
+            self.user_id,
+            self.book_id,
+            self.condition as Option<CopyCondition>,
+            self.acquired_on,
+            self.price_paid_cents,
+            self.location,
+            self.signed,
+            self.first_edition
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.id = res.id;
+
+        Ok(())
+    }
+
+    /// Update copy in the DB. Returns the number of updated rows on Ok.
+    pub async fn update(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE owned_copies
+            SET book_id = $1, condition = $2, acquired_on = $3, price_paid_cents = $4, location = $5,
+                signed = $6, first_edition = $7
+            WHERE id = $8
+            "#,
+            self.book_id,
+            self.condition as Option<CopyCondition>,
+            self.acquired_on,
+            self.price_paid_cents,
+            self.location,
+            self.signed,
+            self.first_edition,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected())
+    }
+
+    /// Fetch a copy by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            OwnedCopy,
+            r#"
+            SELECT id, user_id, book_id, condition as "condition: CopyCondition", acquired_on, price_paid_cents,
+                   location, signed, first_edition
+            FROM owned_copies
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// All copies a user owns, by location and then oldest first.
+    pub async fn for_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            OwnedCopy,
+            r#"
+            SELECT id, user_id, book_id, condition as "condition: CopyCondition", acquired_on, price_paid_cents,
+                   location, signed, first_edition
+            FROM owned_copies
+            WHERE user_id = $1
+            ORDER BY lower(trim(location)) NULLS LAST, id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// The copies a user keeps at `location`, ignoring case and surrounding
+    /// whitespace.
+    pub async fn at_location(
+        pool: &PgPool,
+        user_id: i64,
+        location: &str,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            OwnedCopy,
+            r#"
+            SELECT id, user_id, book_id, condition as "condition: CopyCondition", acquired_on, price_paid_cents,
+                   location, signed, first_edition
+            FROM owned_copies
+            WHERE user_id = $1 AND lower(trim(location)) = lower(trim($2))
+            ORDER BY id
+            "#,
+            user_id,
+            location
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// The places a user keeps copies, with the number of copies at each.
+    pub async fn locations(pool: &PgPool, user_id: i64) -> Result<Vec<Location>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Location,
+            r#"
+            SELECT min(trim(location)) AS "location!", COUNT(*) AS "copies!"
+            FROM owned_copies
+            WHERE user_id = $1 AND location IS NOT NULL
+            GROUP BY lower(trim(location))
+            ORDER BY lower(trim(location))
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// What the user paid for their copies, optionally only those at
+    /// `location`. Copies without a price count as 0.
+    pub async fn total_value(
+        pool: &PgPool,
+        user_id: i64,
+        location: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let record = sqlx::query!(
+            r#"
+            SELECT COALESCE(SUM(price_paid_cents), 0)::int8 AS "total!"
+            FROM owned_copies
+            WHERE user_id = $1 AND ($2::text IS NULL OR lower(trim(location)) = lower(trim($2)))
+            "#,
+            user_id,
+            location
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record.total)
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM owned_copies WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user};
+    use crate::test_utils::setup_db;
+
+    #[tokio::test]
+    async fn create_update_and_get() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        let mut copy = OwnedCopyBuilder::default()
+            .user_id(user.id)
+            .book_id(book.id)
+            .condition(Some(CopyCondition::VeryGood))
+            .acquired_on(NaiveDate::from_ymd_opt(2024, 5, 1))
+            .price_paid_cents(Some(1250))
+            .signed(true)
+            .build()
+            .unwrap();
+
+        // Act
+        copy.create(&pool).await.unwrap();
+        copy.location = Some("Study".to_string());
+        copy.first_edition = true;
+        let updated_rows = copy.update(&pool).await.unwrap();
+        let fetched = OwnedCopy::get(&pool, copy.id).await.unwrap();
+        let deleted_rows = copy.delete(&pool).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(copy, fetched);
+        assert_eq!(1, updated_rows);
+        assert_eq!(1, deleted_rows);
+    }
+
+    #[tokio::test]
+    async fn inventory_by_location() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        let mut copies = Vec::new();
+        for (location, price) in [
+            (Some("Living room"), Some(1000)),
+            (Some(" Living room "), Some(550)),
+            (Some("Bedroom"), None),
+            (None, Some(2000)),
+        ] {
+            let mut copy = OwnedCopyBuilder::default()
+                .user_id(user.id)
+                .book_id(book.id)
+                .location(location.map(str::to_string))
+                .price_paid_cents(price)
+                .build()
+                .unwrap();
+            copy.create(&pool).await.unwrap();
+            copies.push(copy);
+        }
+
+        // Act
+        let living_room = OwnedCopy::at_location(&pool, user.id, "LIVING ROOM")
+            .await
+            .unwrap();
+        let locations = OwnedCopy::locations(&pool, user.id).await.unwrap();
+        let total = OwnedCopy::total_value(&pool, user.id, None).await.unwrap();
+        let living_room_total = OwnedCopy::total_value(&pool, user.id, Some("Living room"))
+            .await
+            .unwrap();
+        let all = OwnedCopy::for_user(&pool, user.id).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(copies[..2], living_room[..]);
+        assert_eq!(
+            vec![
+                Location {
+                    location: "Bedroom".to_string(),
+                    copies: 1,
+                },
+                Location {
+                    location: "Living room".to_string(),
+                    copies: 2,
+                },
+            ],
+            locations
+        );
+        assert_eq!(3550, total);
+        assert_eq!(1550, living_room_total);
+        assert_eq!(4, all.len());
+        assert_eq!(None, all[3].location);
+    }
+}
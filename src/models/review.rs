@@ -0,0 +1,287 @@
+// Written reviews of books on users' shelves. The rating stays on the shelf
+// entry, a review is the text that goes with it.
+
+use crate::models::activity::{Activity, ActivityKind};
+use crate::models::user::Visibility;
+use crate::models::user_book::UserBook;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Review {
+    pub user_id: i64,
+    pub book_id: i64,
+    pub body: String,
+    /// Who can see the review. It is hidden as well from those who can't see
+    /// the shelf entry it's on.
+    pub visibility: Visibility,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Review {
+    /// A new public review, not yet in the DB.
+    pub fn new(user_id: i64, book_id: i64, body: String) -> Self {
+        let now = Utc::now();
+        Review {
+            user_id,
+            book_id,
+            body,
+            visibility: Visibility::Public,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Insert the review into the DB. The book has to be on the user's shelf.
+    pub async fn create(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO reviews (user_id, book_id, body, visibility, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            self.user_id,
+            self.book_id,
+            self.body,
+            self.visibility as Visibility,
+            self.created_at,
+            self.updated_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let user_book = UserBook::get_in(&mut tx, self.book_id, self.user_id).await?;
+        Activity::record(&mut tx, &user_book, ActivityKind::Reviewed).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Saves an edited review. Returns the number of updated rows on Ok.
+    pub async fn update(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        self.updated_at = Utc::now();
+        let updated = sqlx::query!(
+            r#"
+            UPDATE reviews SET body = $1, visibility = $2, updated_at = $3
+            WHERE user_id = $4 AND book_id = $5
+            "#,
+            self.body,
+            self.visibility as Visibility,
+            self.updated_at,
+            self.user_id,
+            self.book_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected())
+    }
+
+    /// A user's review of a book, if they wrote one and `viewer_id` can see it.
+    pub async fn get(
+        pool: &PgPool,
+        book_id: i64,
+        user_id: i64,
+        viewer_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Review,
+            r#"
+            SELECT r.user_id, r.book_id, r.body, r.visibility as "visibility: Visibility", r.created_at, r.updated_at
+            FROM reviews r
+            JOIN user_books ub ON ub.user_id = r.user_id AND ub.book_id = r.book_id
+            JOIN users u ON u.id = r.user_id
+            WHERE r.book_id = $1 AND r.user_id = $2
+              AND visible_to(r.user_id, $3, LEAST(u.visibility, ub.visibility, r.visibility))
+            "#,
+            book_id,
+            user_id,
+            viewer_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The reviews of a book `viewer_id` can see, newest first.
+    pub async fn for_book(
+        pool: &PgPool,
+        book_id: i64,
+        viewer_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Review,
+            r#"
+            SELECT r.user_id, r.book_id, r.body, r.visibility as "visibility: Visibility", r.created_at, r.updated_at
+            FROM reviews r
+            JOIN user_books ub ON ub.user_id = r.user_id AND ub.book_id = r.book_id
+            JOIN users u ON u.id = r.user_id
+            WHERE r.book_id = $1
+              AND visible_to(r.user_id, $2, LEAST(u.visibility, ub.visibility, r.visibility))
+            ORDER BY r.created_at DESC, r.user_id
+            "#,
+            book_id,
+            viewer_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Deletes the row associated with the record
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM reviews WHERE user_id = $1 AND book_id = $2",
+            self.user_id,
+            self.book_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user, fake_user_book};
+    use crate::test_utils::{setup_db, shelf_by_visibility};
+    use chrono::Timelike;
+    use sqlx::postgres::PgPoolOptions;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn create_update_and_list() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        fake_user_book(user.id, book.id)
+            .create(&pool)
+            .await
+            .unwrap();
+        let mut review = Review::new(user.id, book.id, "Gripping.".to_string());
+        review.created_at = review.created_at.with_nanosecond(0).unwrap();
+        review.updated_at = review.created_at;
+
+        // Act
+        review.create(&pool).await.unwrap();
+        review.body = "Gripping, if long.".to_string();
+        review.update(&pool).await.unwrap();
+        let fetched = Review::get(&pool, book.id, user.id, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let for_book = Review::for_book(&pool, book.id, user.id).await.unwrap();
+        let activities = Activity::for_user(&pool, user.id, user.id).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!("Gripping, if long.", fetched.body);
+        assert_eq!(review.created_at, fetched.created_at);
+        assert!(fetched.updated_at > fetched.created_at);
+        assert_eq!(vec![fetched], for_book);
+        assert_eq!(ActivityKind::Reviewed, activities[0].kind);
+    }
+
+    #[tokio::test]
+    async fn create_needs_a_single_connection() {
+        // Arrange
+        let pool = setup_db().await;
+        let single = PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_secs(5))
+            .connect_with((*pool.connect_options()).clone())
+            .await
+            .unwrap();
+        let mut user = fake_user();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        fake_user_book(user.id, book.id)
+            .create(&pool)
+            .await
+            .unwrap();
+
+        // Act
+        let created = Review::new(user.id, book.id, "Short and sweet.".to_string())
+            .create(&single)
+            .await;
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(created.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reviews_hidden_by_visibility() {
+        // Arrange
+        let pool = setup_db().await;
+        let (author_user, follower_user, stranger_user, books) = shelf_by_visibility(&pool).await;
+        let (author, follower, stranger) = (author_user.id, follower_user.id, stranger_user.id);
+        let mut reviews = Vec::new();
+        for book in &books {
+            let review = Review::new(author, book.id, "Fine.".to_string());
+            review.create(&pool).await.unwrap();
+            reviews.push(review);
+        }
+        let visible = async |viewer| {
+            let mut visible = Vec::new();
+            for book in &books {
+                visible.push(
+                    !Review::for_book(&pool, book.id, viewer)
+                        .await
+                        .unwrap()
+                        .is_empty(),
+                );
+            }
+            visible
+        };
+
+        // Act
+        let as_author = visible(author).await;
+        let as_follower = visible(follower).await;
+        let as_stranger = visible(stranger).await;
+        reviews[0].visibility = Visibility::Followers;
+        reviews[0].update(&pool).await.unwrap();
+        let followers_as_follower = Review::get(&pool, books[0].id, author, follower)
+            .await
+            .unwrap();
+        let followers_as_stranger = Review::get(&pool, books[0].id, author, stranger)
+            .await
+            .unwrap();
+        reviews[0].visibility = Visibility::Private;
+        reviews[0].update(&pool).await.unwrap();
+        let private_as_follower = Review::get(&pool, books[0].id, author, follower)
+            .await
+            .unwrap();
+
+        // Cleanup
+        for user in [&author_user, &follower_user, &stranger_user] {
+            user.delete(&pool).await.unwrap();
+        }
+        for book in &books {
+            book.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert_eq!(vec![true, true, true], as_author);
+        assert_eq!(vec![true, true, false], as_follower);
+        assert_eq!(vec![true, false, false], as_stranger);
+        assert!(followers_as_follower.is_some());
+        assert_eq!(None, followers_as_stranger);
+        assert_eq!(None, private_as_follower);
+    }
+}
@@ -1,6 +1,17 @@
 use derive_builder::Builder;
 use sqlx::PgPool;
 
+/// Who can see a profile, a shelf entry or a review besides its owner. The
+/// levels are ordered from most to least restrictive.
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "visibility", rename_all = "lowercase")]
+pub enum Visibility {
+    Private,
+    /// Only users whose follow the owner accepted.
+    Followers,
+    Public,
+}
+
 #[derive(Debug, Builder, PartialEq)]
 pub struct User {
     #[builder(default = 0)]
@@ -8,16 +19,31 @@ pub struct User {
     pub name: String,
     pub email: String,
     pub password: String,
+    /// Caps the visibility of everything on the user's shelf. Follows have
+    /// to be accepted by the user unless it is `Public`.
+    #[builder(default = Visibility::Public)]
+    pub visibility: Visibility,
+    /// IANA time zone, such as `Europe/Copenhagen`, the user's reading days
+    /// are counted in.
+    #[builder(default = "UTC".to_string())]
+    pub timezone: String,
+    /// Local hour after which the user is reminded to read if they haven't
+    /// yet that day, `None` for no reminders.
+    #[builder(default = None)]
+    pub reminder_hour: Option<i16>,
 }
 
 impl User {
     /// Insert user into the DB
     pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
         let res = sqlx::query!(
-            "INSERT INTO users (name, email, password) VALUES ($1, $2, $3) RETURNING id",
+            "INSERT INTO users (name, email, password, visibility, timezone, reminder_hour) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
             self.name,
             self.email,
-            self.password
+            self.password,
+            self.visibility as Visibility,
+            self.timezone,
+            self.reminder_hour
         )
         .fetch_one(pool)
         .await?;
@@ -30,10 +56,13 @@ impl User {
     /// Update this user row in place.
     pub async fn update(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
         let updated = sqlx::query!(
-            "UPDATE users SET name = $1, email = $2, password = $3 WHERE id = $4",
+            "UPDATE users SET name = $1, email = $2, password = $3, visibility = $4, timezone = $5, reminder_hour = $6 WHERE id = $7",
             self.name,
             self.email,
             self.password,
+            self.visibility as Visibility,
+            self.timezone,
+            self.reminder_hour,
             self.id
         )
         .execute(pool)
@@ -45,7 +74,7 @@ impl User {
     pub async fn fetch(&self, pool: &PgPool) -> Result<Self, sqlx::Error> {
         let record = sqlx::query_as!(
             User,
-            "SELECT id, name, email, password FROM users WHERE id = $1",
+            r#"SELECT id, name, email, password, visibility as "visibility: Visibility", timezone, reminder_hour FROM users WHERE id = $1"#,
             self.id
         )
         .fetch_one(pool)
@@ -57,7 +86,7 @@ impl User {
     pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
         let record = sqlx::query_as!(
             User,
-            "SELECT id, name, email, password FROM users WHERE id = $1",
+            r#"SELECT id, name, email, password, visibility as "visibility: Visibility", timezone, reminder_hour FROM users WHERE id = $1"#,
             id
         )
         .fetch_one(pool)
@@ -65,6 +94,18 @@ impl User {
         Ok(record)
     }
 
+    /// Fetch a user by its email.
+    pub async fn get_by_email(pool: &PgPool, email: &str) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            User,
+            r#"SELECT id, name, email, password, visibility as "visibility: Visibility", timezone, reminder_hour FROM users WHERE email = $1"#,
+            email
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(record)
+    }
+
     /// Deletes the row associated with the record
     pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
         let deleted = sqlx::query_as!(User, "DELETE FROM users WHERE id = $1", self.id)
@@ -1,12 +1,14 @@
 // Table for a many-to-many relationship between a user and a book, holding
 // information that a specific user has on a specific book
 
-use crate::models::book::Book;
-use crate::models::user::User;
+use crate::achievements::{self, Change};
+use crate::models::activity::{Activity, ActivityKind};
+use crate::models::book::{Book, BookFormat, Length};
+use crate::models::user::{User, Visibility};
 use chrono::{DateTime, Utc};
 use derive_builder::Builder;
 use fake::rand;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 
 #[derive(Debug, Clone, sqlx::Type, PartialEq, Copy)]
 #[sqlx(type_name = "reading_status")]
@@ -29,31 +31,144 @@ impl fake::Dummy<fake::Faker> for ReadingStatus {
     }
 }
 
+/// A reading position, in whichever unit the reader has at hand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    Page(i32),
+    /// From 0 to 100.
+    Percent(f64),
+    /// Seconds from the start of an audiobook.
+    Timestamp(i32),
+}
+
+/// How far into a book a user was at some point. Logged whenever a shelf
+/// entry is saved with a changed `progress_percent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressEntry {
+    pub progress_percent: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum EditionSwitchError {
+    /// The editions don't belong to the same work.
+    DifferentWork,
+    /// The user already has the other edition on their shelf.
+    AlreadyOnShelf,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for EditionSwitchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditionSwitchError::DifferentWork => write!(f, "the editions are of different works"),
+            EditionSwitchError::AlreadyOnShelf => write!(f, "the edition is already on the shelf"),
+            EditionSwitchError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for EditionSwitchError {}
+
+impl From<sqlx::Error> for EditionSwitchError {
+    fn from(e: sqlx::Error) -> Self {
+        EditionSwitchError::Database(e)
+    }
+}
+
 #[derive(Debug, Builder, sqlx::FromRow)]
 pub struct UserBook {
-    user_id: i64,
-    book_id: i64,
+    pub user_id: i64,
+    pub book_id: i64,
     #[builder(default = ReadingStatus::ToRead)]
-    status: ReadingStatus,
+    pub status: ReadingStatus,
     #[builder(default = None)]
-    rating: Option<i16>,
+    pub rating: Option<i16>,
     #[builder(default = Utc::now())]
-    added_at: DateTime<Utc>,
+    pub added_at: DateTime<Utc>,
     #[builder(default = None)]
-    began_reading: Option<DateTime<Utc>>,
+    pub began_reading: Option<DateTime<Utc>>,
     #[builder(default = None)]
-    done_reading: Option<DateTime<Utc>>,
+    pub done_reading: Option<DateTime<Utc>>,
     #[builder(default = None)]
-    current_page: Option<i32>,
+    pub current_page: Option<i32>,
+    #[builder(default = None)]
+    pub current_seconds: Option<i32>,
+    /// How far into the book the user is, from 0 to 100, whatever the format.
+    /// `None` when the position can't be related to the book's length.
+    #[builder(default = None)]
+    pub progress_percent: Option<f64>,
+    /// Who can see the entry, along with its activities and highlights. The
+    /// owner's profile visibility applies as well when it is stricter.
+    #[builder(default = Visibility::Public)]
+    pub visibility: Visibility,
 }
 
 impl UserBook {
-    /// Creates a new instance of `UserBook` and adds it to the database.
+    /// Moves the reading position of `book`, the book of this entry, to
+    /// `progress`. The position is kept as a percentage as well as a page
+    /// and, for audiobooks, a timestamp where the book's length allows it.
+    /// Starts the book if it hadn't been started, and completes it at 100%.
+    /// Only changes the struct, call `update` to save it.
+    pub fn set_progress(&mut self, progress: Progress, book: &Book) {
+        (
+            self.progress_percent,
+            self.current_page,
+            self.current_seconds,
+        ) = position(progress, book);
+
+        let now = Utc::now();
+        let started = match progress {
+            Progress::Page(page) => page > 0,
+            Progress::Percent(percent) => percent > 0.0,
+            Progress::Timestamp(seconds) => seconds > 0,
+        };
+        if self
+            .progress_percent
+            .is_some_and(|percent| percent >= 100.0)
+        {
+            self.status = ReadingStatus::Completed;
+            self.began_reading.get_or_insert(now);
+            self.done_reading.get_or_insert(now);
+        } else if started {
+            self.status = ReadingStatus::Reading;
+            self.began_reading.get_or_insert(now);
+            self.done_reading = None;
+        }
+    }
+
+    /// Creates a new instance of `UserBook` and adds it to the database,
+    /// awarding the user any badges it earns them.
     pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        self.fill_position(pool, None).await?;
+        let mut tx = pool.begin().await?;
+        self.insert(&mut tx).await?;
+        self.record_progress(&mut tx).await?;
+        Activity::record_changes(&mut tx, None, self).await?;
+        achievements::evaluate(&mut tx, self.user_id, Change::between(None, self)).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Adds an entry brought in from elsewhere, such as another reading
+    /// tool, like `create` but without recording activities: reading done
+    /// long ago shouldn't flood followers' feeds.
+    pub async fn import(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        self.fill_position(pool, None).await?;
+        let mut tx = pool.begin().await?;
+        self.insert(&mut tx).await?;
+        self.record_progress(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn insert(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
-            INSERT INTO user_books (user_id, book_id, status, rating, added_at, began_reading, done_reading, current_page)
-            VALUES ($1, $2, $3::reading_status, $4, $5, $6, $7, $8)
+            INSERT INTO user_books (user_id, book_id, status, rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility)
+            VALUES ($1, $2, $3::reading_status, $4, $5, $6, $7, $8, $9, $10, $11)
             "#,
             self.user_id,
             self.book_id,
@@ -62,21 +177,38 @@ impl UserBook {
             self.added_at,
             self.began_reading,
             self.done_reading,
-            self.current_page
+            self.current_page,
+            self.current_seconds,
+            self.progress_percent,
+            self.visibility as Visibility
         )
-            .execute(pool)
+            .execute(conn)
             .await?;
 
         Ok(())
     }
 
-    /// Synchronizes the information in the struct to the database.
+    /// Synchronizes the information in the struct to the database, recording
+    /// the activities the changes make for the user's followers and awarding
+    /// any badges they earn. The entry is locked while it is compared and
+    /// saved, so concurrent saves record their activities one after another.
     pub async fn update(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let before = sqlx::query_as!(
+            UserBook,
+            r#"SELECT user_id, book_id, status as "status: ReadingStatus", rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility as "visibility: Visibility" FROM user_books WHERE book_id = $1 AND user_id = $2 FOR UPDATE"#,
+            self.book_id,
+            self.user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        self.fill_position(pool, before.as_ref()).await?;
         let updated = sqlx::query!(
             r#"
             UPDATE user_books
-            SET user_id = $1, book_id = $2, status = $3::reading_status, rating = $4, added_at = $5, began_reading = $6, done_reading = $7, current_page = $8
-            WHERE user_id = $9 AND book_id = $10
+            SET user_id = $1, book_id = $2, status = $3::reading_status, rating = $4, added_at = $5, began_reading = $6, done_reading = $7, current_page = $8,
+                current_seconds = $9, progress_percent = $10, visibility = $11
+            WHERE user_id = $12 AND book_id = $13
             "#,
             self.user_id,
             self.book_id,
@@ -86,20 +218,112 @@ impl UserBook {
             self.began_reading,
             self.done_reading,
             self.current_page,
+            self.current_seconds,
+            self.progress_percent,
+            self.visibility as Visibility,
             self.user_id,
             self.book_id,
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+        if let Some(before) = before
+            && updated.rows_affected() > 0
+        {
+            self.record_progress(&mut tx).await?;
+            Activity::record_changes(&mut tx, Some(&before), self).await?;
+            let change = Change::between(Some(&before), self);
+            achievements::evaluate(&mut tx, self.user_id, change).await?;
+        }
+        tx.commit().await?;
 
         Ok(updated.rows_affected())
     }
 
+    /// Fills in the percentage, and the page or timestamp, from a page or
+    /// timestamp set directly on the struct rather than with `set_progress`,
+    /// so the progress history and everything built on it see the change.
+    /// `before` is the entry as saved, `None` for a new entry.
+    async fn fill_position(
+        &mut self,
+        pool: &PgPool,
+        before: Option<&UserBook>,
+    ) -> Result<(), sqlx::Error> {
+        if self.progress_percent != before.and_then(|b| b.progress_percent) {
+            return Ok(());
+        }
+        let progress = match (self.current_page, self.current_seconds) {
+            (Some(page), _) if self.current_page != before.and_then(|b| b.current_page) => {
+                Progress::Page(page)
+            }
+            (_, Some(seconds))
+                if self.current_seconds != before.and_then(|b| b.current_seconds) =>
+            {
+                Progress::Timestamp(seconds)
+            }
+            _ => return Ok(()),
+        };
+
+        let book = Book::get(pool, self.book_id).await?;
+        (
+            self.progress_percent,
+            self.current_page,
+            self.current_seconds,
+        ) = position(progress, &book);
+
+        Ok(())
+    }
+
+    /// Logs the progress in the reading progress history, unless it is what
+    /// was logged last.
+    async fn record_progress(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        let Some(percent) = self.progress_percent else {
+            return Ok(());
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO reading_progress (user_id, book_id, progress_percent)
+            SELECT $1::int8, $2::int8, $3::float8
+            WHERE $3 IS DISTINCT FROM (
+                SELECT progress_percent FROM reading_progress
+                WHERE user_id = $1 AND book_id = $2
+                ORDER BY recorded_at DESC, id DESC
+                LIMIT 1
+            )
+            "#,
+            self.user_id,
+            self.book_id,
+            percent
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The logged progress of this entry, oldest first.
+    pub async fn progress_history(&self, pool: &PgPool) -> Result<Vec<ProgressEntry>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ProgressEntry,
+            r#"
+            SELECT progress_percent, recorded_at
+            FROM reading_progress
+            WHERE user_id = $1 AND book_id = $2
+            ORDER BY recorded_at, id
+            "#,
+            self.user_id,
+            self.book_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
     /// Synchronizes the struct with the information in the database.
     pub async fn fetch(&self, pool: &PgPool) -> Result<Self, sqlx::Error> {
         let record = sqlx::query_as!(
             UserBook,
-            "SELECT user_id, book_id, status as \"status: ReadingStatus\", rating, added_at, began_reading, done_reading, current_page FROM user_books WHERE book_id = $1 AND user_id = $2",
+            r#"SELECT user_id, book_id, status as "status: ReadingStatus", rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility as "visibility: Visibility" FROM user_books WHERE book_id = $1 AND user_id = $2"#,
             self.book_id,
             self.user_id
         )
@@ -110,20 +334,213 @@ impl UserBook {
     }
 
     /// Gets a specific `user_book` instance from the database given a `book_id`
-    /// and `user_id`.
+    /// and `user_id`. Visibility isn't checked, use `get_visible` to show the
+    /// entry to anyone but its owner.
     pub async fn get(pool: &PgPool, book_id: i64, user_id: i64) -> Result<Self, sqlx::Error> {
+        Self::get_in(&mut *pool.acquire().await?, book_id, user_id).await
+    }
+
+    /// `get` in a transaction of the caller's.
+    pub(crate) async fn get_in(
+        conn: &mut PgConnection,
+        book_id: i64,
+        user_id: i64,
+    ) -> Result<Self, sqlx::Error> {
         let record = sqlx::query_as!(
             UserBook,
-            "SELECT user_id, book_id, status as \"status: ReadingStatus\", rating, added_at, began_reading, done_reading, current_page FROM user_books WHERE book_id = $1 AND user_id = $2",
+            r#"SELECT user_id, book_id, status as "status: ReadingStatus", rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility as "visibility: Visibility" FROM user_books WHERE book_id = $1 AND user_id = $2"#,
             book_id,
             user_id
         )
-            .fetch_one(pool)
+            .fetch_one(conn)
             .await?;
 
         Ok(record)
     }
 
+    /// Gets `user_id`'s entry for a book as `viewer_id` sees it. `None` when
+    /// it isn't on the shelf, or the entry or the owner's profile is hidden
+    /// from the viewer.
+    pub async fn get_visible(
+        pool: &PgPool,
+        book_id: i64,
+        user_id: i64,
+        viewer_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            UserBook,
+            r#"
+            SELECT ub.user_id, ub.book_id, ub.status as "status: ReadingStatus", ub.rating, ub.added_at, ub.began_reading, ub.done_reading,
+                   ub.current_page, ub.current_seconds, ub.progress_percent, ub.visibility as "visibility: Visibility"
+            FROM user_books ub
+            JOIN users u ON u.id = ub.user_id
+            WHERE ub.book_id = $1 AND ub.user_id = $2 AND visible_to(ub.user_id, $3, LEAST(u.visibility, ub.visibility))
+            "#,
+            book_id,
+            user_id,
+            viewer_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Gets the user's entry for a book, adding the book to the user's shelf
+    /// first if it isn't on it yet.
+    pub async fn get_or_create(
+        pool: &PgPool,
+        book_id: i64,
+        user_id: i64,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let user_book = Self::get_or_create_in(&mut tx, book_id, user_id).await?;
+        tx.commit().await?;
+
+        Ok(user_book)
+    }
+
+    /// `get_or_create` in a transaction of the caller's.
+    pub(crate) async fn get_or_create_in(
+        conn: &mut PgConnection,
+        book_id: i64,
+        user_id: i64,
+    ) -> Result<Self, sqlx::Error> {
+        let inserted = sqlx::query_as!(
+            UserBook,
+            r#"
+            INSERT INTO user_books (user_id, book_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            RETURNING user_id, book_id, status as "status: ReadingStatus", rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility as "visibility: Visibility"
+            "#,
+            user_id,
+            book_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some(user_book) = inserted {
+            Activity::record(conn, &user_book, ActivityKind::Added).await?;
+            return Ok(user_book);
+        }
+
+        Self::get_in(conn, book_id, user_id).await
+    }
+
+    /// Moves this entry to another edition of the same work, taking the
+    /// user's highlights, library loan, review, progress history and
+    /// activities along. The position is carried over as a percentage,
+    /// so page 100 of 400 becomes page 50 of 200, or the matching timestamp
+    /// of an audiobook edition.
+    pub async fn switch_edition(
+        &mut self,
+        pool: &PgPool,
+        book_id: i64,
+    ) -> Result<(), EditionSwitchError> {
+        let from = Book::get(pool, self.book_id).await?;
+        let to = Book::get(pool, book_id).await?;
+        if from.work_id.is_none() || from.work_id != to.work_id {
+            return Err(EditionSwitchError::DifferentWork);
+        }
+
+        let percent = match (
+            self.progress_percent,
+            self.current_page,
+            self.current_seconds,
+        ) {
+            (Some(percent), _, _) => Some(percent),
+            (None, Some(page), _) => position(Progress::Page(page), &from).0,
+            (None, None, Some(seconds)) => position(Progress::Timestamp(seconds), &from).0,
+            (None, None, None) => None,
+        };
+        let (progress_percent, current_page, current_seconds) = match percent {
+            Some(percent) => position(Progress::Percent(percent), &to),
+            None => (None, self.current_page, self.current_seconds),
+        };
+
+        // Highlights reference the entry, so a new entry is made for them to
+        // move to before the old one is removed
+        let mut tx = pool.begin().await?;
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO user_books (user_id, book_id, status, rating, added_at, began_reading, done_reading, current_page, current_seconds, progress_percent, visibility)
+            VALUES ($1, $2, $3::reading_status, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT DO NOTHING
+            "#,
+            self.user_id,
+            book_id,
+            self.status as ReadingStatus,
+            self.rating,
+            self.added_at,
+            self.began_reading,
+            self.done_reading,
+            current_page,
+            current_seconds,
+            progress_percent,
+            self.visibility as Visibility
+        )
+        .execute(&mut *tx)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Err(EditionSwitchError::AlreadyOnShelf);
+        }
+
+        sqlx::query!(
+            "UPDATE highlights SET book_id = $1 WHERE user_id = $2 AND book_id = $3",
+            book_id,
+            self.user_id,
+            self.book_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE library_loans SET book_id = $1 WHERE user_id = $2 AND book_id = $3",
+            book_id,
+            self.user_id,
+            self.book_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE reading_progress SET book_id = $1 WHERE user_id = $2 AND book_id = $3",
+            book_id,
+            self.user_id,
+            self.book_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE reviews SET book_id = $1 WHERE user_id = $2 AND book_id = $3",
+            book_id,
+            self.user_id,
+            self.book_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE activities SET book_id = $1 WHERE user_id = $2 AND book_id = $3",
+            book_id,
+            self.user_id,
+            self.book_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM user_books WHERE user_id = $1 AND book_id = $2",
+            self.user_id,
+            self.book_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.book_id = book_id;
+        self.current_page = current_page;
+        self.current_seconds = current_seconds;
+        self.progress_percent = progress_percent;
+
+        Ok(())
+    }
+
     /// Deletes the row in the database associated with this instance.
     pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
         let deleted = sqlx::query!(
@@ -141,7 +558,7 @@ impl UserBook {
     pub async fn get_user(&self, pool: &PgPool) -> Result<User, sqlx::Error> {
         let user = sqlx::query_as!(
             User,
-            "SELECT id, name, email, password FROM users WHERE id = $1",
+            r#"SELECT id, name, email, password, visibility as "visibility: Visibility", timezone, reminder_hour FROM users WHERE id = $1"#,
             self.user_id
         )
         .fetch_one(pool)
@@ -154,7 +571,7 @@ impl UserBook {
     pub async fn get_book(&self, pool: &PgPool) -> Result<Book, sqlx::Error> {
         let book = sqlx::query_as!(
             Book,
-            "SELECT id, title, author, isbn, published_year, description, cover_url, pages FROM books WHERE id = $1",
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE id = $1"#,
             self.book_id
         )
             .fetch_one(pool)
@@ -164,12 +581,50 @@ impl UserBook {
     }
 }
 
+/// Where `progress` is in `book`, as a percentage, a page and a timestamp.
+/// Each is `None` when the book's length doesn't allow working it out, and
+/// only audiobooks get a timestamp and only other formats a page.
+fn position(progress: Progress, book: &Book) -> (Option<f64>, Option<i32>, Option<i32>) {
+    let percent = match (progress, book.length()) {
+        (Progress::Percent(percent), _) => Some(percent),
+        (Progress::Page(page), Some(Length::Pages(pages))) => {
+            Some(page as f64 * 100.0 / pages as f64)
+        }
+        (Progress::Timestamp(seconds), Some(Length::Minutes(minutes))) => {
+            Some(seconds as f64 * 100.0 / (minutes as f64 * 60.0))
+        }
+        _ => None,
+    }
+    .map(|percent| percent.clamp(0.0, 100.0));
+
+    let audiobook = book.format == BookFormat::Audiobook;
+    let page = match (progress, percent, book.pages) {
+        (Progress::Page(page), _, _) => Some(page),
+        (_, Some(percent), Some(pages)) if !audiobook => {
+            Some((percent * pages as f64 / 100.0).round() as i32)
+        }
+        _ => None,
+    };
+    let seconds = match (progress, percent, book.duration_minutes) {
+        (Progress::Timestamp(seconds), _, _) => Some(seconds),
+        (_, Some(percent), Some(minutes)) if audiobook => {
+            Some((percent * minutes as f64 * 60.0 / 100.0).round() as i32)
+        }
+        _ => None,
+    };
+
+    (percent, page, seconds)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::factories;
     use crate::models::book::BookBuilder;
+    use crate::models::follow::Follow;
+    use crate::models::highlight::{Highlight, HighlightBuilder};
     use crate::models::user::UserBuilder;
+    use crate::models::work::Work;
     use crate::test_utils::setup_db;
     use chrono::Timelike;
 
@@ -193,7 +648,7 @@ mod tests {
             .published_year(2012)
             .description(Some("FYODOR MIKAILOVICH DOSTOEVSKY's life was as dark and dramatic as the great novels he wrote. He was born in Moscow in 1821. A short first novel, Poor Folk (1846) brought him instant success, but his writing career was cut short by his arrest for alleged subversion against Tsar Nicholas I in 1849. In prison he was given the “silent treatment” for eight months (guards even wore velvet soled boots) before he was led in front a firing squad. Dressed in a death shroud, he faced an open grave and awaited execution, when suddenly, an order arrived commuting his sentence. He then spent four years at hard labor in a Siberian prison, where he began to suffer from epilepsy, and he returned to St. Petersburg only a full ten years after he had left in chains.\n His prison experiences coupled with his conversion to a profoundly religious philosophy formed the basis for his great novels. But it was his fortuitous marriage to Anna Snitkina, following a period of utter destitution brought about by his compulsive gambling, that gave Dostoevsky the emotional stability to complete Crime and Punishment (1866), The Idiot (1868-69), The Possessed (1871-72),and The Brothers Karamazov (1879-80). When Dostoevsky died in 1881, he left a legacy of masterworks that influenced the great thinkers and writers of the Western world and immortalized him as a giant among writers of world literature.".to_string()))
             .cover_url(None)
-            .pages(592)
+            .pages(Some(592))
             .build()
             .unwrap();
 
@@ -251,7 +706,7 @@ mod tests {
             .published_year(2012)
             .description(Some("FYODOR MIKAILOVICH DOSTOEVSKY's life was as dark and dramatic as the great novels he wrote. He was born in Moscow in 1821. A short first novel, Poor Folk (1846) brought him instant success, but his writing career was cut short by his arrest for alleged subversion against Tsar Nicholas I in 1849. In prison he was given the “silent treatment” for eight months (guards even wore velvet soled boots) before he was led in front a firing squad. Dressed in a death shroud, he faced an open grave and awaited execution, when suddenly, an order arrived commuting his sentence. He then spent four years at hard labor in a Siberian prison, where he began to suffer from epilepsy, and he returned to St. Petersburg only a full ten years after he had left in chains.\n His prison experiences coupled with his conversion to a profoundly religious philosophy formed the basis for his great novels. But it was his fortuitous marriage to Anna Snitkina, following a period of utter destitution brought about by his compulsive gambling, that gave Dostoevsky the emotional stability to complete Crime and Punishment (1866), The Idiot (1868-69), The Possessed (1871-72),and The Brothers Karamazov (1879-80). When Dostoevsky died in 1881, he left a legacy of masterworks that influenced the great thinkers and writers of the Western world and immortalized him as a giant among writers of world literature.".to_string()))
             .cover_url(None)
-            .pages(592)
+            .pages(Some(592))
             .build()
             .unwrap();
 
@@ -297,6 +752,44 @@ mod tests {
         assert_eq!(1, deleted_rows);
     }
 
+    #[tokio::test]
+    async fn page_set_directly_is_logged_as_progress() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut book = factories::fake_book();
+        book.pages = Some(200);
+        book.create(&pool).await.unwrap();
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut user_book = UserBookBuilder::default()
+            .user_id(user.id)
+            .book_id(book.id)
+            .current_page(Some(50))
+            .build()
+            .unwrap();
+
+        // Act
+        user_book.create(&pool).await.unwrap();
+        user_book.current_page = Some(100);
+        user_book.update(&pool).await.unwrap();
+        let history = user_book.progress_history(&pool).await.unwrap();
+        let saved = user_book.fetch(&pool).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![25.0, 50.0],
+            history
+                .iter()
+                .map(|entry| entry.progress_percent)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(50.0), saved.progress_percent);
+    }
+
     #[tokio::test]
     async fn get_book_gets_correct_book() {
         // Arrange
@@ -342,4 +835,158 @@ mod tests {
         // Assert
         assert_eq!(user_book_received_user, user);
     }
+
+    #[tokio::test]
+    async fn switch_edition_scales_progress_and_moves_highlights() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut hardcover = factories::fake_book();
+        hardcover.pages = Some(400);
+        hardcover.create(&pool).await.unwrap();
+        let mut paperback = factories::fake_book();
+        paperback.pages = Some(200);
+        paperback.create(&pool).await.unwrap();
+        let mut other = factories::fake_book();
+        other.create(&pool).await.unwrap();
+        let work = Work::for_book(&pool, &mut hardcover).await.unwrap();
+        work.add_edition(&pool, &mut paperback).await.unwrap();
+
+        let mut user = factories::fake_user();
+        user.create(&pool).await.unwrap();
+        let mut user_book = factories::fake_user_book(user.id, hardcover.id);
+        user_book.current_page = Some(100);
+        user_book.create(&pool).await.unwrap();
+        let mut highlight = HighlightBuilder::default()
+            .user_id(user.id)
+            .book_id(hardcover.id)
+            .quote(Some("A quote".to_string()))
+            .build()
+            .unwrap();
+        highlight.create(&pool).await.unwrap();
+
+        // Act
+        let different_work = user_book.switch_edition(&pool, other.id).await;
+        user_book.switch_edition(&pool, paperback.id).await.unwrap();
+        let fetched = UserBook::get(&pool, paperback.id, user.id).await.unwrap();
+        let old_entry = UserBook::get(&pool, hardcover.id, user.id).await;
+        let moved = Highlight::get(&pool, highlight.id, user.id).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        for book in [&hardcover, &paperback, &other] {
+            book.delete(&pool).await.unwrap();
+        }
+        work.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(matches!(
+            different_work,
+            Err(EditionSwitchError::DifferentWork)
+        ));
+        assert_eq!(paperback.id, user_book.book_id);
+        assert_eq!(Some(50), fetched.current_page);
+        assert_eq!(Some(25.0), fetched.progress_percent);
+        assert_eq!(user_book.status, fetched.status);
+        assert_eq!(user_book.rating, fetched.rating);
+        assert!(matches!(old_entry, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(paperback.id, moved.book_id);
+    }
+
+    #[test]
+    fn set_progress_normalises_to_percent() {
+        // Arrange
+        let mut paperback = factories::fake_book();
+        paperback.pages = Some(400);
+        let mut audiobook = factories::fake_book();
+        audiobook.format = BookFormat::Audiobook;
+        audiobook.pages = None;
+        audiobook.duration_minutes = Some(600);
+        let mut unknown = factories::fake_book();
+        unknown.pages = None;
+        let mut user_book = factories::fake_user_book(1, paperback.id);
+        user_book.status = ReadingStatus::ToRead;
+        user_book.began_reading = None;
+
+        // Act
+        user_book.set_progress(Progress::Page(100), &paperback);
+        let by_page = (
+            user_book.progress_percent,
+            user_book.current_page,
+            user_book.status,
+        );
+        user_book.set_progress(Progress::Percent(50.0), &paperback);
+        let by_percent = (user_book.progress_percent, user_book.current_page);
+        user_book.set_progress(Progress::Timestamp(9_000), &audiobook);
+        let by_timestamp = (
+            user_book.progress_percent,
+            user_book.current_seconds,
+            user_book.current_page,
+        );
+        user_book.set_progress(Progress::Percent(50.0), &audiobook);
+        let audiobook_percent = user_book.current_seconds;
+        user_book.set_progress(Progress::Page(30), &unknown);
+        let without_length = (user_book.progress_percent, user_book.current_page);
+        user_book.set_progress(Progress::Timestamp(36_000), &audiobook);
+
+        // Assert
+        assert_eq!((Some(25.0), Some(100), ReadingStatus::Reading), by_page);
+        assert_eq!((Some(50.0), Some(200)), by_percent);
+        assert_eq!((Some(25.0), Some(9_000), None), by_timestamp);
+        assert_eq!(Some(18_000), audiobook_percent);
+        assert_eq!((None, Some(30)), without_length);
+        assert_eq!(Some(100.0), user_book.progress_percent);
+        assert_eq!(ReadingStatus::Completed, user_book.status);
+        assert!(user_book.done_reading.is_some());
+    }
+
+    #[tokio::test]
+    async fn get_visible_respects_entry_and_profile_visibility() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut owner = factories::fake_user();
+        owner.create(&pool).await.unwrap();
+        let mut follower = factories::fake_user();
+        follower.create(&pool).await.unwrap();
+        let mut stranger = factories::fake_user();
+        stranger.create(&pool).await.unwrap();
+        Follow::request(&pool, follower.id, owner.id).await.unwrap();
+        let mut book = factories::fake_book();
+        book.create(&pool).await.unwrap();
+        let mut user_book = factories::fake_user_book(owner.id, book.id);
+        user_book.visibility = Visibility::Followers;
+        user_book.create(&pool).await.unwrap();
+        let visible = async |viewer: &User| {
+            UserBook::get_visible(&pool, book.id, owner.id, viewer.id)
+                .await
+                .unwrap()
+                .is_some()
+        };
+
+        // Act
+        let followers_only = [
+            visible(&owner).await,
+            visible(&follower).await,
+            visible(&stranger).await,
+        ];
+        user_book.visibility = Visibility::Public;
+        user_book.update(&pool).await.unwrap();
+        owner.visibility = Visibility::Private;
+        owner.update(&pool).await.unwrap();
+        let private_profile = [
+            visible(&owner).await,
+            visible(&follower).await,
+            visible(&stranger).await,
+        ];
+        let fetched = UserBook::get(&pool, book.id, owner.id).await.unwrap();
+
+        owner.delete(&pool).await.unwrap();
+        follower.delete(&pool).await.unwrap();
+        stranger.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!([true, true, false], followers_only);
+        assert_eq!([true, false, false], private_profile);
+        assert_eq!(Visibility::Public, fetched.visibility);
+    }
 }
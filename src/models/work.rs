@@ -0,0 +1,284 @@
+// A work groups the editions of the same text, e.g. the hardcover and the
+// paperback of a novel. Shelf entries stay on editions, stats are per work.
+
+use crate::models::book::{Book, BookFormat};
+use derive_builder::Builder;
+use sqlx::PgPool;
+
+#[derive(Debug, Builder, PartialEq)]
+pub struct Work {
+    #[builder(default = 0)]
+    pub id: i64,
+    pub title: String,
+    pub author: String,
+}
+
+/// Reading stats over all editions of a work, from the shelf entries a viewer
+/// can see. A user with several editions on their shelf counts once, with the
+/// average of their ratings.
+#[derive(Debug, Default, PartialEq)]
+pub struct WorkStats {
+    pub readers: i64,
+    pub completed: i64,
+    pub ratings: i64,
+    pub average_rating: Option<f64>,
+}
+
+impl Work {
+    /// Insert work into the DB
+    pub async fn create(&mut self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let res = sqlx::query!(
+            "INSERT INTO works (title, author) VALUES ($1, $2) RETURNING id",
+            self.title,
+            self.author
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.id = res.id;
+
+        Ok(())
+    }
+
+    /// Creates a work with the title and author of `book`, with the book as
+    /// its first edition.
+    pub async fn for_book(pool: &PgPool, book: &mut Book) -> Result<Self, sqlx::Error> {
+        let mut work = Work {
+            id: 0,
+            title: book.title.clone(),
+            author: book.author.clone(),
+        };
+        work.create(pool).await?;
+        work.add_edition(pool, book).await?;
+
+        Ok(work)
+    }
+
+    /// Update work in the DB. Returns the number of updated rows on Ok.
+    pub async fn update(&mut self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let updated = sqlx::query!(
+            "UPDATE works SET title = $1, author = $2 WHERE id = $3",
+            self.title,
+            self.author,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected())
+    }
+
+    /// Fetch a work by its ID.
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            Work,
+            "SELECT id, title, author FROM works WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Makes `book` an edition of this work, moving it from any other work.
+    pub async fn add_edition(&self, pool: &PgPool, book: &mut Book) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE books SET work_id = $1 WHERE id = $2",
+            self.id,
+            book.id
+        )
+        .execute(pool)
+        .await?;
+
+        book.work_id = Some(self.id);
+
+        Ok(())
+    }
+
+    /// The editions of this work, oldest first.
+    pub async fn editions(&self, pool: &PgPool) -> Result<Vec<Book>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Book,
+            r#"SELECT id, title, author, isbn, published_year, description, cover_url, pages, work_id, format as "format: BookFormat", duration_minutes, word_count FROM books WHERE work_id = $1 ORDER BY published_year, id"#,
+            self.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Stats over all editions of this work, as `viewer_id` sees them.
+    pub async fn stats(&self, pool: &PgPool, viewer_id: i64) -> Result<WorkStats, sqlx::Error> {
+        let record = sqlx::query_as!(
+            WorkStats,
+            r#"
+            WITH per_user AS (
+                SELECT ub.user_id, AVG(ub.rating)::float8 AS rating, bool_or(ub.status = 'completed') AS completed
+                FROM user_books ub
+                JOIN books b ON b.id = ub.book_id
+                JOIN users u ON u.id = ub.user_id
+                WHERE b.work_id = $1 AND visible_to(ub.user_id, $2, LEAST(u.visibility, ub.visibility))
+                GROUP BY ub.user_id
+            )
+            SELECT COUNT(*) AS "readers!",
+                   COUNT(*) FILTER (WHERE completed) AS "completed!",
+                   COUNT(rating) AS "ratings!",
+                   AVG(rating) AS average_rating
+            FROM per_user
+            "#,
+            self.id,
+            viewer_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Deletes the row associated with the record. Its editions are kept as
+    /// books of their own.
+    pub async fn delete(&self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM works WHERE id = $1", self.id)
+            .execute(pool)
+            .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+impl WorkStats {
+    /// Stats for a book, over all editions of its work if it has one, as
+    /// `viewer_id` sees them.
+    pub async fn for_book(
+        pool: &PgPool,
+        book_id: i64,
+        viewer_id: i64,
+    ) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query_as!(
+            WorkStats,
+            r#"
+            WITH per_user AS (
+                SELECT ub.user_id, AVG(ub.rating)::float8 AS rating, bool_or(ub.status = 'completed') AS completed
+                FROM user_books ub
+                JOIN books b ON b.id = ub.book_id
+                JOIN users u ON u.id = ub.user_id
+                WHERE (b.id = $1 OR b.work_id = (SELECT work_id FROM books WHERE id = $1))
+                  AND visible_to(ub.user_id, $2, LEAST(u.visibility, ub.visibility))
+                GROUP BY ub.user_id
+            )
+            SELECT COUNT(*) AS "readers!",
+                   COUNT(*) FILTER (WHERE completed) AS "completed!",
+                   COUNT(rating) AS "ratings!",
+                   AVG(rating) AS average_rating
+            FROM per_user
+            "#,
+            book_id,
+            viewer_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user, fake_user_book};
+    use crate::models::user::Visibility;
+    use crate::models::user_book::ReadingStatus;
+    use crate::test_utils::setup_db;
+
+    #[tokio::test]
+    async fn stats_aggregate_over_editions() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut hardcover = fake_book();
+        hardcover.create(&pool).await.unwrap();
+        let mut paperback = fake_book();
+        paperback.create(&pool).await.unwrap();
+        let mut other = fake_book();
+        other.create(&pool).await.unwrap();
+
+        let mut anna = fake_user();
+        anna.create(&pool).await.unwrap();
+        let mut ben = fake_user();
+        ben.create(&pool).await.unwrap();
+        let mut cleo = fake_user();
+        cleo.create(&pool).await.unwrap();
+        let mut private = fake_user_book(cleo.id, hardcover.id);
+        private.rating = Some(1);
+        private.visibility = Visibility::Private;
+        private.create(&pool).await.unwrap();
+
+        let mut entries = [
+            fake_user_book(anna.id, hardcover.id),
+            fake_user_book(anna.id, paperback.id),
+            fake_user_book(ben.id, paperback.id),
+            fake_user_book(ben.id, other.id),
+        ];
+        for (entry, (rating, status)) in entries.iter_mut().zip([
+            (Some(8), ReadingStatus::Completed),
+            (Some(6), ReadingStatus::Reading),
+            (None, ReadingStatus::Reading),
+            (Some(1), ReadingStatus::Completed),
+        ]) {
+            entry.rating = rating;
+            entry.status = status;
+            entry.create(&pool).await.unwrap();
+        }
+
+        // Act
+        let work = Work::for_book(&pool, &mut hardcover).await.unwrap();
+        work.add_edition(&pool, &mut paperback).await.unwrap();
+        let editions = work.editions(&pool).await.unwrap();
+        let stats = work.stats(&pool, anna.id).await.unwrap();
+        let paperback_stats = WorkStats::for_book(&pool, paperback.id, anna.id)
+            .await
+            .unwrap();
+        let other_stats = WorkStats::for_book(&pool, other.id, anna.id).await.unwrap();
+        let cleos_stats = work.stats(&pool, cleo.id).await.unwrap();
+        let deleted_rows = work.delete(&pool).await.unwrap();
+        let orphan = Book::get(&pool, hardcover.id).await.unwrap();
+
+        // Cleanup
+        anna.delete(&pool).await.unwrap();
+        ben.delete(&pool).await.unwrap();
+        cleo.delete(&pool).await.unwrap();
+        for book in [&hardcover, &paperback, &other] {
+            book.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert_eq!(Some(work.id), hardcover.work_id);
+        assert_eq!(hardcover.title, work.title);
+        assert_eq!(2, editions.len());
+        assert!(editions.iter().all(|e| e.work_id == Some(work.id)));
+        assert_eq!(
+            WorkStats {
+                readers: 2,
+                completed: 1,
+                ratings: 1,
+                average_rating: Some(7.0),
+            },
+            stats
+        );
+        assert_eq!(stats, paperback_stats);
+        assert_eq!(3, cleos_stats.readers);
+        assert_eq!(2, cleos_stats.ratings);
+        assert_eq!(
+            WorkStats {
+                readers: 1,
+                completed: 1,
+                ratings: 1,
+                average_rating: Some(1.0),
+            },
+            other_stats
+        );
+        assert_eq!(1, deleted_rows);
+        assert_eq!(None, orphan.work_id);
+    }
+}
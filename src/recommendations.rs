@@ -0,0 +1,452 @@
+//! Recommends books to users from their ratings, by item-item collaborative
+//! filtering.
+//!
+//! Two books are similar when the users who rated both rated them alike,
+//! measured by adjusted cosine similarity: ratings are taken relative to each
+//! user's average, so a harsh and a generous rater agree when both liked a
+//! book more than they usually do. A user's rating of a book they haven't
+//! shelved is then predicted from their ratings of the books similar to it.
+//!
+//! Both are computed in batches by `recompute`, which the `recommendations`
+//! binary runs periodically, and read with `Recommendation::for_user`.
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Recommendations stored per user by default.
+pub const DEFAULT_COUNT: usize = 20;
+/// Most similar books kept per book.
+pub const NEIGHBOURS: usize = 50;
+/// Fewest users having rated both books for the books to be compared.
+pub const MIN_CO_RATINGS: i32 = 2;
+
+/// A book on a user's shelf, with the user's rating if they gave one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShelfRating {
+    pub user_id: i64,
+    pub book_id: i64,
+    pub rating: Option<i16>,
+}
+
+/// How alike `book_id` and `similar_book_id` are rated, from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Similarity {
+    pub book_id: i64,
+    pub similar_book_id: i64,
+    pub similarity: f64,
+    /// Number of users who rated both books.
+    pub co_ratings: i32,
+}
+
+/// A book worth suggesting to a user, not yet in the DB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub book_id: i64,
+    /// The rating the user is predicted to give the book.
+    pub score: f64,
+    /// The rated book that spoke most for this one.
+    pub because_book_id: i64,
+}
+
+/// A suggestion stored by `recompute`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recommendation {
+    pub user_id: i64,
+    pub book_id: i64,
+    pub score: f64,
+    pub because_book_id: i64,
+    pub because_title: String,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// Number of rows stored by `recompute`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Recomputed {
+    pub similarities: usize,
+    pub recommendations: usize,
+}
+
+/// Running sums of two books' ratings, relative to each rater's average.
+#[derive(Default)]
+struct CoRatings {
+    product: f64,
+    squares_a: f64,
+    squares_b: f64,
+    count: i32,
+}
+
+impl Recommendation {
+    /// Why the book is recommended, e.g. "Because you rated Dune highly".
+    pub fn explanation(&self) -> String {
+        format!("Because you rated {} highly", self.because_title)
+    }
+
+    /// The user's recommendations from the last `recompute`, best first.
+    /// Books they shelved since are left out.
+    pub async fn for_user(pool: &PgPool, user_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            Recommendation,
+            r#"
+            SELECT r.user_id, r.book_id, r.score, r.because_book_id, b.title AS because_title, r.computed_at
+            FROM recommendations r
+            JOIN books b ON b.id = r.because_book_id
+            WHERE r.user_id = $1 AND NOT EXISTS (
+                SELECT 1 FROM user_books ub WHERE ub.user_id = r.user_id AND ub.book_id = r.book_id
+            )
+            ORDER BY r.score DESC, r.book_id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+}
+
+/// The similarities between books rated alike by at least `MIN_CO_RATINGS`
+/// users, the `NEIGHBOURS` most similar of each book. Every pair is returned
+/// both ways round, ordered by book and then most similar first.
+pub fn similarities(entries: &[ShelfRating]) -> Vec<Similarity> {
+    let mut by_user: HashMap<i64, Vec<(i64, f64)>> = HashMap::new();
+    for entry in entries {
+        if let Some(rating) = entry.rating {
+            by_user
+                .entry(entry.user_id)
+                .or_default()
+                .push((entry.book_id, rating as f64));
+        }
+    }
+
+    let mut pairs: HashMap<(i64, i64), CoRatings> = HashMap::new();
+    for ratings in by_user.values_mut() {
+        let mean = ratings.iter().map(|(_, rating)| rating).sum::<f64>() / ratings.len() as f64;
+        ratings.sort_by_key(|&(book_id, _)| book_id);
+        for (n, &(a, rating_a)) in ratings.iter().enumerate() {
+            for &(b, rating_b) in &ratings[n + 1..] {
+                let (a_dev, b_dev) = (rating_a - mean, rating_b - mean);
+                let sums = pairs.entry((a, b)).or_default();
+                sums.product += a_dev * b_dev;
+                sums.squares_a += a_dev * a_dev;
+                sums.squares_b += b_dev * b_dev;
+                sums.count += 1;
+            }
+        }
+    }
+
+    let mut neighbours: BTreeMap<i64, Vec<Similarity>> = BTreeMap::new();
+    for ((a, b), sums) in pairs {
+        if sums.count < MIN_CO_RATINGS || sums.squares_a == 0.0 || sums.squares_b == 0.0 {
+            continue;
+        }
+        let similarity = sums.product / (sums.squares_a * sums.squares_b).sqrt();
+        // Books rated oppositely say nothing about what to read next
+        if similarity <= 0.0 {
+            continue;
+        }
+        for (book_id, similar_book_id) in [(a, b), (b, a)] {
+            neighbours.entry(book_id).or_default().push(Similarity {
+                book_id,
+                similar_book_id,
+                similarity,
+                co_ratings: sums.count,
+            });
+        }
+    }
+
+    neighbours
+        .into_values()
+        .flat_map(|mut similar| {
+            similar.sort_by(|x, y| {
+                y.similarity
+                    .total_cmp(&x.similarity)
+                    .then(x.similar_book_id.cmp(&y.similar_book_id))
+            });
+            similar.truncate(NEIGHBOURS);
+            similar
+        })
+        .collect()
+}
+
+/// Up to `count` books to suggest to the owner of `shelf`, best first.
+///
+/// A book is suggested when the user rated the books similar to it above
+/// their average, and never when it is on their shelf already.
+/// `neighbours` holds the similarities of each book, by `book_id`.
+pub fn suggest(
+    shelf: &[ShelfRating],
+    neighbours: &HashMap<i64, Vec<Similarity>>,
+    count: usize,
+) -> Vec<Suggestion> {
+    let rated: Vec<(i64, f64)> = shelf
+        .iter()
+        .filter_map(|entry| entry.rating.map(|rating| (entry.book_id, rating as f64)))
+        .collect();
+    if rated.is_empty() {
+        return Vec::new();
+    }
+    let mean = rated.iter().map(|(_, rating)| rating).sum::<f64>() / rated.len() as f64;
+    let on_shelf: HashSet<i64> = shelf.iter().map(|entry| entry.book_id).collect();
+
+    // Per candidate: the weighted sum of deviations, the sum of weights and
+    // the largest contribution with the book it came from
+    let mut predictions: HashMap<i64, (f64, f64, f64, i64)> = HashMap::new();
+    for &(book_id, rating) in &rated {
+        for similar in neighbours.get(&book_id).into_iter().flatten() {
+            if on_shelf.contains(&similar.similar_book_id) {
+                continue;
+            }
+            let contribution = similar.similarity * (rating - mean);
+            let (sum, weights, best, because) = predictions
+                .entry(similar.similar_book_id)
+                .or_insert((0.0, 0.0, f64::NEG_INFINITY, book_id));
+            *sum += contribution;
+            *weights += similar.similarity;
+            if contribution > *best {
+                (*best, *because) = (contribution, book_id);
+            }
+        }
+    }
+
+    let mut suggestions: Vec<Suggestion> = predictions
+        .into_iter()
+        .filter(|(_, (sum, _, best, _))| *sum > 0.0 && *best > 0.0)
+        .map(|(book_id, (sum, weights, _, because_book_id))| Suggestion {
+            book_id,
+            score: mean + sum / weights,
+            because_book_id,
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.book_id.cmp(&b.book_id)));
+    suggestions.truncate(count);
+
+    suggestions
+}
+
+/// Recomputes the similarities between books from every rating, and up to
+/// `count` recommendations for every user, replacing the stored ones.
+pub async fn recompute(pool: &PgPool, count: usize) -> Result<Recomputed, sqlx::Error> {
+    let entries = sqlx::query_as!(
+        ShelfRating,
+        "SELECT user_id, book_id, rating FROM user_books"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let similarities = similarities(&entries);
+    let mut neighbours: HashMap<i64, Vec<Similarity>> = HashMap::new();
+    for similarity in &similarities {
+        neighbours
+            .entry(similarity.book_id)
+            .or_default()
+            .push(similarity.clone());
+    }
+    let mut shelves: HashMap<i64, Vec<ShelfRating>> = HashMap::new();
+    for entry in entries {
+        shelves.entry(entry.user_id).or_default().push(entry);
+    }
+    let (mut user_ids, mut book_ids, mut scores, mut because_ids) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for (user_id, shelf) in &shelves {
+        for suggestion in suggest(shelf, &neighbours, count) {
+            user_ids.push(*user_id);
+            book_ids.push(suggestion.book_id);
+            scores.push(suggestion.score);
+            because_ids.push(suggestion.because_book_id);
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM book_similarities")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO book_similarities (book_id, similar_book_id, similarity, co_ratings)
+        SELECT * FROM UNNEST($1::int8[], $2::int8[], $3::float8[], $4::int4[])
+        "#,
+        &similarities.iter().map(|s| s.book_id).collect::<Vec<_>>(),
+        &similarities
+            .iter()
+            .map(|s| s.similar_book_id)
+            .collect::<Vec<_>>(),
+        &similarities
+            .iter()
+            .map(|s| s.similarity)
+            .collect::<Vec<_>>(),
+        &similarities
+            .iter()
+            .map(|s| s.co_ratings)
+            .collect::<Vec<_>>()
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM recommendations")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO recommendations (user_id, book_id, score, because_book_id)
+        SELECT * FROM UNNEST($1::int8[], $2::int8[], $3::float8[], $4::int8[])
+        "#,
+        &user_ids,
+        &book_ids,
+        &scores,
+        &because_ids
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Recomputed {
+        similarities: similarities.len(),
+        recommendations: user_ids.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user_book};
+    use crate::test_utils::{create_fake_users, setup_db};
+
+    const A: i64 = 1;
+    const B: i64 = 2;
+    const C: i64 = 3;
+    const D: i64 = 4;
+
+    fn rated(user_id: i64, ratings: &[(i64, i16)]) -> Vec<ShelfRating> {
+        ratings
+            .iter()
+            .map(|&(book_id, rating)| ShelfRating {
+                user_id,
+                book_id,
+                rating: Some(rating),
+            })
+            .collect()
+    }
+
+    fn ratings() -> Vec<ShelfRating> {
+        [
+            rated(1, &[(A, 9), (B, 8), (C, 2)]),
+            rated(2, &[(A, 8), (B, 9), (C, 3)]),
+            rated(3, &[(A, 10), (D, 8)]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn similar_when_rated_alike() {
+        // Act
+        let similarities = similarities(&ratings());
+
+        // Assert
+        // A and B are both liked by the two users who rated them, C is
+        // disliked and D only has one rating
+        let pairs: Vec<_> = similarities
+            .iter()
+            .map(|s| (s.book_id, s.similar_book_id, s.co_ratings))
+            .collect();
+        assert_eq!(vec![(A, B, 2), (B, A, 2)], pairs);
+        assert!((similarities[0].similarity - 0.884).abs() < 0.001);
+        assert_eq!(similarities[0].similarity, similarities[1].similarity);
+    }
+
+    #[test]
+    fn suggests_unshelved_books_like_the_highly_rated() {
+        // Arrange
+        let mut neighbours: HashMap<i64, Vec<Similarity>> = HashMap::new();
+        for similarity in similarities(&ratings()) {
+            neighbours
+                .entry(similarity.book_id)
+                .or_default()
+                .push(similarity);
+        }
+        let shelf = rated(4, &[(A, 9), (C, 3)]);
+        let mut shelved = shelf.clone();
+        shelved.push(ShelfRating {
+            user_id: 4,
+            book_id: B,
+            rating: None,
+        });
+
+        // Act
+        let suggestions = suggest(&shelf, &neighbours, DEFAULT_COUNT);
+        let disliked_a = suggest(&rated(4, &[(A, 2), (C, 8)]), &neighbours, DEFAULT_COUNT);
+        let already_shelved = suggest(&shelved, &neighbours, DEFAULT_COUNT);
+
+        // Assert
+        assert_eq!(
+            vec![Suggestion {
+                book_id: B,
+                score: 9.0,
+                because_book_id: A,
+            }],
+            suggestions
+        );
+        assert!(disliked_a.is_empty());
+        assert!(already_shelved.is_empty());
+    }
+
+    #[tokio::test]
+    async fn recompute_stores_recommendations() {
+        // Arrange
+        let pool = setup_db().await;
+        let users = create_fake_users(&pool, 3).await;
+        let mut books = Vec::new();
+        for _ in 0..3 {
+            let mut book = fake_book();
+            book.create(&pool).await.unwrap();
+            books.push(book);
+        }
+        // Two readers love the first two books and dislike the third, the
+        // last user loved the first and is yet to find the second
+        let ratings = [
+            (0, 0, 9),
+            (0, 1, 8),
+            (0, 2, 2),
+            (1, 0, 8),
+            (1, 1, 9),
+            (1, 2, 3),
+            (2, 0, 9),
+            (2, 2, 3),
+        ];
+        for (user, book, rating) in ratings {
+            let mut entry = fake_user_book(users[user].id, books[book].id);
+            entry.rating = Some(rating);
+            entry.create(&pool).await.unwrap();
+        }
+
+        // Act
+        let recomputed = recompute(&pool, DEFAULT_COUNT).await.unwrap();
+        let recommendations = Recommendation::for_user(&pool, users[2].id).await.unwrap();
+        fake_user_book(users[2].id, books[1].id)
+            .create(&pool)
+            .await
+            .unwrap();
+        let after_shelving = Recommendation::for_user(&pool, users[2].id).await.unwrap();
+
+        // Cleanup
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        for book in &books {
+            book.delete(&pool).await.unwrap();
+        }
+
+        // Assert
+        assert!(recomputed.recommendations >= 1);
+        assert_eq!(
+            vec![books[1].id],
+            recommendations
+                .iter()
+                .map(|r| r.book_id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            format!("Because you rated {} highly", books[0].title),
+            recommendations[0].explanation()
+        );
+        assert!(after_shelving.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! Daily reminders to read, for users who asked for them.
+//!
+//! `schedule` stores a reminder for every user who set a `reminder_hour`,
+//! once that hour has come in their time zone, unless they've already logged
+//! progress or frozen their streak today, or been reminded today. `deliver`
+//! then hands the stored notifications to a `Notifier` to send, such as
+//! `StdoutNotifier`. The `reminders` binary runs both periodically, and
+//! notifications that couldn't be sent are tried again on the next run. A
+//! reminder expires at the end of the user's day, so it isn't sent late
+//! after an outage.
+use crate::models::notification::{Notification, NotificationKind};
+use crate::models::user::User;
+use crate::streaks::Streak;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::io;
+
+pub mod stdout;
+
+pub use stdout::StdoutNotifier;
+
+/// Sends notifications to users, e.g. by email or push message.
+pub trait Notifier: Send + Sync {
+    fn notify(
+        &self,
+        user: &User,
+        notification: &Notification,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// Notifications handed to a notifier by `deliver`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Delivered {
+    pub delivered: usize,
+    /// Left undelivered, to be tried again.
+    pub failed: usize,
+}
+
+/// The reminder for a user with `streak` who hasn't read today.
+pub fn reminder(streak: &Streak) -> String {
+    match streak.current {
+        0 => "You haven't read yet today. A few pages start a new streak!".to_string(),
+        days => format!("Read today to keep your {days}-day streak going!"),
+    }
+}
+
+/// Stores the reminders due at `now`, returning them.
+pub async fn schedule(pool: &PgPool, now: DateTime<Utc>) -> Result<Vec<Notification>, sqlx::Error> {
+    let users = sqlx::query!(
+        r#"
+        SELECT u.id, ((l.local_now::DATE + 1)::TIMESTAMP AT TIME ZONE u.timezone) AS "end_of_day!"
+        FROM users u
+        CROSS JOIN LATERAL (SELECT $1::timestamptz AT TIME ZONE u.timezone AS local_now) l
+        WHERE EXTRACT(HOUR FROM l.local_now) >= u.reminder_hour
+          AND NOT EXISTS (
+              SELECT 1 FROM reading_progress rp
+              WHERE rp.user_id = u.id AND (rp.recorded_at AT TIME ZONE u.timezone)::DATE = l.local_now::DATE
+          )
+          AND NOT EXISTS (
+              SELECT 1 FROM streak_freezes f
+              WHERE f.user_id = u.id AND f.day = l.local_now::DATE
+          )
+          AND NOT EXISTS (
+              SELECT 1 FROM notifications n
+              WHERE n.user_id = u.id AND n.kind = 'reading-reminder'
+                AND (n.created_at AT TIME ZONE u.timezone)::DATE = l.local_now::DATE
+          )
+        ORDER BY u.id
+        "#,
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut reminders = Vec::with_capacity(users.len());
+    for user in users {
+        let streak = Streak::for_user(pool, user.id, now).await?;
+        reminders.push(
+            Notification::create(
+                pool,
+                user.id,
+                NotificationKind::ReadingReminder,
+                &reminder(&streak),
+                now,
+                Some(user.end_of_day),
+            )
+            .await?,
+        );
+    }
+
+    Ok(reminders)
+}
+
+/// Sends the notifications undelivered and unexpired at `now` with
+/// `notifier`, marking the ones it sent delivered.
+pub async fn deliver(
+    pool: &PgPool,
+    notifier: &impl Notifier,
+    now: DateTime<Utc>,
+) -> Result<Delivered, sqlx::Error> {
+    let mut delivered = Delivered::default();
+    for mut notification in Notification::undelivered(pool, now).await? {
+        let user = User::get(pool, notification.user_id).await?;
+        if notifier.notify(&user, &notification).await.is_ok() {
+            notification.mark_delivered(pool).await?;
+            delivered.delivered += 1;
+        } else {
+            delivered.failed += 1;
+        }
+    }
+
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::fake_user;
+    use crate::test_utils::setup_db;
+    use chrono::{NaiveDate, TimeZone};
+    use std::sync::Mutex;
+
+    /// Keeps what it's asked to send, or fails every time.
+    struct TestNotifier {
+        sent: Mutex<Vec<(i64, String)>>,
+        fail: bool,
+    }
+
+    impl Notifier for TestNotifier {
+        async fn notify(&self, user: &User, notification: &Notification) -> io::Result<()> {
+            if self.fail {
+                return Err(io::Error::other("unreachable"));
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push((user.id, notification.body.clone()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reminders_mention_the_streak_at_stake() {
+        let mut streak = Streak::default();
+        assert_eq!(
+            "You haven't read yet today. A few pages start a new streak!",
+            reminder(&streak)
+        );
+        streak.current = 6;
+        assert_eq!(
+            "Read today to keep your 6-day streak going!",
+            reminder(&streak)
+        );
+    }
+
+    #[tokio::test]
+    async fn reminders_are_scheduled_once_a_day_and_delivered() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut users = Vec::new();
+        for timezone in ["Europe/Copenhagen", "America/New_York", "Europe/Copenhagen"] {
+            let mut user = fake_user();
+            user.timezone = timezone.to_string();
+            user.reminder_hour = Some(19);
+            user.create(&pool).await.unwrap();
+            users.push(user);
+        }
+        let mut no_reminders = fake_user();
+        no_reminders.create(&pool).await.unwrap();
+        let (copenhagen, new_york, frozen) = (users[0].id, users[1].id, users[2].id);
+        Streak::freeze(
+            &pool,
+            frozen,
+            NaiveDate::from_ymd_opt(2026, 3, 10).unwrap(),
+            Utc.with_ymd_and_hms(2026, 3, 10, 8, 0, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+        let ids = |notifications: Vec<Notification>| {
+            notifications
+                .into_iter()
+                .map(|n| n.user_id)
+                .filter(|id| [copenhagen, new_york, frozen, no_reminders.id].contains(id))
+                .collect::<Vec<_>>()
+        };
+        let failing = TestNotifier {
+            sent: Mutex::new(Vec::new()),
+            fail: true,
+        };
+        let notifier = TestNotifier {
+            sent: Mutex::new(Vec::new()),
+            fail: false,
+        };
+
+        // Act
+        // 18:30 in Copenhagen
+        let early = schedule(&pool, Utc.with_ymd_and_hms(2026, 3, 10, 17, 30, 0).unwrap())
+            .await
+            .unwrap();
+        // 19:30 in Copenhagen, 14:30 in New York
+        let evening = schedule(&pool, Utc.with_ymd_and_hms(2026, 3, 10, 18, 30, 0).unwrap())
+            .await
+            .unwrap();
+        let again = schedule(&pool, Utc.with_ymd_and_hms(2026, 3, 10, 21, 0, 0).unwrap())
+            .await
+            .unwrap();
+        // 19:00 in New York
+        let new_york_evening =
+            schedule(&pool, Utc.with_ymd_and_hms(2026, 3, 10, 23, 0, 0).unwrap())
+                .await
+                .unwrap();
+        // Past midnight in Copenhagen, 19:30 in New York
+        let later = Utc.with_ymd_and_hms(2026, 3, 10, 23, 30, 0).unwrap();
+        let failed = deliver(&pool, &failing, later).await.unwrap();
+        let undelivered = Notification::for_user(&pool, new_york).await.unwrap();
+        let sent = deliver(&pool, &notifier, later).await.unwrap();
+        let delivered = Notification::for_user(&pool, new_york).await.unwrap();
+        let expired = Notification::for_user(&pool, copenhagen).await.unwrap();
+
+        // Cleanup
+        for user in &users {
+            user.delete(&pool).await.unwrap();
+        }
+        no_reminders.delete(&pool).await.unwrap();
+
+        // Assert
+        assert!(ids(early).is_empty());
+        assert_eq!(vec![copenhagen], ids(evening));
+        assert!(ids(again).is_empty());
+        assert_eq!(vec![new_york], ids(new_york_evening));
+        assert!(failed.failed >= 1);
+        assert_eq!(None, undelivered[0].delivered_at);
+        assert!(sent.delivered >= 1);
+        assert!(delivered[0].delivered_at.is_some());
+        assert_eq!(None, expired[0].delivered_at);
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 3, 10, 23, 0, 0).unwrap()),
+            expired[0].expires_at
+        );
+        let sent: Vec<_> = notifier
+            .sent
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter(|(id, _)| [copenhagen, new_york].contains(id))
+            .collect();
+        assert_eq!(
+            vec![(
+                new_york,
+                "You haven't read yet today. A few pages start a new streak!".to_string()
+            )],
+            sent
+        );
+    }
+}
@@ -0,0 +1,20 @@
+//! A notifier that prints notifications, for development and for running
+//! the scheduler before a real notifier is set up.
+use crate::models::notification::Notification;
+use crate::models::user::User;
+use crate::reminders::Notifier;
+use std::io::{self, Write};
+
+pub struct StdoutNotifier;
+
+impl Notifier for StdoutNotifier {
+    async fn notify(&self, user: &User, notification: &Notification) -> io::Result<()> {
+        writeln!(
+            io::stdout().lock(),
+            "📚 to {} <{}>: {}",
+            user.name,
+            user.email,
+            notification.body
+        )
+    }
+}
@@ -0,0 +1,38 @@
+//! What the HTTP servers of the crate, `covers` and `kosync`, share.
+use axum::response::Response;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// An error a handler answered with a server error for. It goes along in the
+/// response's extensions, for whoever runs the server to `report`.
+#[derive(Debug, Clone)]
+pub struct ServerError {
+    /// What failed, like `covers: database error`.
+    pub context: &'static str,
+    pub error: Arc<dyn Error + Send + Sync>,
+}
+
+impl ServerError {
+    pub fn new(context: &'static str, error: impl Error + Send + Sync + 'static) -> Self {
+        ServerError {
+            context,
+            error: Arc::new(error),
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+/// Prints the errors requests failed with, as a layer of
+/// `axum::middleware::map_response`.
+pub async fn report(response: Response) -> Response {
+    if let Some(error) = response.extensions().get::<ServerError>() {
+        eprintln!("{error}");
+    }
+    response
+}
@@ -0,0 +1,373 @@
+//! Finds books that read alike, for books too new to have the ratings that
+//! `recommendations` works from.
+//!
+//! Books are TF-IDF vectors of the words in their title, authors and
+//! description. `reindex` stores the vocabulary, with the number of books
+//! using each word, and every book's vector as the ids of its terms and
+//! their weights. `Book::similar` ranks the stored vectors by their cosine
+//! similarity to a book's, which is worked out on the spot so books added
+//! since the last `reindex` can be looked up too.
+use crate::models::book::Book;
+use deunicode::deunicode;
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+
+/// How many times a word of the title counts, against once for the
+/// description.
+pub const TITLE_WEIGHT: f64 = 2.0;
+
+/// Words too common to tell books apart.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "are", "but", "can", "for", "from", "had", "has",
+    "her", "his", "into", "its", "not", "now", "one", "only", "our", "she", "than", "that", "the",
+    "their", "them", "then", "there", "they", "this", "two", "was", "were", "what", "when",
+    "where", "which", "who", "will", "with", "you", "your",
+];
+
+/// A sparse vector of unit length, its terms in ascending order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vector {
+    pub terms: Vec<i32>,
+    pub weights: Vec<f32>,
+}
+
+impl Vector {
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Cosine similarity to `other`, from 0 to 1.
+    pub fn cosine(&self, other: &Vector) -> f64 {
+        let (mut i, mut j, mut dot) = (0, 0, 0.0);
+        while i < self.terms.len() && j < other.terms.len() {
+            match self.terms[i].cmp(&other.terms[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    dot += self.weights[i] as f64 * other.weights[j] as f64;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        dot
+    }
+}
+
+/// The terms known to the index, by term, with their ids and document
+/// frequencies, and the number of books indexed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Vocabulary {
+    pub terms: HashMap<String, (i32, i32)>,
+    pub documents: i32,
+}
+
+impl Vocabulary {
+    /// The vocabulary of `documents`, the term frequencies of each book.
+    /// Ids follow the terms' alphabetical order, and books without terms
+    /// aren't counted, as they get no vector.
+    pub fn build(documents: &[HashMap<String, f64>]) -> Self {
+        let mut frequencies: HashMap<&str, i32> = HashMap::new();
+        for document in documents {
+            for term in document.keys() {
+                *frequencies.entry(term).or_default() += 1;
+            }
+        }
+        let sorted: BTreeSet<&str> = frequencies.keys().copied().collect();
+        let terms = sorted
+            .into_iter()
+            .enumerate()
+            .map(|(id, term)| (term.to_string(), (id as i32, frequencies[term])))
+            .collect();
+
+        Vocabulary {
+            terms,
+            documents: documents.iter().filter(|d| !d.is_empty()).count() as i32,
+        }
+    }
+
+    /// The stored vocabulary, limited to `terms`.
+    pub async fn load(pool: &PgPool, terms: &[String]) -> Result<Self, sqlx::Error> {
+        let records = sqlx::query!(
+            "SELECT id, term, document_frequency FROM terms WHERE term = ANY($1)",
+            terms
+        )
+        .fetch_all(pool)
+        .await?;
+        let documents = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM book_vectors"#)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(Vocabulary {
+            terms: records
+                .into_iter()
+                .map(|r| (r.term, (r.id, r.document_frequency)))
+                .collect(),
+            documents: documents as i32,
+        })
+    }
+
+    /// The TF-IDF vector of a book with the term `frequencies`, with a
+    /// dampened term frequency and a smoothed IDF. Terms missing from the
+    /// vocabulary are left out.
+    pub fn vector(&self, frequencies: &HashMap<String, f64>) -> Vector {
+        let mut weights: Vec<(i32, f64)> = frequencies
+            .iter()
+            .filter_map(|(term, &frequency)| {
+                let &(id, document_frequency) = self.terms.get(term)?;
+                let idf =
+                    ((1.0 + self.documents as f64) / (1.0 + document_frequency as f64)).ln() + 1.0;
+                Some((id, (1.0 + frequency.ln()) * idf))
+            })
+            .collect();
+        weights.sort_by_key(|&(id, _)| id);
+        let norm = weights.iter().map(|(_, w)| w * w).sum::<f64>().sqrt();
+
+        Vector {
+            terms: weights.iter().map(|&(id, _)| id).collect(),
+            weights: weights.iter().map(|&(_, w)| (w / norm) as f32).collect(),
+        }
+    }
+}
+
+/// How often each term appears in the book's title, counted
+/// `TITLE_WEIGHT` times, and description. The authors' names are terms of
+/// their own, prefixed with "author:", so a book by Green doesn't match one
+/// about the colour.
+pub fn term_frequencies(book: &Book) -> HashMap<String, f64> {
+    let mut frequencies = HashMap::new();
+    for word in words(&book.title) {
+        *frequencies.entry(word).or_default() += TITLE_WEIGHT;
+    }
+    for word in words(book.description.as_deref().unwrap_or_default()) {
+        *frequencies.entry(word).or_default() += 1.0;
+    }
+    for word in words(&book.author) {
+        *frequencies.entry(format!("author:{word}")).or_default() += 1.0;
+    }
+
+    frequencies
+}
+
+fn words(text: &str) -> Vec<String> {
+    deunicode(text)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| {
+            word.len() > 2 && !word.chars().all(|c| c.is_ascii_digit()) && !STOPWORDS.contains(word)
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Rebuilds the vocabulary and the vectors of every book. Returns the number
+/// of books indexed.
+pub async fn reindex(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let books = Book::all(pool).await?;
+    let documents: Vec<_> = books.iter().map(term_frequencies).collect();
+    let vocabulary = Vocabulary::build(&documents);
+
+    let (mut book_ids, mut terms, mut weights) = (Vec::new(), Vec::new(), Vec::new());
+    for (book, document) in books.iter().zip(&documents) {
+        let vector = vocabulary.vector(document);
+        book_ids.extend(std::iter::repeat_n(book.id, vector.terms.len()));
+        terms.extend(vector.terms);
+        weights.extend(vector.weights);
+    }
+    let (mut term_ids, mut names, mut frequencies) = (Vec::new(), Vec::new(), Vec::new());
+    for (term, &(id, document_frequency)) in &vocabulary.terms {
+        term_ids.push(id);
+        names.push(term.clone());
+        frequencies.push(document_frequency);
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM book_vectors")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM terms").execute(&mut *tx).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO terms (id, term, document_frequency)
+        SELECT * FROM UNNEST($1::int4[], $2::text[], $3::int4[])
+        "#,
+        &term_ids,
+        &names,
+        &frequencies
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO book_vectors (book_id, terms, weights)
+        SELECT book_id, array_agg(term ORDER BY term), array_agg(weight ORDER BY term)
+        FROM UNNEST($1::int8[], $2::int4[], $3::float4[]) AS v(book_id, term, weight)
+        GROUP BY book_id
+        "#,
+        &book_ids,
+        &terms,
+        &weights
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(books.len())
+}
+
+/// The ids of the `n` indexed books most similar to `book`, with their
+/// similarity, most similar first. Books with nothing in common are left out.
+pub async fn nearest(pool: &PgPool, book: &Book, n: usize) -> Result<Vec<(i64, f64)>, sqlx::Error> {
+    let frequencies = term_frequencies(book);
+    let terms: Vec<String> = frequencies.keys().cloned().collect();
+    let query = Vocabulary::load(pool, &terms).await?.vector(&frequencies);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let records = sqlx::query!(
+        "SELECT book_id, terms, weights FROM book_vectors WHERE book_id <> $1",
+        book.id
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut nearest: Vec<(i64, f64)> = records
+        .into_iter()
+        .map(|r| {
+            let vector = Vector {
+                terms: r.terms,
+                weights: r.weights,
+            };
+            (r.book_id, query.cosine(&vector))
+        })
+        .filter(|&(_, similarity)| similarity > 0.0)
+        .collect();
+    nearest.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    nearest.truncate(n);
+
+    Ok(nearest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::fake_book;
+    use crate::test_utils::setup_db;
+
+    fn book(title: &str, author: &str, description: &str) -> Book {
+        let mut book = fake_book();
+        book.title = title.to_string();
+        book.author = author.to_string();
+        book.description = Some(description.to_string());
+        book
+    }
+
+    #[test]
+    fn terms_of_title_description_and_authors() {
+        // Arrange
+        let book = book(
+            "The Green Mile",
+            "Stephen King",
+            "Death row in 1932, and the green mile.",
+        );
+
+        // Act
+        let frequencies = term_frequencies(&book);
+
+        // Assert
+        let mut terms: Vec<_> = frequencies.iter().map(|(t, &f)| (t.as_str(), f)).collect();
+        terms.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(
+            vec![
+                ("author:king", 1.0),
+                ("author:stephen", 1.0),
+                ("death", 1.0),
+                ("green", 3.0),
+                ("mile", 3.0),
+                ("row", 1.0),
+            ],
+            terms
+        );
+    }
+
+    #[test]
+    fn shared_rare_words_make_books_similar() {
+        // Arrange
+        let books = [
+            book("Dragon Keep", "Ann Smith", "A dragon guards the keep."),
+            book(
+                "Dragon Flight",
+                "Ann Smith",
+                "The dragon riders take flight.",
+            ),
+            book("Keep Calm", "Bo Lee", "A guide to calm gardening."),
+            book("Tea Gardens", "Cy Park", "Gardening with tea."),
+        ];
+        let documents: Vec<_> = books.iter().map(term_frequencies).collect();
+        let vocabulary = Vocabulary::build(&documents);
+        let vectors: Vec<_> = documents.iter().map(|d| vocabulary.vector(d)).collect();
+
+        // Act
+        let similarity = |a: usize, b: usize| vectors[a].cosine(&vectors[b]);
+
+        // Assert
+        assert!((similarity(0, 0) - 1.0).abs() < 1e-6);
+        assert!(similarity(0, 1) > similarity(0, 2));
+        assert!(similarity(2, 3) > 0.0);
+        assert_eq!(0.0, similarity(0, 3));
+        assert_eq!(similarity(0, 1), similarity(1, 0));
+    }
+
+    #[tokio::test]
+    async fn similar_books_from_the_index() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut books = [
+            book(
+                "Quillon Marsh",
+                "Edda Vantrell",
+                "Smugglers cross the quillon marsh at night.",
+            ),
+            book(
+                "Return to Quillon Marsh",
+                "Edda Vantrell",
+                "The smugglers come back to the marsh.",
+            ),
+            book(
+                "Orchard Ledger",
+                "Pim Osterhout",
+                "Accounts of an orchard kept by smugglers.",
+            ),
+        ];
+        for book in books.iter_mut() {
+            book.create(&pool).await.unwrap();
+        }
+
+        // Act
+        reindex(&pool).await.unwrap();
+        let similar = books[0].similar(&pool, 2).await.unwrap();
+        let mut unindexed = book(
+            "Marsh Lights",
+            "Edda Vantrell",
+            "Lights over the quillon marsh.",
+        );
+        unindexed.create(&pool).await.unwrap();
+        let similar_to_new = unindexed.similar(&pool, 1).await.unwrap();
+
+        // Cleanup
+        for book in &books {
+            book.delete(&pool).await.unwrap();
+        }
+        unindexed.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![books[1].id, books[2].id],
+            similar.iter().map(|b| b.id).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![books[0].id],
+            similar_to_new.iter().map(|b| b.id).collect::<Vec<_>>()
+        );
+    }
+}
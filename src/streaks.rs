@@ -0,0 +1,347 @@
+//! Reading streaks: runs of consecutive days a user logged progress on.
+//!
+//! Days are counted in the user's own time zone, so reading late in the
+//! evening counts for that evening wherever the user is. A streak survives a
+//! day without reading when the user froze it beforehand, up to
+//! `FREEZES_PER_MONTH` days a month. Days already past can't be frozen, so a
+//! broken streak can't be mended afterwards; frozen days keep the streak going
+//! without adding to it. Today never breaks a streak: it is still going
+//! until a whole day passes without reading.
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use sqlx::PgPool;
+use std::collections::BTreeSet;
+
+/// Days a user can freeze in a calendar month.
+pub const FREEZES_PER_MONTH: i64 = 2;
+
+#[derive(Debug)]
+pub enum StreakError {
+    /// The user froze `FREEZES_PER_MONTH` days of the month already.
+    NoFreezesLeft,
+    /// The day is over in the user's time zone.
+    PastDay,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for StreakError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreakError::NoFreezesLeft => write!(f, "no freeze days left this month"),
+            StreakError::PastDay => write!(f, "days already past can't be frozen"),
+            StreakError::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for StreakError {}
+
+impl From<sqlx::Error> for StreakError {
+    fn from(e: sqlx::Error) -> Self {
+        StreakError::Database(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Streak {
+    /// Days read in the streak going on now, 0 if there is none.
+    pub current: i64,
+    pub longest: i64,
+    pub read_today: bool,
+}
+
+/// The streaks in the days `read` on, with the `frozen` days bridging gaps,
+/// as of `today`.
+pub fn streak(
+    read: &BTreeSet<NaiveDate>,
+    frozen: &BTreeSet<NaiveDate>,
+    today: NaiveDate,
+) -> Streak {
+    let bridged = |from: NaiveDate, to: NaiveDate| {
+        from.iter_days()
+            .skip(1)
+            .take_while(|day| *day < to)
+            .all(|day| frozen.contains(&day))
+    };
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut last: Option<NaiveDate> = None;
+    for &day in read.range(..=today) {
+        run = match last {
+            Some(last) if bridged(last, day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        last = Some(day);
+    }
+
+    let current = match last {
+        Some(last) if bridged(last, today) => run,
+        _ => 0,
+    };
+
+    Streak {
+        current,
+        longest,
+        read_today: read.contains(&today),
+    }
+}
+
+impl Streak {
+    /// The streaks of `user_id` at `now`.
+    pub async fn for_user(
+        pool: &PgPool,
+        user_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Self, sqlx::Error> {
+        let record = sqlx::query!(
+            r#"
+            SELECT
+                ($2::timestamptz AT TIME ZONE u.timezone)::DATE AS "today!",
+                COALESCE((
+                    SELECT ARRAY_AGG(DISTINCT (rp.recorded_at AT TIME ZONE u.timezone)::DATE)
+                    FROM reading_progress rp
+                    WHERE rp.user_id = u.id
+                ), '{}') AS "read!",
+                COALESCE((
+                    SELECT ARRAY_AGG(f.day) FROM streak_freezes f WHERE f.user_id = u.id
+                ), '{}') AS "frozen!"
+            FROM users u
+            WHERE u.id = $1
+            "#,
+            user_id,
+            now
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(streak(
+            &record.read.into_iter().collect(),
+            &record.frozen.into_iter().collect(),
+            record.today,
+        ))
+    }
+
+    /// Freezes `day` for `user_id`, which has to be today or later in their
+    /// time zone at `now`. Returns false if it was frozen already.
+    pub async fn freeze(
+        pool: &PgPool,
+        user_id: i64,
+        day: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> Result<bool, StreakError> {
+        let today = sqlx::query_scalar!(
+            r#"SELECT ($2::timestamptz AT TIME ZONE timezone)::DATE AS "today!" FROM users WHERE id = $1"#,
+            user_id,
+            now
+        )
+        .fetch_one(pool)
+        .await?;
+        if day < today {
+            return Err(StreakError::PastDay);
+        }
+
+        let month = day.with_day(1).unwrap_or(day);
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO streak_freezes (user_id, day)
+            SELECT $1, $2
+            WHERE (
+                SELECT COUNT(*) FROM streak_freezes
+                WHERE user_id = $1 AND day >= $3 AND day < $3 + INTERVAL '1 month'
+            ) < $4
+            ON CONFLICT DO NOTHING
+            "#,
+            user_id,
+            day,
+            month,
+            FREEZES_PER_MONTH
+        )
+        .execute(pool)
+        .await?;
+        if inserted.rows_affected() > 0 {
+            return Ok(true);
+        }
+
+        let frozen = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM streak_freezes WHERE user_id = $1 AND day = $2
+            ) AS "frozen!"
+            "#,
+            user_id,
+            day
+        )
+        .fetch_one(pool)
+        .await?;
+        if frozen {
+            Ok(false)
+        } else {
+            Err(StreakError::NoFreezesLeft)
+        }
+    }
+
+    /// Unfreezes `day` for `user_id`, returning whether it was frozen.
+    pub async fn unfreeze(
+        pool: &PgPool,
+        user_id: i64,
+        day: NaiveDate,
+    ) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM streak_freezes WHERE user_id = $1 AND day = $2",
+            user_id,
+            day
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factories::{fake_book, fake_user, fake_user_book};
+    use crate::test_utils::setup_db;
+    use chrono::TimeZone;
+
+    fn days(days: &[u32]) -> BTreeSet<NaiveDate> {
+        days.iter()
+            .map(|&day| NaiveDate::from_ymd_opt(2026, 3, day).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn frozen_days_bridge_streaks_and_today_does_not_break_them() {
+        let read = days(&[1, 2, 3, 6, 7, 9, 10]);
+        let frozen = days(&[8]);
+        let on = |day| {
+            streak(
+                &read,
+                &frozen,
+                NaiveDate::from_ymd_opt(2026, 3, day).unwrap(),
+            )
+        };
+
+        assert_eq!(
+            Streak {
+                current: 4,
+                longest: 4,
+                read_today: true,
+            },
+            on(10)
+        );
+        assert_eq!(
+            Streak {
+                current: 4,
+                longest: 4,
+                read_today: false,
+            },
+            on(11)
+        );
+        assert_eq!(
+            Streak {
+                current: 0,
+                longest: 4,
+                read_today: false,
+            },
+            on(12)
+        );
+        assert_eq!(
+            Streak {
+                current: 0,
+                longest: 3,
+                read_today: false,
+            },
+            on(5)
+        );
+        assert_eq!(
+            Streak::default(),
+            streak(
+                &read,
+                &frozen,
+                NaiveDate::from_ymd_opt(2026, 2, 28).unwrap()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn days_are_counted_in_the_users_time_zone() {
+        // Arrange
+        let pool = setup_db().await;
+        let mut user = fake_user();
+        user.timezone = "Pacific/Auckland".to_string();
+        user.create(&pool).await.unwrap();
+        let mut book = fake_book();
+        book.create(&pool).await.unwrap();
+        let mut entry = fake_user_book(user.id, book.id);
+        entry.progress_percent = None;
+        entry.create(&pool).await.unwrap();
+        // 20:00 and 23:00 UTC on March 1st are already March 2nd in Auckland
+        for (hour, percent) in [(9, 10.0), (20, 20.0), (23, 30.0)] {
+            sqlx::query!(
+                "INSERT INTO reading_progress (user_id, book_id, progress_percent, recorded_at) VALUES ($1, $2, $3, $4)",
+                user.id,
+                book.id,
+                percent,
+                Utc.with_ymd_and_hms(2026, 3, 1, hour, 0, 0).unwrap()
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let day = |day| NaiveDate::from_ymd_opt(2026, 3, day).unwrap();
+        // Evening of March 2nd in Auckland
+        let second = Utc.with_ymd_and_hms(2026, 3, 2, 6, 0, 0).unwrap();
+        // Already March 4th in Auckland, with March 3rd missed
+        let fourth = Utc.with_ymd_and_hms(2026, 3, 3, 12, 0, 0).unwrap();
+
+        // Act
+        let on_the_second = Streak::for_user(&pool, user.id, second).await.unwrap();
+        let without_freeze = Streak::for_user(&pool, user.id, fourth).await.unwrap();
+        let afterwards = Streak::freeze(&pool, user.id, day(3), fourth).await;
+        let froze = Streak::freeze(&pool, user.id, day(3), second)
+            .await
+            .unwrap();
+        let froze_again = Streak::freeze(&pool, user.id, day(3), second)
+            .await
+            .unwrap();
+        let with_freeze = Streak::for_user(&pool, user.id, fourth).await.unwrap();
+        Streak::freeze(&pool, user.id, day(20), second)
+            .await
+            .unwrap();
+        let third = Streak::freeze(&pool, user.id, day(21), second).await;
+        let next_month = Streak::freeze(
+            &pool,
+            user.id,
+            NaiveDate::from_ymd_opt(2026, 4, 1).unwrap(),
+            second,
+        )
+        .await
+        .unwrap();
+        let unfrozen = Streak::unfreeze(&pool, user.id, day(3)).await.unwrap();
+
+        // Cleanup
+        user.delete(&pool).await.unwrap();
+        book.delete(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            Streak {
+                current: 2,
+                longest: 2,
+                read_today: true,
+            },
+            on_the_second
+        );
+        assert_eq!(0, without_freeze.current);
+        assert!(matches!(afterwards, Err(StreakError::PastDay)));
+        assert!(froze);
+        assert!(!froze_again);
+        assert_eq!(2, with_freeze.current);
+        assert!(matches!(third, Err(StreakError::NoFreezesLeft)));
+        assert!(next_month);
+        assert!(unfrozen);
+    }
+}
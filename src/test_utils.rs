@@ -1,3 +1,8 @@
+use crate::factories::{fake_book, fake_user, fake_user_book};
+use crate::models::book::Book;
+use crate::models::follow::Follow;
+use crate::models::user::{User, Visibility};
+use crate::models::user_book::ReadingStatus;
 use dotenvy::dotenv;
 use sqlx::PgPool;
 use sqlx::migrate;
@@ -17,3 +22,39 @@ pub async fn setup_db() -> PgPool {
 
     pool
 }
+
+/// `n` fake users, added to the database.
+pub async fn create_fake_users(pool: &PgPool, n: usize) -> Vec<User> {
+    let mut users = Vec::new();
+    for _ in 0..n {
+        let mut user = fake_user();
+        user.create(pool).await.unwrap();
+        users.push(user);
+    }
+    users
+}
+
+/// An owner, a follower of theirs and a stranger, and a book on the owner's
+/// shelf for each visibility: public, followers and private, in that order.
+/// The entries are plain to-read ones, so adding them is their only activity.
+pub async fn shelf_by_visibility(pool: &PgPool) -> (User, User, User, Vec<Book>) {
+    let [owner, follower, stranger]: [User; 3] =
+        create_fake_users(pool, 3).await.try_into().unwrap();
+    Follow::request(pool, follower.id, owner.id).await.unwrap();
+    let mut books = Vec::new();
+    for visibility in [
+        Visibility::Public,
+        Visibility::Followers,
+        Visibility::Private,
+    ] {
+        let mut book = fake_book();
+        book.create(pool).await.unwrap();
+        let mut entry = fake_user_book(owner.id, book.id);
+        entry.status = ReadingStatus::ToRead;
+        entry.rating = None;
+        entry.visibility = visibility;
+        entry.create(pool).await.unwrap();
+        books.push(book);
+    }
+    (owner, follower, stranger, books)
+}